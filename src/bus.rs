@@ -1,6 +1,6 @@
 use super::graphics::Graphics;
 use super::io::{Io, IF_REG};
-use super::joypad::{Joypad, JOYP_REG};
+use super::joypad::{Button, Joypad, JOYP_REG};
 use super::mbc::Mbc;
 use super::memory::Memory;
 use super::serial::*;
@@ -8,10 +8,22 @@ use super::sound::*;
 use super::timer::*;
 use crate::graphics::dma::*;
 use crate::graphics::gpu_memory::{
-    OAM_END, OAM_START, PPUIO_END, PPUIO_START, UNUSED_END, UNUSED_START, VRAM_END, VRAM_START,
+    Layer, OAM_END, OAM_START, PPUIO_END, PPUIO_START, UNUSED_END, UNUSED_START, VRAM_END,
+    VRAM_START,
 };
+use crate::graphics::oam_bug::OamBug;
+use crate::graphics::palette::Palette;
+use crate::graphics::viewer::{Image, View};
+use crate::graphics::DisplayOptions;
+use crate::model::Model;
+#[cfg(feature = "profile")]
+use crate::perf::Part;
+use crate::perf::PartTimes;
+use crate::state::{StateReader, StateWriter};
 use sdl2::render::Texture;
-use sdl2::EventPump;
+use std::collections::HashSet;
+#[cfg(feature = "profile")]
+use std::time::Instant;
 
 pub struct Bus {
     mem: Memory,
@@ -22,6 +34,8 @@ pub struct Bus {
     serial: Serial,
     sound: Sound,
     oam_dma: OamDma,
+    #[cfg(feature = "profile")]
+    part_times: PartTimes,
 }
 
 pub enum BusType {
@@ -51,11 +65,87 @@ impl Bus {
             serial: Serial::new(),
             sound: Sound::new(),
             oam_dma: OamDma::new(),
+            #[cfg(feature = "profile")]
+            part_times: PartTimes::new(),
         };
     }
 
-    pub fn set_joypad(self: &mut Self, event_pump: EventPump) {
-        self.joypad.set_joypad(event_pump);
+    pub fn set_palette(self: &mut Self, palette: Palette) {
+        self.graphics.set_palette(palette);
+    }
+
+    pub fn get_shades(self: &Self) -> Vec<u8> {
+        return self.graphics.get_shades();
+    }
+
+    pub fn screenshot(self: &Self, scale: usize) -> Image {
+        return self.graphics.screenshot(scale);
+    }
+
+    pub fn viewer_image(self: &Self, view: View, grayscale: bool) -> Image {
+        return self.graphics.viewer_image(view, grayscale);
+    }
+
+    pub fn viewer_info(self: &Self, view: View, x: usize, y: usize) -> Option<String> {
+        return self.graphics.viewer_info(view, x, y);
+    }
+
+    pub fn viewer_listing(self: &Self, view: View) -> Option<String> {
+        return self.graphics.viewer_listing(view);
+    }
+
+    pub fn set_layer_visible(self: &mut Self, layer: Layer, visible: bool) {
+        self.graphics.set_layer_visible(layer, visible);
+    }
+
+    pub fn is_layer_visible(self: &Self, layer: Layer) -> bool {
+        return self.graphics.is_layer_visible(layer);
+    }
+
+    pub fn set_oam_bug(self: &mut Self, enabled: bool) {
+        self.graphics.set_oam_bug(enabled);
+    }
+
+    pub fn save_state(self: &Self, state: &mut StateWriter) {
+        self.mem.save_state(state);
+        self.graphics.save_state(state);
+        self.io.save_state(state);
+        self.timer.save_state(state);
+        self.joypad.save_state(state);
+        self.serial.save_state(state);
+        self.sound.save_state(state);
+        self.oam_dma.save_state(state);
+    }
+
+    pub fn load_state(self: &mut Self, state: &mut StateReader) {
+        self.mem.load_state(state);
+        self.graphics.load_state(state);
+        self.io.load_state(state);
+        self.timer.load_state(state);
+        self.joypad.load_state(state);
+        self.serial.load_state(state);
+        self.sound.load_state(state);
+        self.oam_dma.load_state(state);
+    }
+
+    pub fn trigger_oam_bug(self: &mut Self, addr: u16, kind: OamBug) {
+        self.graphics.trigger_oam_bug(addr, kind);
+    }
+
+    pub fn set_audio_settings(self: &mut Self, enabled: bool, volume: u8, sample_rate: u32) {
+        self.sound.set_audio_settings(enabled, volume, sample_rate);
+    }
+
+    pub fn get_sample_rate(self: &Self) -> u32 {
+        return self.sound.get_sample_rate();
+    }
+
+    pub fn set_audio_capture(self: &mut Self, capture: bool) {
+        self.sound.set_capture(capture);
+    }
+
+    pub fn take_audio_samples(self: &mut Self) -> Vec<f32> {
+        return self.sound.take_samples();
     }
 
     pub fn set_mbc(self: &mut Self, cart_mbc: Box<dyn Mbc>) {
@@ -107,7 +197,13 @@ impl Bus {
             DMA_REG => self.oam_dma.write_dma(addr, data),
             UNUSED_START..=UNUSED_END => self.graphics.write_byte(addr, data), // Memory area not usuable
             PPUIO_START..=PPUIO_END => self.graphics.write_io_byte(addr, data),
-            JOYP_REG => self.joypad.write_byte(addr, data),
+            JOYP_REG => {
+                // Selecting a group with a button held down pulls a line low aswell
+                self.joypad.write_byte(addr, data);
+                if self.joypad.take_interrupt() {
+                    self.io.request_joypad_interrupt();
+                }
+            }
             SB_REG | SC_REG => self.serial.write_byte(addr, data),
             TIMER_START..=TIMER_END => self.timer.write_byte(addr, data),
             SOUND_START..=SOUND_END => self.sound.write_byte(addr, data),
@@ -146,23 +242,45 @@ impl Bus {
         self.graphics.write_byte_for_dma(addr, data);
     }
 
-    pub fn dmg_init(self: &mut Self) {
+    pub fn init(self: &mut Self, model: Model) {
         self.mem.dmg_init();
-        self.timer.dmg_init();
-        self.io.dmg_init();
-        self.graphics.dmg_init();
-        self.serial.dmg_init();
+        self.timer.init(model);
+        self.io.init(model);
+        self.graphics.init(model);
+        self.serial.init(model);
         self.joypad.dmg_init();
-        self.oam_dma.dmg_init();
+        self.oam_dma.init(model);
         self.sound.dmg_init();
     }
 
+    // Everything goes back to how init leaves it except the cartridge ram and
+    // the emulator settings (palette, audio, ...)
+    pub fn reset(self: &mut Self, model: Model) {
+        self.mem.reset();
+        self.graphics.reset();
+        self.io = Io::new();
+        self.timer = Timer::new();
+        self.joypad = Joypad::new();
+        self.serial = Serial::new();
+        self.sound.reset();
+        self.oam_dma = OamDma::new();
+        self.init(model);
+    }
+
     pub fn adv_cycles(self: &mut Self, cycles: usize) {
         self.timer.adv_cycles(&mut self.io, cycles);
         self.serial.adv_cycles(&mut self.io, cycles);
+        #[cfg(feature = "profile")]
+        let start = Instant::now();
         self.graphics.adv_cycles(&mut self.io, cycles);
+        #[cfg(feature = "profile")]
+        let start = self.part_times.add(Part::Ppu, start);
         self.mem.adv_cycles(cycles);
+        #[cfg(feature = "profile")]
+        let start = self.part_times.add(Part::Mbc, start);
         self.sound.adv_cycles(cycles);
+        #[cfg(feature = "profile")]
+        self.part_times.add(Part::Apu, start);
 
         if self.oam_dma.dma_active() {
             self.handle_dma_transfer();
@@ -187,19 +305,35 @@ impl Bus {
         (self.mem.i_enable & self.io.read_byte(IF_REG) & 0x1F) != 0
     }
 
-    pub fn update_input(self: &mut Self) -> bool {
-        let should_exit = self.joypad.update_input();
-        if self.joypad.is_joypad_interrupt() {
+    pub fn set_buttons(self: &mut Self, pressed: HashSet<Button>) {
+        self.joypad.set_buttons(pressed);
+        if self.joypad.take_interrupt() {
             self.io.request_joypad_interrupt();
         }
-        return should_exit;
+    }
+
+    pub fn is_rumbling(self: &Self) -> bool {
+        return self.mem.is_rumbling();
     }
 
     pub fn write_bytes(self: &mut Self, location: u16, data: &Vec<u8>) {
         self.mem.write_bytes(location, data);
     }
 
-    pub fn update_display(self: &mut Self, texture: &mut Texture) -> bool {
-        return self.graphics.update_display(texture);
+    pub fn take_frame(self: &mut Self) -> Option<usize> {
+        return self.graphics.take_frame();
+    }
+
+    pub fn draw_frame(self: &mut Self, texture: &mut Texture, display: DisplayOptions) {
+        self.graphics.draw_frame(texture, display);
+    }
+
+    // The time spent in each part since the last call, see perf.rs.
+    // Only timed with the profile feature
+    pub fn take_part_times(self: &mut Self) -> Option<PartTimes> {
+        #[cfg(feature = "profile")]
+        return Some(std::mem::replace(&mut self.part_times, PartTimes::new()));
+        #[cfg(not(feature = "profile"))]
+        return None;
     }
 }
@@ -0,0 +1,1124 @@
+/*
+    Settings that are read in from a config file and can then be overriden
+    from the command line. By default the file is looked for at:
+        Linux/Mac: $XDG_CONFIG_HOME/gameboy-emulator/config.toml
+                   or ~/.config/gameboy-emulator/config.toml
+        Windows:   %APPDATA%\gameboy-emulator\config.toml
+    If the file doesnt exist, the defaults below are used.
+
+    The window scale and fullscreen get written back to the file (see save_settings)
+    when they are changed while playing, so the window comes back the same size.
+    Everything else in the file, including comments, is left as it is.
+
+    The file is a small subset of toml. Sections, `key = value`, comments
+    with #, and values that are strings, integers, decimals, booleans, or arrays of strings
+
+        [keys]              # Gameboy button = SDL key name
+        up = "Up"
+        a = "F"
+        start = "Right Shift"
+
+        [hotkeys]           # Emulator function = SDL key name
+        quit = "Escape"
+        tile_viewer = "F1"  # Opens/closes a window showing every tile in vram
+        tilemap_viewer = "F2" # Opens/closes a window showing both background tile maps
+        sprite_viewer = "F3" # Opens/closes a window showing all 40 sprites in oam
+        toggle_background = "1" # Hides/shows the background, window or sprites
+        toggle_window = "2"
+        toggle_sprites = "3"
+        screenshot = "F12"
+        record_video = "F9" # Starts/stops recording frames and audio to a folder
+        record_movie = "F5" # Starts/stops recording a movie from the game as it is
+        next_palette = "F7" # Switches between the palette presets
+        next_scaler = "F6"  # Switches between the upscaling filters
+        toggle_frame_blend = "F8"
+        toggle_lcd = "F4"
+        fullscreen = "F11"
+        scale_up = "="      # Window scale 1x - 8x
+        scale_down = "-"
+        pause = "P"
+        frame_advance = "N" # Runs one frame while paused (and pauses if not)
+        reset = "Backspace" # Starts the game over, the save is kept
+        fast_forward = "Tab" # Runs at fast_forward_speed while held
+        toggle_uncapped = "`" # Runs as fast as possible until pressed again
+        speed_up = "]"      # Steps the speed through 0.25x - 8x
+        speed_down = "["
+        rewind = "R"        # Goes back in time while held
+        perf_overlay = "F10" # Shows the performance numbers in the corner
+
+        [controller]        # Gameboy button = SDL game controller button name
+        a = "b"
+        b = "a"
+        start = "start"
+        select = "back"
+        deadzone = 8000     # How far the left stick has to move to count (0 - 32767)
+        rumble = true       # Pass rumble from rumble cartridges to the controller
+
+        [video]
+        scale = 3
+        fullscreen = false
+        palette = "dmg"     # A preset (default, dmg, pocket, light), or colors lightest to darkest
+                            # palette = ["#F8F8F0", "#E7DAD0", "#C98A98", "#916C75"]
+        palette_file = "my.pal" # A palette file, see graphics/palette.rs
+        obp0_palette = ["#FFFFFF", "#FF8484", "#943A3A", "#000000"] # Different colors for
+                            # bg_palette, obp0_palette or obp1_palette (after the ones above)
+        scaler = "xbr"      # nearest, scale2x, scale3x, smooth2x or xbr, see graphics/scaler.rs
+        integer_scale = true # Only whole multiples of 160x144 when the window is resized
+        frame_blend = true  # Mix each frame with the last like the slow dmg screen, see graphics/lcd.rs
+        lcd = true          # Pixel grid and color bleed (used instead of the scaler)
+
+        [audio]
+        enabled = true
+        volume = 100        # 0 - 100
+        sample_rate = 48000
+
+        [saves]
+        directory = "saves" # Where .gbsav and .gbrtc files go (Next to the rom if not set)
+
+        [screenshots]
+        directory = "shots" # Where screenshots go (The current directory if not set)
+        window_scale = false # Save at the window scale instead of 160x144
+
+        [emulation]
+        oam_bug = true      # Emulate the dmg oam corruption bug
+        speed = 1.0         # Times normal speed (0.25 - 8)
+        fast_forward_speed = 4.0 # Speed while fast_forward is held (0.25 - 8)
+        spin_wait = false   # Busy wait the last millisecond before each frame, for smoother pacing
+
+        [rewind]
+        enabled = true
+        interval = 2        # Frames between snapshots, rewinding goes back this many frames a step
+        memory = 64         # Most megabytes the snapshots can take up
+
+    Key names are the ones SDL uses: https://wiki.libsdl.org/SDL2/SDL_Keycode
+    Controller button names are the ones in SDL mappings: a, b, x, y, back, guide, start,
+    leftstick, rightstick, leftshoulder, rightshoulder, dpup, dpdown, dpleft, dpright
+*/
+
+use crate::graphics::gpu_memory::COLORS;
+use crate::graphics::palette::{parse_color, Colors, Palette};
+use crate::graphics::scaler::Scaler;
+use crate::graphics::{MAX_SCALE, MAX_SPEED, MIN_SPEED, SCALE};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const CONFIG_DIR_NAME: &str = "gameboy-emulator";
+pub const CONFIG_FILE_NAME: &str = "config.toml";
+
+pub struct Config {
+    pub path: Option<PathBuf>, // Where the file is, or would be if there isnt one
+    pub keys: ButtonNames,
+    pub hotkeys: HotkeyNames,
+    pub controller: ControllerConfig,
+    pub scale: u32,
+    pub fullscreen: bool,
+    pub palette: Palette,
+    pub scaler: Scaler,
+    pub integer_scale: bool,
+    pub frame_blend: bool,
+    pub lcd: bool,
+    pub audio: AudioConfig,
+    pub save_dir: Option<String>,
+    pub screenshots: ScreenshotConfig,
+    pub oam_bug: bool,
+    pub speed: f64,
+    pub fast_forward_speed: f64,
+    pub spin_wait: bool,
+    pub rewind: RewindConfig,
+}
+
+// Gameboy buttons. Kept as names and turned into keycodes/buttons once SDL is running
+pub struct ButtonNames {
+    pub up: String,
+    pub down: String,
+    pub left: String,
+    pub right: String,
+    pub a: String,
+    pub b: String,
+    pub start: String,
+    pub select: String,
+}
+
+// Keys for controlling the emulator rather than the game
+pub struct HotkeyNames {
+    pub quit: String,
+    pub tile_viewer: String,
+    pub tilemap_viewer: String,
+    pub sprite_viewer: String,
+    pub toggle_background: String,
+    pub toggle_window: String,
+    pub toggle_sprites: String,
+    pub screenshot: String,
+    pub record_video: String,
+    pub record_movie: String,
+    pub next_palette: String,
+    pub next_scaler: String,
+    pub toggle_frame_blend: String,
+    pub toggle_lcd: String,
+    pub fullscreen: String,
+    pub scale_up: String,
+    pub scale_down: String,
+    pub pause: String,
+    pub frame_advance: String,
+    pub reset: String,
+    pub fast_forward: String,
+    pub toggle_uncapped: String,
+    pub speed_up: String,
+    pub speed_down: String,
+    pub rewind: String,
+    pub perf_overlay: String,
+}
+
+pub struct ControllerConfig {
+    pub buttons: ButtonNames,
+    pub deadzone: i16,
+    pub rumble: bool,
+}
+
+pub struct ScreenshotConfig {
+    pub dir: Option<String>,
+    pub window_scale: bool,
+}
+
+pub struct RewindConfig {
+    pub enabled: bool,
+    pub interval: u64,
+    pub memory: usize, // Megabytes
+}
+
+pub struct AudioConfig {
+    pub enabled: bool,
+    pub volume: u8,
+    pub sample_rate: u32,
+}
+
+enum Value {
+    Str(String),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    List(Vec<String>),
+}
+
+impl Value {
+    // Whole numbers can be used where a decimal is expected
+    fn number(self: &Self) -> Option<f64> {
+        return match self {
+            Value::Int(int) => Some(*int as f64),
+            Value::Float(float) => Some(*float),
+            _ => None,
+        };
+    }
+}
+
+impl Config {
+    pub fn new() -> Config {
+        return Config {
+            path: None,
+            keys: ButtonNames {
+                up: String::from("Up"),
+                down: String::from("Down"),
+                left: String::from("Left"),
+                right: String::from("Right"),
+                a: String::from("F"),
+                b: String::from("D"),
+                start: String::from("Right Shift"),
+                select: String::from("Return"),
+            },
+            hotkeys: HotkeyNames {
+                quit: String::from("Escape"),
+                tile_viewer: String::from("F1"),
+                tilemap_viewer: String::from("F2"),
+                sprite_viewer: String::from("F3"),
+                toggle_background: String::from("1"),
+                toggle_window: String::from("2"),
+                toggle_sprites: String::from("3"),
+                screenshot: String::from("F12"),
+                record_video: String::from("F9"),
+                record_movie: String::from("F5"),
+                next_palette: String::from("F7"),
+                next_scaler: String::from("F6"),
+                toggle_frame_blend: String::from("F8"),
+                toggle_lcd: String::from("F4"),
+                fullscreen: String::from("F11"),
+                scale_up: String::from("="),
+                scale_down: String::from("-"),
+                pause: String::from("P"),
+                frame_advance: String::from("N"),
+                reset: String::from("Backspace"),
+                fast_forward: String::from("Tab"),
+                toggle_uncapped: String::from("`"),
+                speed_up: String::from("]"),
+                speed_down: String::from("["),
+                rewind: String::from("R"),
+                perf_overlay: String::from("F10"),
+            },
+            // The gameboy has B on the left and A on the right, so use the
+            // face buttons in the same positions (south and east)
+            controller: ControllerConfig {
+                buttons: ButtonNames {
+                    up: String::from("dpup"),
+                    down: String::from("dpdown"),
+                    left: String::from("dpleft"),
+                    right: String::from("dpright"),
+                    a: String::from("b"),
+                    b: String::from("a"),
+                    start: String::from("start"),
+                    select: String::from("back"),
+                },
+                deadzone: 8000,
+                rumble: true,
+            },
+            scale: SCALE,
+            fullscreen: false,
+            palette: Palette::uniform(COLORS),
+            scaler: Scaler::Nearest,
+            integer_scale: false,
+            frame_blend: false,
+            lcd: false,
+            audio: AudioConfig {
+                enabled: true,
+                volume: 100,
+                sample_rate: 48_000,
+            },
+            save_dir: None,
+            screenshots: ScreenshotConfig {
+                dir: None,
+                window_scale: false,
+            },
+            oam_bug: true,
+            speed: 1.0,
+            fast_forward_speed: 4.0,
+            spin_wait: false,
+            rewind: RewindConfig {
+                enabled: true,
+                interval: 2,
+                memory: 64,
+            },
+        };
+    }
+
+    pub fn default_path() -> Option<PathBuf> {
+        let base = if let Ok(dir) = std::env::var("XDG_CONFIG_HOME") {
+            PathBuf::from(dir)
+        } else if let Ok(dir) = std::env::var("APPDATA") {
+            PathBuf::from(dir)
+        } else if let Ok(home) = std::env::var("HOME") {
+            PathBuf::from(home).join(".config")
+        } else {
+            return None;
+        };
+        return Some(base.join(CONFIG_DIR_NAME).join(CONFIG_FILE_NAME));
+    }
+
+    // A missing default file is fine and just means defaults, but a file with mistakes
+    // or one given with --config that doesnt exist is an error
+    pub fn load(path: Option<PathBuf>) -> Result<Config, String> {
+        let from_args = path.is_some();
+        let path = match path.or(Config::default_path()) {
+            Some(p) => p,
+            None => return Ok(Config::new()),
+        };
+        if !path.exists() {
+            if from_args {
+                return Err(format!("Config {} doesnt exist", path.display()));
+            }
+            let mut config = Config::new();
+            config.path = Some(path);
+            return Ok(config);
+        }
+        let contents = match fs::read_to_string(&path) {
+            Ok(c) => c,
+            Err(e) => return Err(format!("Couldnt read config {}: {}", path.display(), e)),
+        };
+        return match Config::parse(&contents) {
+            Ok(mut config) => {
+                config.path = Some(path);
+                Ok(config)
+            }
+            Err(e) => Err(format!("{}: {}", path.display(), e)),
+        };
+    }
+
+    // Changes (or adds) settings in the config file at path, which gets made if
+    // it doesnt exist yet. Values are written as is so strings need their quotes
+    pub fn save_settings(path: &Path, settings: &[(&str, &str, String)]) -> Result<(), String> {
+        let mut contents = match fs::read_to_string(path) {
+            Ok(c) => c,
+            Err(_) => String::new(),
+        };
+        for (section, key, value) in settings {
+            contents = set_setting(&contents, section, key, value);
+        }
+        if let Some(dir) = path.parent() {
+            if let Err(e) = fs::create_dir_all(dir) {
+                return Err(format!("Couldnt create {}: {}", dir.display(), e));
+            }
+        }
+        return match fs::write(path, contents) {
+            Ok(()) => Ok(()),
+            Err(e) => Err(format!("Couldnt write config {}: {}", path.display(), e)),
+        };
+    }
+
+    pub fn parse(contents: &str) -> Result<Config, String> {
+        let mut config = Config::new();
+        let mut layer_palettes = Vec::new(); // Applied last so they arent overwritten by palette
+        let mut key_lines = HashMap::new(); // Where each key binding was set
+        for ((section, key), (line_no, value)) in parse_toml(contents)? {
+            let err = |msg: &str| format!("line {}: {}.{} {}", line_no, section, key, msg);
+            if section == "keys" || section == "hotkeys" {
+                key_lines.insert(format!("{}.{}", section, key), line_no);
+            }
+            match (section.as_str(), key.as_str(), value) {
+                ("keys", _, Value::Str(name)) => match config.keys.get_mut(&key) {
+                    Some(binding) => *binding = name,
+                    None => return Err(err("is not a gameboy button")),
+                },
+                ("hotkeys", _, Value::Str(name)) => match config.hotkeys.get_mut(&key) {
+                    Some(binding) => *binding = name,
+                    None => return Err(err("is not an emulator hotkey")),
+                },
+                ("controller", "deadzone", Value::Int(deadzone))
+                    if (0..=32767).contains(&deadzone) =>
+                {
+                    config.controller.deadzone = deadzone as i16
+                }
+                ("controller", "rumble", Value::Bool(rumble)) => config.controller.rumble = rumble,
+                ("controller", _, Value::Str(name)) => {
+                    match config.controller.buttons.get_mut(&key) {
+                        Some(binding) => *binding = name,
+                        None => return Err(err("is not a gameboy button")),
+                    }
+                }
+                ("video", "scale", Value::Int(scale)) => {
+                    config.scale = parse_scale(scale).map_err(|e| err(&e))?
+                }
+                ("video", "fullscreen", Value::Bool(fullscreen)) => config.fullscreen = fullscreen,
+                ("video", "palette", Value::List(colors)) => {
+                    config.palette = parse_palette(&colors).map_err(|e| err(&e))?
+                }
+                ("video", "palette", Value::Str(name)) => match Palette::preset(&name) {
+                    Some(palette) => config.palette = palette,
+                    None => {
+                        return Err(err("is not a palette preset (default, dmg, pocket, light)"))
+                    }
+                },
+                ("video", "palette_file", Value::Str(path)) => {
+                    config.palette = Palette::load(&path).map_err(|e| err(&e))?
+                }
+                ("video", "bg_palette" | "obp0_palette" | "obp1_palette", Value::List(colors)) => {
+                    let colors = parse_colors(&colors).map_err(|e| err(&e))?;
+                    layer_palettes.push((key, colors));
+                }
+                ("video", "scaler", Value::Str(name)) => {
+                    config.scaler = parse_scaler(&name).map_err(|e| err(&e))?
+                }
+                ("video", "integer_scale", Value::Bool(integer_scale)) => {
+                    config.integer_scale = integer_scale
+                }
+                ("video", "frame_blend", Value::Bool(frame_blend)) => {
+                    config.frame_blend = frame_blend
+                }
+                ("video", "lcd", Value::Bool(lcd)) => config.lcd = lcd,
+                ("audio", "enabled", Value::Bool(enabled)) => config.audio.enabled = enabled,
+                ("audio", "volume", Value::Int(vol)) if (0..=100).contains(&vol) => {
+                    config.audio.volume = vol as u8
+                }
+                ("audio", "sample_rate", Value::Int(rate)) if rate > 0 => {
+                    config.audio.sample_rate = rate as u32
+                }
+                ("saves", "directory", Value::Str(dir)) => config.save_dir = Some(dir),
+                ("screenshots", "directory", Value::Str(dir)) => config.screenshots.dir = Some(dir),
+                ("screenshots", "window_scale", Value::Bool(window_scale)) => {
+                    config.screenshots.window_scale = window_scale
+                }
+                ("emulation", "oam_bug", Value::Bool(oam_bug)) => config.oam_bug = oam_bug,
+                ("emulation", "spin_wait", Value::Bool(spin)) => config.spin_wait = spin,
+                ("emulation", "speed", value) if value.number().is_some() => {
+                    config.speed = parse_speed(value.number().unwrap()).map_err(|e| err(&e))?
+                }
+                ("emulation", "fast_forward_speed", value) if value.number().is_some() => {
+                    config.fast_forward_speed =
+                        parse_speed(value.number().unwrap()).map_err(|e| err(&e))?
+                }
+                ("rewind", "enabled", Value::Bool(enabled)) => config.rewind.enabled = enabled,
+                ("rewind", "interval", Value::Int(interval)) if (1..=60).contains(&interval) => {
+                    config.rewind.interval = interval as u64
+                }
+                ("rewind", "memory", Value::Int(memory)) if memory > 0 => {
+                    config.rewind.memory = memory as usize
+                }
+                _ => return Err(err("is not a known setting or has an invalid value")),
+            }
+        }
+        for (key, colors) in layer_palettes {
+            config.set_layer_palette(&key, colors);
+        }
+        config.check_duplicate_keys(&key_lines)?;
+        return Ok(config);
+    }
+
+    // A key bound to two buttons or hotkeys would only ever do one of them. Names are
+    // compared ignoring case like SDL does, KeyBindings::from_config catches the rest
+    fn check_duplicate_keys(self: &Self, key_lines: &HashMap<String, usize>) -> Result<(), String> {
+        let mut bindings = Vec::new();
+        for button in ButtonNames::NAMES {
+            bindings.push((format!("keys.{}", button), self.keys.get(button).unwrap()));
+        }
+        for function in HotkeyNames::NAMES {
+            bindings.push((
+                format!("hotkeys.{}", function),
+                self.hotkeys.get(function).unwrap(),
+            ));
+        }
+        let line = |binding: &str| match key_lines.get(binding) {
+            Some(line_no) => format!("line {}", line_no),
+            None => String::from("default"),
+        };
+        for (i, (binding, name)) in bindings.iter().enumerate() {
+            let same = bindings[..i]
+                .iter()
+                .find(|(_, other)| other.eq_ignore_ascii_case(name));
+            if let Some((other, _)) = same {
+                return Err(format!(
+                    "{} ({}) and {} ({}) are both bound to {}",
+                    other,
+                    line(other),
+                    binding,
+                    line(binding),
+                    name
+                ));
+            }
+        }
+        return Ok(());
+    }
+
+    // Command line arguments take priority over the config file. Returns the args
+    // that were not config overrides. Options:
+    //   --config <path>        (Handled before the file is loaded, see config_path_arg)
+    //   --scale <n>
+    //   --fullscreen
+    //   --palette <preset or #RRGGBB,#RRGGBB,#RRGGBB,#RRGGBB (or 12 colors)>
+    //   --palette-file <path>
+    //   --bg-palette, --obp0-palette, --obp1-palette <#RRGGBB,#RRGGBB,#RRGGBB,#RRGGBB>
+    //   --scaler <nearest, scale2x, scale3x, smooth2x, xbr>
+    //   --integer-scale
+    //   --frame-blend
+    //   --lcd
+    //   --volume <0-100>
+    //   --mute
+    //   --save-dir <dir>
+    //   --screenshot-dir <dir>
+    //   --key <button>=<key name>   e.g. --key a=Z
+    //   --hotkey <function>=<key name>
+    //   --pad <button>=<controller button name>   e.g. --pad a=x
+    //   --no-rumble
+    //   --no-oam-bug
+    //   --speed <0.25-8>
+    //   --spin-wait
+    //   --no-rewind
+    pub fn apply_args(self: &mut Self, args: Vec<String>) -> Result<Vec<String>, String> {
+        let mut rest = Vec::new();
+        let mut args_iter = args.into_iter();
+
+        while let Some(arg) = args_iter.next() {
+            let mut value = || match args_iter.next() {
+                Some(v) => Ok(v),
+                None => Err(format!("{} needs a value", arg)),
+            };
+            match arg.as_str() {
+                "--config" => {
+                    value()?;
+                }
+                "--scale" => {
+                    let scale = value()?;
+                    match scale.parse::<i64>() {
+                        Ok(s) => self.scale = parse_scale(s)?,
+                        Err(_) => return Err(format!("Invalid scale: {}", scale)),
+                    }
+                }
+                "--fullscreen" => self.fullscreen = true,
+                "--palette" => {
+                    let value = value()?;
+                    self.palette = match Palette::preset(&value) {
+                        Some(palette) => palette,
+                        None => parse_palette(&value.split(',').map(String::from).collect())?,
+                    };
+                }
+                "--palette-file" => self.palette = Palette::load(&value()?)?,
+                "--bg-palette" | "--obp0-palette" | "--obp1-palette" => {
+                    let colors = parse_colors(&value()?.split(',').map(String::from).collect())?;
+                    self.set_layer_palette(&arg[2..].replace('-', "_"), colors);
+                }
+                "--scaler" => self.scaler = parse_scaler(&value()?)?,
+                "--integer-scale" => self.integer_scale = true,
+                "--frame-blend" => self.frame_blend = true,
+                "--lcd" => self.lcd = true,
+                "--volume" => {
+                    let vol = value()?;
+                    match vol.parse::<u8>() {
+                        Ok(v) if v <= 100 => self.audio.volume = v,
+                        _ => return Err(format!("Invalid volume: {}", vol)),
+                    }
+                }
+                "--mute" => self.audio.enabled = false,
+                "--save-dir" => self.save_dir = Some(value()?),
+                "--screenshot-dir" => self.screenshots.dir = Some(value()?),
+                "--no-rumble" => self.controller.rumble = false,
+                "--no-oam-bug" => self.oam_bug = false,
+                "--no-rewind" => self.rewind.enabled = false,
+                "--spin-wait" => self.spin_wait = true,
+                "--speed" => {
+                    let speed = value()?;
+                    match speed.parse::<f64>() {
+                        Ok(s) => self.speed = parse_speed(s)?,
+                        Err(_) => return Err(format!("Invalid speed: {}", speed)),
+                    }
+                }
+                "--key" | "--hotkey" | "--pad" => {
+                    let binding = value()?;
+                    let (name, key) = match binding.split_once('=') {
+                        Some((n, k)) => (n.trim().to_lowercase(), k.trim().to_string()),
+                        None => return Err(format!("Expected <name>=<key>, got: {}", binding)),
+                    };
+                    let slot = match arg.as_str() {
+                        "--key" => self.keys.get_mut(&name),
+                        "--pad" => self.controller.buttons.get_mut(&name),
+                        _ => self.hotkeys.get_mut(&name),
+                    };
+                    match slot {
+                        Some(s) => *s = key,
+                        None => return Err(format!("Nothing to bind called: {}", name)),
+                    }
+                }
+                _ => rest.push(arg),
+            }
+        }
+        return Ok(rest);
+    }
+
+    // name is bg_palette, obp0_palette or obp1_palette
+    fn set_layer_palette(self: &mut Self, name: &str, colors: Colors) {
+        match name {
+            "bg_palette" => self.palette.bg = colors,
+            "obp0_palette" => self.palette.obp0 = colors,
+            _ => self.palette.obp1 = colors,
+        }
+    }
+
+    // The config file has to be known before it can be loaded
+    pub fn config_path_arg(args: &Vec<String>) -> Option<PathBuf> {
+        let pos = args.iter().position(|arg| arg == "--config")?;
+        return args.get(pos + 1).map(PathBuf::from);
+    }
+}
+
+impl ButtonNames {
+    const NAMES: [&'static str; 8] = ["up", "down", "left", "right", "a", "b", "start", "select"];
+
+    fn get(self: &Self, button: &str) -> Option<&String> {
+        return match button {
+            "up" => Some(&self.up),
+            "down" => Some(&self.down),
+            "left" => Some(&self.left),
+            "right" => Some(&self.right),
+            "a" => Some(&self.a),
+            "b" => Some(&self.b),
+            "start" => Some(&self.start),
+            "select" => Some(&self.select),
+            _ => None,
+        };
+    }
+
+    fn get_mut(self: &mut Self, button: &str) -> Option<&mut String> {
+        return match button {
+            "up" => Some(&mut self.up),
+            "down" => Some(&mut self.down),
+            "left" => Some(&mut self.left),
+            "right" => Some(&mut self.right),
+            "a" => Some(&mut self.a),
+            "b" => Some(&mut self.b),
+            "start" => Some(&mut self.start),
+            "select" => Some(&mut self.select),
+            _ => None,
+        };
+    }
+}
+
+impl HotkeyNames {
+    const NAMES: [&'static str; 26] = [
+        "quit",
+        "tile_viewer",
+        "tilemap_viewer",
+        "sprite_viewer",
+        "toggle_background",
+        "toggle_window",
+        "toggle_sprites",
+        "screenshot",
+        "record_video",
+        "record_movie",
+        "next_palette",
+        "next_scaler",
+        "toggle_frame_blend",
+        "toggle_lcd",
+        "fullscreen",
+        "scale_up",
+        "scale_down",
+        "pause",
+        "frame_advance",
+        "reset",
+        "fast_forward",
+        "toggle_uncapped",
+        "speed_up",
+        "speed_down",
+        "rewind",
+        "perf_overlay",
+    ];
+
+    pub fn get(self: &Self, function: &str) -> Option<&String> {
+        return match function {
+            "quit" => Some(&self.quit),
+            "tile_viewer" => Some(&self.tile_viewer),
+            "tilemap_viewer" => Some(&self.tilemap_viewer),
+            "sprite_viewer" => Some(&self.sprite_viewer),
+            "toggle_background" => Some(&self.toggle_background),
+            "toggle_window" => Some(&self.toggle_window),
+            "toggle_sprites" => Some(&self.toggle_sprites),
+            "screenshot" => Some(&self.screenshot),
+            "record_video" => Some(&self.record_video),
+            "record_movie" => Some(&self.record_movie),
+            "next_palette" => Some(&self.next_palette),
+            "next_scaler" => Some(&self.next_scaler),
+            "toggle_frame_blend" => Some(&self.toggle_frame_blend),
+            "toggle_lcd" => Some(&self.toggle_lcd),
+            "fullscreen" => Some(&self.fullscreen),
+            "scale_up" => Some(&self.scale_up),
+            "scale_down" => Some(&self.scale_down),
+            "pause" => Some(&self.pause),
+            "frame_advance" => Some(&self.frame_advance),
+            "reset" => Some(&self.reset),
+            "fast_forward" => Some(&self.fast_forward),
+            "toggle_uncapped" => Some(&self.toggle_uncapped),
+            "speed_up" => Some(&self.speed_up),
+            "speed_down" => Some(&self.speed_down),
+            "rewind" => Some(&self.rewind),
+            "perf_overlay" => Some(&self.perf_overlay),
+            _ => None,
+        };
+    }
+
+    fn get_mut(self: &mut Self, function: &str) -> Option<&mut String> {
+        return match function {
+            "quit" => Some(&mut self.quit),
+            "tile_viewer" => Some(&mut self.tile_viewer),
+            "tilemap_viewer" => Some(&mut self.tilemap_viewer),
+            "sprite_viewer" => Some(&mut self.sprite_viewer),
+            "toggle_background" => Some(&mut self.toggle_background),
+            "toggle_window" => Some(&mut self.toggle_window),
+            "toggle_sprites" => Some(&mut self.toggle_sprites),
+            "screenshot" => Some(&mut self.screenshot),
+            "record_video" => Some(&mut self.record_video),
+            "record_movie" => Some(&mut self.record_movie),
+            "next_palette" => Some(&mut self.next_palette),
+            "next_scaler" => Some(&mut self.next_scaler),
+            "toggle_frame_blend" => Some(&mut self.toggle_frame_blend),
+            "toggle_lcd" => Some(&mut self.toggle_lcd),
+            "fullscreen" => Some(&mut self.fullscreen),
+            "scale_up" => Some(&mut self.scale_up),
+            "scale_down" => Some(&mut self.scale_down),
+            "pause" => Some(&mut self.pause),
+            "frame_advance" => Some(&mut self.frame_advance),
+            "reset" => Some(&mut self.reset),
+            "fast_forward" => Some(&mut self.fast_forward),
+            "toggle_uncapped" => Some(&mut self.toggle_uncapped),
+            "speed_up" => Some(&mut self.speed_up),
+            "speed_down" => Some(&mut self.speed_down),
+            "rewind" => Some(&mut self.rewind),
+            "perf_overlay" => Some(&mut self.perf_overlay),
+            _ => None,
+        };
+    }
+}
+
+fn parse_scale(scale: i64) -> Result<u32, String> {
+    if scale < 1 || scale > MAX_SCALE as i64 {
+        return Err(format!(
+            "scale must be between 1 and {}, got {}",
+            MAX_SCALE, scale
+        ));
+    }
+    return Ok(scale as u32);
+}
+
+fn parse_speed(speed: f64) -> Result<f64, String> {
+    if !(MIN_SPEED..=MAX_SPEED).contains(&speed) {
+        return Err(format!(
+            "speed must be between {} and {}, got {}",
+            MIN_SPEED, MAX_SPEED, speed
+        ));
+    }
+    return Ok(speed);
+}
+
+fn parse_scaler(name: &str) -> Result<Scaler, String> {
+    return match Scaler::from_name(name) {
+        Some(scaler) => Ok(scaler),
+        None => Err(format!(
+            "Unknown scaler: {} (nearest, scale2x, scale3x, smooth2x, xbr)",
+            name
+        )),
+    };
+}
+
+// 4 colors for everything or 12 for the background, OBP0 and OBP1
+fn parse_palette(colors: &Vec<String>) -> Result<Palette, String> {
+    let colors = colors
+        .iter()
+        .map(|color| parse_color(color))
+        .collect::<Result<Vec<[u8; 4]>, String>>()?;
+    return Palette::from_colors(&colors);
+}
+
+// Exactly 4 colors
+fn parse_colors(colors: &Vec<String>) -> Result<Colors, String> {
+    if colors.len() != 4 {
+        return Err(format!("A palette needs 4 colors, got {}", colors.len()));
+    }
+    let mut palette = [[0; 4]; 4];
+    for (i, color) in colors.iter().enumerate() {
+        palette[i] = parse_color(color)?;
+    }
+    return Ok(palette);
+}
+
+// Returns every setting as (section, key) -> (line number, value)
+fn parse_toml(contents: &str) -> Result<Vec<((String, String), (usize, Value))>, String> {
+    let mut settings = Vec::new();
+    let mut seen = HashMap::new();
+    let mut section = String::new();
+
+    for (i, raw_line) in contents.lines().enumerate() {
+        let line_no = i + 1;
+        let line = strip_comment(raw_line).trim();
+
+        if line.is_empty() {
+            continue;
+        }
+        if line.starts_with('[') {
+            if !line.ends_with(']') {
+                return Err(format!("line {}: unclosed section header", line_no));
+            }
+            section = line[1..line.len() - 1].trim().to_lowercase();
+            continue;
+        }
+
+        let (key, value) = match line.split_once('=') {
+            Some((k, v)) => (k.trim().to_lowercase(), v.trim()),
+            None => return Err(format!("line {}: expected key = value", line_no)),
+        };
+        let value = match parse_value(value) {
+            Some(v) => v,
+            None => return Err(format!("line {}: invalid value for {}", line_no, key)),
+        };
+
+        if let Some(prev) = seen.insert((section.clone(), key.clone()), line_no) {
+            return Err(format!(
+                "line {}: {} was already set on line {}",
+                line_no, key, prev
+            ));
+        }
+        settings.push(((section.clone(), key), (line_no, value)));
+    }
+    return Ok(settings);
+}
+
+// The contents with section.key set to value. The line already setting it is
+// changed (keeping its comment), otherwise a new line goes at the end of the section
+fn set_setting(contents: &str, section: &str, key: &str, value: &str) -> String {
+    let mut lines: Vec<String> = contents.lines().map(String::from).collect();
+    let mut current = String::new();
+    let mut section_end = None; // Line after the last setting in the section
+
+    for i in 0..lines.len() {
+        let line = strip_comment(&lines[i]).trim().to_string();
+        if line.starts_with('[') && line.ends_with(']') {
+            current = line[1..line.len() - 1].trim().to_lowercase();
+            if current == section {
+                section_end = Some(i + 1);
+            }
+            continue;
+        }
+        if current != section || line.is_empty() {
+            continue;
+        }
+        section_end = Some(i + 1);
+        if let Some((k, _)) = line.split_once('=') {
+            if k.trim().to_lowercase() == key {
+                let indent = &lines[i][..lines[i].len() - lines[i].trim_start().len()];
+                let comment = &lines[i][strip_comment(&lines[i]).trim_end().len()..];
+                lines[i] = format!("{}{} = {}{}", indent, key, value, comment);
+                return lines.join("\n") + "\n";
+            }
+        }
+    }
+
+    let setting = format!("{} = {}", key, value);
+    match section_end {
+        Some(end) => lines.insert(end, setting),
+        None => {
+            if lines.last().map_or(false, |line| !line.trim().is_empty()) {
+                lines.push(String::new());
+            }
+            lines.push(format!("[{}]", section));
+            lines.push(setting);
+        }
+    }
+    return lines.join("\n") + "\n";
+}
+
+// A # inside of a string is part of the string (colors), not a comment
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..i],
+            _ => {}
+        }
+    }
+    return line;
+}
+
+fn parse_value(value: &str) -> Option<Value> {
+    if value.starts_with('"') {
+        return parse_string(value).map(Value::Str);
+    }
+    if value.starts_with('[') && value.ends_with(']') {
+        let inner = value[1..value.len() - 1].trim();
+        if inner.is_empty() {
+            return Some(Value::List(Vec::new()));
+        }
+        let mut list = Vec::new();
+        for item in inner.split(',') {
+            let item = item.trim();
+            if item.is_empty() {
+                continue; // Trailing comma
+            }
+            list.push(parse_string(item)?);
+        }
+        return Some(Value::List(list));
+    }
+    return match value {
+        "true" => Some(Value::Bool(true)),
+        "false" => Some(Value::Bool(false)),
+        _ => {
+            let number = value.replace('_', "");
+            match number.parse::<i64>() {
+                Ok(int) => Some(Value::Int(int)),
+                Err(_) => number.parse::<f64>().ok().map(Value::Float),
+            }
+        }
+    };
+}
+
+fn parse_string(value: &str) -> Option<String> {
+    if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        return Some(value[1..value.len() - 1].to_string());
+    }
+    return None;
+}
+
+#[test]
+fn test_parse_config() {
+    let contents = r##"
+        # Rebind for a layout without easy access to the arrows
+        [keys]
+        up = "W"
+        a = "K"   # trailing comment
+
+        [hotkeys]
+        quit = "Q"
+
+        [controller]
+        a = "a"
+        b = "x"
+        deadzone = 12000
+        rumble = false
+
+        [video]
+        scale = 4
+        fullscreen = true
+        obp0_palette = ["#FFFFFF", "#FF8484", "#943A3A", "#000000"]
+        palette = ["#9BBC0F", "#8BAC0F", "#306230", "#0F380F",]
+        scaler = "Scale3x"
+        integer_scale = true
+        frame_blend = true
+
+        [audio]
+        enabled = false
+        volume = 50
+        sample_rate = 44_100
+
+        [saves]
+        directory = "my saves"
+
+        [screenshots]
+        directory = "shots"
+        window_scale = true
+
+        [emulation]
+        oam_bug = false
+        speed = 0.5
+        fast_forward_speed = 8
+        spin_wait = true
+
+        [rewind]
+        interval = 5
+        memory = 16
+    "##;
+    let config = Config::parse(contents).unwrap();
+
+    assert_eq!(config.keys.up, "W");
+    assert_eq!(config.keys.a, "K");
+    assert_eq!(config.keys.down, "Down"); // Untouched
+    assert_eq!(config.hotkeys.quit, "Q");
+    assert_eq!(config.controller.buttons.a, "a");
+    assert_eq!(config.controller.buttons.b, "x");
+    assert_eq!(config.controller.buttons.start, "start"); // Untouched
+    assert_eq!(config.controller.deadzone, 12000);
+    assert_eq!(config.controller.rumble, false);
+    assert_eq!(config.scale, 4);
+    assert_eq!(config.fullscreen, true);
+    assert_eq!(config.palette.bg[0], [0x0F, 0xBC, 0x9B, 0xFF]);
+    assert_eq!(config.palette.bg[3], [0x0F, 0x38, 0x0F, 0xFF]);
+    assert_eq!(config.palette.obp0[3], [0x00, 0x00, 0x00, 0xFF]);
+    assert_eq!(config.palette.obp1, config.palette.bg);
+    assert_eq!(config.scaler, Scaler::Scale3x);
+    assert_eq!(config.integer_scale, true);
+    assert_eq!(config.frame_blend, true);
+    assert_eq!(config.lcd, false); // Untouched
+    assert_eq!(config.audio.enabled, false);
+    assert_eq!(config.audio.volume, 50);
+    assert_eq!(config.audio.sample_rate, 44_100);
+    assert_eq!(config.save_dir, Some(String::from("my saves")));
+    assert_eq!(config.screenshots.dir, Some(String::from("shots")));
+    assert_eq!(config.screenshots.window_scale, true);
+    assert_eq!(config.oam_bug, false);
+    assert_eq!(config.speed, 0.5);
+    assert_eq!(config.fast_forward_speed, 8.0);
+    assert_eq!(config.spin_wait, true);
+    assert_eq!(config.rewind.enabled, true); // Untouched
+    assert_eq!(config.rewind.interval, 5);
+    assert_eq!(config.rewind.memory, 16);
+}
+
+#[test]
+fn test_parse_config_errors() {
+    assert!(Config::parse("[keys]\nturbo = \"T\"").is_err());
+    assert!(Config::parse("[video]\nscale = 20").is_err());
+    assert!(Config::parse("[controller]\ndeadzone = 40000").is_err());
+    assert!(Config::parse("[video]\nscale = \"big\"").is_err());
+    assert!(Config::parse("[video]\npalette = [\"#FFFFFF\"]").is_err());
+    assert!(Config::parse("[video]\npalette = \"gbc\"").is_err());
+    assert!(Config::parse("[video]\npalette_file = \"missing.pal\"").is_err());
+    assert!(Config::parse("[video]\nscaler = \"hq4x\"").is_err());
+    assert!(Config::parse("[keys]\nup = \"W\"\nup = \"I\"").is_err());
+    assert!(Config::parse("[keys\nup = \"W\"").is_err());
+    assert!(Config::parse("up").is_err());
+    assert!(Config::parse("[emulation]\nspeed = 0.1").is_err());
+    assert!(Config::parse("[emulation]\nfast_forward_speed = 16").is_err());
+    assert!(Config::parse("[rewind]\ninterval = 0").is_err());
+}
+
+#[test]
+fn test_duplicate_keys() {
+    let err = Config::parse("[keys]\nup = \"W\"\n\n[hotkeys]\nrewind = \"w\"")
+        .err()
+        .unwrap();
+    assert_eq!(
+        err,
+        "keys.up (line 2) and hotkeys.rewind (line 5) are both bound to w"
+    );
+    let err = Config::parse("[keys]\na = \"R\"").err().unwrap();
+    assert_eq!(
+        err,
+        "keys.a (line 2) and hotkeys.rewind (default) are both bound to R"
+    );
+    let err = Config::parse("[keys]\nstart = \"Q\"\nselect = \"Q\"")
+        .err()
+        .unwrap();
+    assert!(err.starts_with("keys.start (line 2) and keys.select (line 3)"));
+
+    // Moving a hotkey out of the way frees its key
+    assert!(Config::parse("[keys]\na = \"R\"\n\n[hotkeys]\nrewind = \"T\"").is_ok());
+}
+
+#[test]
+fn test_load_missing_config() {
+    let path = std::env::temp_dir()
+        .join("gameboy-emulator-missing")
+        .join("config.toml");
+    assert!(Config::load(Some(path.clone())).is_err());
+    assert!(!path.exists());
+}
+
+#[test]
+fn test_apply_args() {
+    let mut config = Config::new();
+    let args = vec![
+        "--scale",
+        "2",
+        "game.gb",
+        "--key",
+        "B=X",
+        "--mute",
+        "--model",
+        "mgb",
+        "--palette",
+        "pocket",
+        "--obp1-palette",
+        "#000000,#555555,#AAAAAA,#FFFFFF",
+        "--scaler",
+        "smooth2x",
+        "--speed",
+        "0.25",
+    ];
+    let rest = config
+        .apply_args(args.into_iter().map(String::from).collect())
+        .unwrap();
+
+    assert_eq!(rest, vec!["game.gb", "--model", "mgb"]);
+    assert_eq!(config.scale, 2);
+    assert_eq!(config.keys.b, "X");
+    assert_eq!(config.audio.enabled, false);
+    assert_eq!(config.palette.bg, Palette::preset("pocket").unwrap().bg);
+    assert_eq!(config.palette.obp1[3], [0xFF; 4]);
+    assert_eq!(config.scaler, Scaler::Smooth2x);
+    assert_eq!(config.speed, 0.25);
+}
+
+#[test]
+fn test_set_setting() {
+    let contents = "[keys]\nup = \"W\"\n\n[video]\nscale = 3 # comment\n\n[audio]\nvolume = 50\n";
+    let changed = set_setting(contents, "video", "scale", "5");
+    assert!(changed.contains("[video]\nscale = 5 # comment\n"));
+
+    let added = set_setting(&changed, "video", "fullscreen", "true");
+    assert!(added.contains("scale = 5 # comment\nfullscreen = true\n\n[audio]"));
+    let config = Config::parse(&added).unwrap();
+    assert_eq!(
+        (config.scale, config.fullscreen, config.audio.volume),
+        (5, true, 50)
+    );
+
+    assert_eq!(
+        set_setting("", "video", "scale", "2"),
+        "[video]\nscale = 2\n"
+    );
+    assert_eq!(
+        set_setting("[keys]\nup = \"W\"", "video", "scale", "2"),
+        "[keys]\nup = \"W\"\n\n[video]\nscale = 2\n"
+    );
+}
@@ -3,13 +3,22 @@ mod registers;
 
 use super::bus::Bus;
 use super::mbc::Mbc;
+use crate::graphics::gpu_memory::Layer;
+use crate::graphics::oam_bug::OamBug;
+use crate::graphics::palette::Palette;
+use crate::graphics::viewer::{Image, View};
+use crate::graphics::DisplayOptions;
+use crate::joypad::Button;
+use crate::model::Model;
+use crate::perf::PartTimes;
+use crate::state::{StateReader, StateWriter};
 use sdl2;
 use sdl2::render::Texture;
+use std::collections::HashSet;
 
 use registers::Registers as Reg;
 
 pub const CPU_FREQ: usize = 4_194_304;
-pub const CPU_PERIOD_NANOS: f64 = 238.418579;
 
 pub struct Cpu {
     bus: Bus,
@@ -42,9 +51,19 @@ impl Cpu {
         };
     }
 
-    pub fn dmg_init(self: &mut Self, checksum: u8) {
-        self.reg.dmg_init(checksum);
-        self.bus.dmg_init();
+    pub fn init(self: &mut Self, model: Model, checksum: u8) {
+        self.reg.init(model, checksum);
+        self.bus.init(model);
+        self.sp = 0xFFFE;
+    }
+
+    // A soft reset, the same as init on a new cpu but the cartridge stays loaded
+    pub fn reset(self: &mut Self, model: Model, checksum: u8) {
+        let mut bus = std::mem::replace(&mut self.bus, Bus::new());
+        bus.reset(model);
+        *self = Cpu::new();
+        self.bus = bus;
+        self.reg.init(model, checksum);
         self.sp = 0xFFFE;
     }
 
@@ -52,8 +71,88 @@ impl Cpu {
         self.bus.set_mbc(cart_mbc);
     }
 
-    pub fn set_joypad(self: &mut Self, event_pump: sdl2::EventPump) {
-        self.bus.set_joypad(event_pump);
+    pub fn set_palette(self: &mut Self, palette: Palette) {
+        self.bus.set_palette(palette);
+    }
+
+    pub fn get_shades(self: &Self) -> Vec<u8> {
+        return self.bus.get_shades();
+    }
+
+    pub fn set_audio_settings(self: &mut Self, enabled: bool, volume: u8, sample_rate: u32) {
+        self.bus.set_audio_settings(enabled, volume, sample_rate);
+    }
+
+    pub fn get_sample_rate(self: &Self) -> u32 {
+        return self.bus.get_sample_rate();
+    }
+
+    // Audio samples are only kept while capturing, see sound.rs
+    pub fn set_audio_capture(self: &mut Self, capture: bool) {
+        self.bus.set_audio_capture(capture);
+    }
+
+    pub fn take_audio_samples(self: &mut Self) -> Vec<f32> {
+        return self.bus.take_audio_samples();
+    }
+
+    // The screen as an image, scale 1 is the gameboys 160x144
+    pub fn screenshot(self: &Self, scale: usize) -> Image {
+        return self.bus.screenshot(scale);
+    }
+
+    pub fn viewer_image(self: &Self, view: View, grayscale: bool) -> Image {
+        return self.bus.viewer_image(view, grayscale);
+    }
+
+    pub fn viewer_info(self: &Self, view: View, x: usize, y: usize) -> Option<String> {
+        return self.bus.viewer_info(view, x, y);
+    }
+
+    pub fn viewer_listing(self: &Self, view: View) -> Option<String> {
+        return self.bus.viewer_listing(view);
+    }
+
+    pub fn set_layer_visible(self: &mut Self, layer: Layer, visible: bool) {
+        self.bus.set_layer_visible(layer, visible);
+    }
+
+    pub fn is_layer_visible(self: &Self, layer: Layer) -> bool {
+        return self.bus.is_layer_visible(layer);
+    }
+
+    pub fn set_oam_bug(self: &mut Self, enabled: bool) {
+        self.bus.set_oam_bug(enabled);
+    }
+
+    // Everything needed to carry on from this exact point later, see state.rs
+    pub fn save_state(self: &Self, state: &mut StateWriter) {
+        self.bus.save_state(state);
+        for reg in [self.reg.af, self.reg.bc, self.reg.de, self.reg.hl] {
+            state.write_u16(reg);
+        }
+        state.write_u16(self.pc);
+        state.write_u16(self.sp);
+        state.write_usize(self.curr_cycles);
+        state.write_bool(self.ime);
+        state.write_bool(self.ime_scheduled);
+        state.write_bool(self.haltbug);
+        state.write_bool(self.is_running);
+    }
+
+    pub fn load_state(self: &mut Self, state: &mut StateReader) {
+        self.bus.load_state(state);
+        self.reg.af = state.read_u16();
+        self.reg.bc = state.read_u16();
+        self.reg.de = state.read_u16();
+        self.reg.hl = state.read_u16();
+        self.pc = state.read_u16();
+        self.sp = state.read_u16();
+        self.curr_cycles = state.read_usize();
+        self.ime = state.read_bool();
+        self.ime_scheduled = state.read_bool();
+        self.haltbug = state.read_bool();
+        self.is_running = state.read_bool();
     }
 
     pub fn execute(self: &mut Self) {
@@ -164,8 +263,12 @@ impl Cpu {
         }
     }
 
-    pub fn update_input(self: &mut Self) -> bool {
-        return self.bus.update_input();
+    pub fn set_buttons(self: &mut Self, pressed: HashSet<Button>) {
+        self.bus.set_buttons(pressed);
+    }
+
+    pub fn is_rumbling(self: &Self) -> bool {
+        return self.bus.is_rumbling();
     }
 
     fn match_instruction(self: &mut Self, i: u8) {
@@ -214,6 +317,8 @@ impl Cpu {
             }
             0x03 | 0x13 | 0x23 | 0x33 => {
                 // INC BC/DE/HL/SP
+                let old_value = self.get_reg16_by_opcode(values.0);
+                self.bus.trigger_oam_bug(old_value, OamBug::Write);
                 let register = self.get_mut_reg16_by_opcode(values.0);
                 alu::post_incr(register); // Writing a 16 bit register is +4 cycles?
                 self.internal_cycle();
@@ -314,11 +419,17 @@ impl Cpu {
                     0x03 => alu::post_decr(&mut self.reg.hl),
                     _ => panic!("Valid: 0x0A, 0x1A, 0x2A, 0x3A, Current: {:#04X}", i),
                 };
-                let new_a_val = self.read_addr(location);
+                let new_a_val = if values.0 >= 0x02 {
+                    self.read_addr_inc_dec(location)
+                } else {
+                    self.read_addr(location)
+                };
                 self.reg.af = Reg::set_hi(self.reg.af, new_a_val);
             }
             0x0B | 0x1B | 0x2B | 0x3B => {
                 // DEC BC/DE/HL/SP
+                let old_value = self.get_reg16_by_opcode(values.0);
+                self.bus.trigger_oam_bug(old_value, OamBug::Write);
                 let register = self.get_mut_reg16_by_opcode(values.0);
                 alu::post_decr(register);
                 self.internal_cycle();
@@ -683,8 +794,9 @@ impl Cpu {
         return self.read_addr(self.reg.hl);
     }
 
+    // Only used by pop, which increments sp on the same cycle as the read
     fn read_sp(self: &mut Self) -> u8 {
-        return self.read_addr(self.sp);
+        return self.read_addr_inc_dec(self.sp);
     }
 
     // Instructions that are 3 bytes long will call this method to get the next two bytes required
@@ -707,6 +819,17 @@ impl Cpu {
     }
 
     fn read_addr(self: &mut Self, addr: u16) -> u8 {
+        self.bus.trigger_oam_bug(addr, OamBug::Read);
+        let byte = self.bus.read_byte(addr);
+        self.adv_cycles(4);
+        self.curr_cycles += 4;
+        return byte;
+    }
+
+    // Reading while the same address is being incremented or decremented
+    // corrupts oam differently than a plain read
+    fn read_addr_inc_dec(self: &mut Self, addr: u16) -> u8 {
+        self.bus.trigger_oam_bug(addr, OamBug::ReadIncDec);
         let byte = self.bus.read_byte(addr);
         self.adv_cycles(4);
         self.curr_cycles += 4;
@@ -719,12 +842,14 @@ impl Cpu {
     }
 
     fn write_byte(self: &mut Self, addr: u16, data: u8) {
+        self.bus.trigger_oam_bug(addr, OamBug::Write);
         self.bus.write_byte(addr, data);
         self.adv_cycles(4);
         self.curr_cycles += 4;
     }
 
     fn stack_push(self: &mut Self, value: u16) {
+        self.bus.trigger_oam_bug(self.sp, OamBug::Write);
         self.sp = self.sp.wrapping_sub(1);
         self.write_byte(self.sp, Reg::get_hi(value));
 
@@ -808,8 +933,16 @@ impl Cpu {
         };
     }
 
-    pub fn update_display(self: &mut Self, texture: &mut Texture) -> bool {
-        return self.bus.update_display(texture);
+    pub fn take_frame(self: &mut Self) -> Option<usize> {
+        return self.bus.take_frame();
+    }
+
+    pub fn take_part_times(self: &mut Self) -> Option<PartTimes> {
+        return self.bus.take_part_times();
+    }
+
+    pub fn draw_frame(self: &mut Self, texture: &mut Texture, display: DisplayOptions) {
+        self.bus.draw_frame(texture, display);
     }
 } // Impl CPU
 
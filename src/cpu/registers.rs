@@ -1,3 +1,5 @@
+use crate::model::Model;
+
 // Each one may also be addressed as just the upper or lower 8 bits
 pub struct Registers {
     pub af: u16, // A: accumulator, F: flags as 0bZNHC0000
@@ -16,15 +18,19 @@ impl Registers {
         };
     }
 
-    pub fn dmg_init(self: &mut Self, checksum: u8) {
-        if checksum == 0x00 {
-            self.af = 0x0180;
-        } else {
-            self.af = 0x01B0;
-        }
-        self.bc = 0x0013;
-        self.de = 0x00D8;
-        self.hl = 0x014D;
+    // https://gbdev.io/pandocs/Power_Up_Sequence.html#cpu-registers
+    // On the dmg and mgb the half carry and carry flags depend on the header checksum
+    pub fn init(self: &mut Self, model: Model, checksum: u8) {
+        let hc_flags = if checksum == 0x00 { 0x80 } else { 0xB0 };
+
+        (self.af, self.bc, self.de, self.hl) = match model {
+            Model::Dmg0 => (0x0100, 0xFF13, 0x00C1, 0x8403),
+            Model::Dmg => (0x0100 | hc_flags, 0x0013, 0x00D8, 0x014D),
+            Model::Mgb => (0xFF00 | hc_flags, 0x0013, 0x00D8, 0x014D),
+            Model::Sgb => (0x0100, 0x0014, 0x0000, 0xC060),
+            Model::Sgb2 => (0xFF00, 0x0014, 0x0000, 0xC060),
+            Model::Cgb => (0x1180, 0x0000, 0xFF56, 0x000D),
+        };
     }
 
     // returns true if z is set
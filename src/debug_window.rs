@@ -0,0 +1,131 @@
+/*
+    A second SDL window showing one of the debug views (See graphics/viewer.rs).
+    It gets redrawn every frame so changes to vram show up as the game runs.
+
+    Hovering over the image shows what is under the mouse in the title bar.
+    While the window has focus
+        G switches between the palette the game is using and plain grayscale
+        F freezes the image so it stops following the game (hovering still shows
+          what is there now)
+        L prints the views listing (if it has one) to the console
+*/
+
+use crate::cpu::Cpu;
+use crate::graphics::viewer::{Image, View};
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+use sdl2::VideoSubsystem;
+
+const VIEWER_SCALE: u32 = 2;
+
+pub struct DebugWindow {
+    view: View,
+    canvas: Canvas<Window>,
+    grayscale: bool,
+    hover: Option<(usize, usize)>, // Position in the image (not the window)
+    freeze: bool,
+    frozen: Option<Image>, // The image from when it was frozen
+    print_listing: bool,
+}
+
+impl DebugWindow {
+    pub fn new(video_subsystem: &VideoSubsystem, view: View) -> DebugWindow {
+        let (width, height) = view.size();
+        let window = video_subsystem
+            .window(
+                view.name(),
+                width as u32 * VIEWER_SCALE,
+                height as u32 * VIEWER_SCALE,
+            )
+            .build()
+            .expect("Couldnt create debug window");
+        let canvas = window
+            .into_canvas()
+            .build()
+            .expect("Couldnt create debug window canvas");
+
+        return DebugWindow {
+            view: view,
+            canvas: canvas,
+            grayscale: false,
+            hover: None,
+            freeze: false,
+            frozen: None,
+            print_listing: false,
+        };
+    }
+
+    pub fn id(self: &Self) -> u32 {
+        return self.canvas.window().id();
+    }
+
+    pub fn view(self: &Self) -> View {
+        return self.view;
+    }
+
+    pub fn mouse_moved(self: &mut Self, x: i32, y: i32) {
+        if x < 0 || y < 0 {
+            self.hover = None;
+        } else {
+            let scale = VIEWER_SCALE as usize;
+            self.hover = Some((x as usize / scale, y as usize / scale));
+        }
+    }
+
+    pub fn key_pressed(self: &mut Self, key: Keycode) {
+        match key {
+            Keycode::G => self.grayscale = !self.grayscale,
+            Keycode::F => self.freeze = !self.freeze,
+            Keycode::L => self.print_listing = true,
+            _ => (),
+        }
+    }
+
+    pub fn draw(self: &mut Self, cpu: &Cpu) {
+        let image = match (self.freeze, self.frozen.take()) {
+            (true, Some(image)) => image,
+            _ => cpu.viewer_image(self.view, self.grayscale),
+        };
+
+        if self.print_listing {
+            self.print_listing = false;
+            match cpu.viewer_listing(self.view) {
+                Some(listing) => println!("{}", listing),
+                None => println!("{} has no listing", self.view.name()),
+            }
+        }
+
+        let info = match self.hover {
+            Some((x, y)) => cpu.viewer_info(self.view, x, y),
+            None => None,
+        };
+        let title = match info {
+            Some(info) => format!("{} - {}", self.view.name(), info),
+            None => String::from(self.view.name()),
+        };
+        if self.canvas.window().title() != title {
+            // Only fails if the title has a nul byte in it
+            let _ = self.canvas.window_mut().set_title(&title);
+        }
+
+        let creator = self.canvas.texture_creator();
+        let mut texture = creator
+            .create_texture_streaming(
+                PixelFormatEnum::ARGB8888,
+                image.width as u32,
+                image.height as u32,
+            )
+            .expect("Couldnt create debug window texture");
+        texture
+            .update(None, &image.pixels, image.width * 4)
+            .expect("updating debug texture didnt work");
+        self.canvas.copy(&texture, None, None).unwrap();
+        self.canvas.present();
+
+        if self.freeze {
+            self.frozen = Some(image);
+        }
+    }
+}
@@ -1,18 +1,42 @@
 use super::cpu;
-use super::graphics::{NUM_PIXELS_X, NUM_PIXELS_Y, SCREEN_HEIGHT, SCREEN_WIDTH};
-use crate::mbc::cartridge;
+use super::graphics::{MAX_SCALE, NUM_PIXELS_X, NUM_PIXELS_Y, SCALE, SPEEDS};
+use crate::config::{Config, ScreenshotConfig};
+use crate::debug_window::DebugWindow;
+use crate::graphics::palette::{Palette, PRESETS};
+use crate::graphics::scaler;
+use crate::graphics::viewer::View;
+use crate::graphics::DisplayOptions;
+use crate::input::{Command, ControllerBindings, Controllers, Hotkey, Input, KeyBindings};
+use crate::joypad::Button;
+use crate::mbc::{cartridge, RtcClock};
+use crate::model::Model;
+use crate::movie::{self, Movie, MovieMode, MovieSession};
+use crate::osd::Osd;
+use crate::pacing::{Pacer, Stats};
+use crate::perf::{self, Lap, Perf, Report};
+use crate::recorder::{Recorder, FRAME_RATE};
+use crate::rewind::Rewind;
+use crate::screenshot;
+use crate::state::{StateReader, StateWriter};
 
 use sdl2::pixels::PixelFormatEnum;
 use sdl2::rect::Rect;
+use sdl2::render::{Canvas, Texture};
+use sdl2::video::{FullscreenType, Window};
 use sdl2::Sdl;
 use sdl2::VideoSubsystem;
 
+use std::collections::HashSet;
 use std::fs::File;
 use std::io::BufWriter;
 use std::io::Write;
+use std::path::PathBuf;
 use std::str;
+use std::time::{Duration, Instant};
 
-const CPU_PERIOD_NANOS: f64 = 238.418579;
+const BENCHMARK_RTC_START: u64 = 1_700_000_000; // Any fixed time, so rtc games run the same every time
+const WINDOW_TITLE: &str = "Rust-Gameboy-Emulator";
+const PAUSED_WAIT: Duration = Duration::from_millis(16); // Between input checks while paused or rewinding
 
 pub struct Emulator {
     cpu: cpu::Cpu,
@@ -20,6 +44,43 @@ pub struct Emulator {
     sdl_context: Option<Sdl>,
     video_subsystem: Option<VideoSubsystem>,
     file_writer: Option<BufWriter<File>>,
+    input: Option<Input>,
+    movie: Option<MovieSession>,
+    rom_hash: u64, // Movies only play back on the same rom
+    scale: u32,
+    fullscreen: bool,
+    resize_window: bool,       // The scale or fullscreen was changed by a hotkey
+    start_window: (u32, bool), // Scale and fullscreen to begin with, to know if they changed
+    config_path: Option<PathBuf>, // Where the window size gets remembered
+    display: DisplayOptions,   // Scaler and lcd effects
+    integer_scale: bool,       // Only whole multiples of 160x144 when the window is resized
+    debug_windows: Vec<DebugWindow>,
+    dumps: Vec<(View, String)>, // Debug views written to png when the emulator stops
+    title: String,
+    screenshots: ScreenshotConfig,
+    headless: bool,              // No window, input or waiting between frames
+    benchmark: bool,             // Headless, from a clean start, timed
+    frame_count: u64,            // Frames drawn since starting
+    frame_limit: Option<u64>,    // Stop once this many frames have been drawn
+    screenshot_frames: Vec<u64>, // Take a screenshot when these frames are drawn
+    recorder: Option<Recorder>,
+    palettes: Vec<(String, Palette)>, // What next_palette goes through
+    palette_index: usize,
+    model: Model,
+    paused: bool,
+    advance_frame: bool, // Run one frame and then stay paused
+    osd: Osd,
+    pacer: Pacer,
+    speed: f64,              // Times normal speed
+    fast_forward_speed: f64, // Speed while the fast forward key is held
+    fast_forward: bool,
+    uncapped: bool,         // As fast as possible, over the speed and fast forward
+    rewind: Option<Rewind>, // None if turned off in the config
+    rewinding: bool,        // The rewind key is held
+    perf: Perf,
+    perf_report: Option<Report>, // The newest, for the overlay
+    perf_overlay: bool,
+    perf_log: bool, // Print each report to the console
 }
 
 impl Emulator {
@@ -30,12 +91,225 @@ impl Emulator {
             sdl_context: None,
             video_subsystem: None,
             file_writer: None,
+            input: None,
+            movie: None,
+            rom_hash: 0,
+            scale: SCALE,
+            fullscreen: false,
+            resize_window: false,
+            start_window: (SCALE, false),
+            config_path: None,
+            display: DisplayOptions::new(),
+            integer_scale: false,
+            debug_windows: Vec::new(),
+            dumps: Vec::new(),
+            title: String::new(),
+            screenshots: ScreenshotConfig {
+                dir: None,
+                window_scale: false,
+            },
+            headless: false,
+            benchmark: false,
+            frame_count: 0,
+            frame_limit: None,
+            screenshot_frames: Vec::new(),
+            recorder: None,
+            palettes: Vec::new(),
+            palette_index: 0,
+            model: Model::Dmg,
+            paused: false,
+            advance_frame: false,
+            osd: Osd::new(),
+            pacer: Pacer::new(),
+            speed: 1.0,
+            fast_forward_speed: 1.0,
+            fast_forward: false,
+            uncapped: false,
+            rewind: None,
+            rewinding: false,
+            perf: Perf::new(),
+            perf_report: None,
+            perf_overlay: false,
+            perf_log: false,
         };
     }
 
+    // Has to be set before setup_emulator since it decides whether SDL gets started
+    pub fn set_headless(self: &mut Self, headless: bool) {
+        self.headless = headless;
+    }
+
+    // Also has to be set before setup_emulator, and turns on headless
+    pub fn set_benchmark(self: &mut Self, benchmark: bool) {
+        self.benchmark = benchmark;
+        self.headless |= benchmark;
+    }
+
+    pub fn set_perf_log(self: &mut Self, perf_log: bool) {
+        self.perf_log = perf_log;
+    }
+
+    pub fn set_frame_limit(self: &mut Self, frames: u64) {
+        self.frame_limit = Some(frames);
+    }
+
+    pub fn add_screenshot_frame(self: &mut Self, frame: u64) {
+        self.screenshot_frames.push(frame);
+    }
+
+    pub fn add_dump(self: &mut Self, view: View, path: String) {
+        self.dumps.push((view, path));
+    }
+
     // We just want the mbc type really, we wont bother with the nintendo logo boot
     // Will fail if anything required for setting up the emulator for playing fails
-    pub fn setup_emulator(self: &mut Self, game_path: &str) {
+    // If no model is given, the one the cartridge header asks for is used
+    pub fn setup_emulator(
+        self: &mut Self,
+        game_path: &str,
+        model: Option<Model>,
+        config: &Config,
+        movie_mode: Option<MovieMode>,
+    ) {
+        if !self.headless {
+            self.setup_sdl(config);
+        }
+
+        // Movies need the cartridge to start out the same way every time
+        let rom_hash = movie::hash_bytes(&std::fs::read(game_path).unwrap());
+        self.rom_hash = rom_hash;
+        let playing = match &movie_mode {
+            Some(MovieMode::Play(path)) => match Movie::load(path) {
+                Ok(movie) => Some(movie),
+                Err(e) => panic!("{}", e),
+            },
+            _ => None,
+        };
+        let rtc_start = match &playing {
+            Some(movie) => movie.rtc_start,
+            None if self.benchmark => BENCHMARK_RTC_START,
+            None => RtcClock::System.now(),
+        };
+        // A save could change what the game does, so benchmarks start without one too
+        if movie_mode.is_some() || self.benchmark {
+            self.cart.set_battery_enabled(false);
+            self.cart.set_rtc_clock(RtcClock::Fixed(rtc_start));
+        }
+
+        self.cart.set_save_dir(config.save_dir.clone());
+        let cart_mbc = self.cart.read_cartridge_header(game_path).unwrap();
+
+        self.cpu.set_mbc(cart_mbc); // Cartridge header had what mbc to use
+        self.title = self.cart.get_title();
+
+        let mut model = model.unwrap_or(self.cart.get_model());
+        match (movie_mode, playing) {
+            (Some(MovieMode::Play(path)), Some(movie)) => {
+                if movie.rom_hash != rom_hash {
+                    panic!("Movie {} was recorded with a different rom", path);
+                }
+                model = movie.model; // Movies always play back on the model they were recorded on
+                println!("Playing movie {} ({} frames)", path, movie.frames.len());
+                self.movie = Some(MovieSession::play(path, movie));
+            }
+            (Some(MovieMode::Record(path)), _) => {
+                println!("Recording movie to {}", path);
+                let movie = Movie::new(model, rom_hash, rtc_start);
+                self.movie = Some(MovieSession::record(path, movie));
+            }
+            _ => {}
+        }
+        println!("Emulating model: {:?}", model);
+        self.model = model;
+        self.cpu.init(model, self.cart.checksum_val); // Setup registers
+        self.load_movie_state();
+        self.setup_palettes(config.palette);
+        self.cpu.set_oam_bug(config.oam_bug);
+        self.cpu.set_audio_settings(
+            config.audio.enabled && !self.benchmark,
+            config.audio.volume,
+            config.audio.sample_rate,
+        );
+        self.scale = config.scale;
+        self.fullscreen = config.fullscreen;
+        self.start_window = (config.scale, config.fullscreen);
+        self.config_path = config.path.clone();
+        self.display = DisplayOptions {
+            scaler: config.scaler,
+            frame_blend: config.frame_blend,
+            lcd: config.lcd,
+        };
+        self.integer_scale = config.integer_scale;
+        self.speed = config.speed;
+        self.fast_forward_speed = config.fast_forward_speed;
+        self.pacer = Pacer::new().with_spin_wait(config.spin_wait);
+        self.update_speed();
+        if config.rewind.enabled && !self.headless {
+            let max_bytes = config.rewind.memory * 1024 * 1024;
+            self.rewind = Some(Rewind::new(config.rewind.interval, max_bytes));
+        }
+        self.screenshots = ScreenshotConfig {
+            dir: config.screenshots.dir.clone(),
+            window_scale: config.screenshots.window_scale,
+        };
+
+        #[cfg(feature = "debug-file")]
+        {
+            self.file_writer = Some(BufWriter::new(self.setup_debug_file(game_path)));
+        }
+    }
+
+    // The presets, plus the palette from the config if it isnt one of them
+    fn setup_palettes(self: &mut Self, palette: Palette) {
+        self.palettes = PRESETS
+            .iter()
+            .map(|(name, colors)| (String::from(*name), Palette::uniform(*colors)))
+            .collect();
+        self.palette_index = match self.palettes.iter().position(|(_, p)| *p == palette) {
+            Some(index) => index,
+            None => {
+                self.palettes.insert(0, (String::from("custom"), palette));
+                0
+            }
+        };
+        self.cpu.set_palette(palette);
+    }
+
+    fn next_palette(self: &mut Self) {
+        self.palette_index = (self.palette_index + 1) % self.palettes.len();
+        let (name, palette) = &self.palettes[self.palette_index];
+        self.cpu.set_palette(*palette);
+        let message = format!("Palette: {}", name);
+        self.notify(&message);
+    }
+
+    // Frames are paced in the game loop, see pacing.rs
+    fn update_speed(self: &mut Self) {
+        let speed = if self.uncapped {
+            None
+        } else if self.fast_forward {
+            Some(self.fast_forward_speed)
+        } else {
+            Some(self.speed)
+        };
+        self.pacer.set_speed(speed);
+    }
+
+    // Moves to the next step in SPEEDS above (or below) the current speed
+    fn step_speed(self: &mut Self, up: bool) {
+        let next = if up {
+            SPEEDS.iter().find(|speed| **speed > self.speed)
+        } else {
+            SPEEDS.iter().rev().find(|speed| **speed < self.speed)
+        };
+        if let Some(speed) = next {
+            self.speed = *speed;
+            self.update_speed();
+        }
+        self.notify(&format!("Speed: {}x", self.speed));
+    }
+
+    fn setup_sdl(self: &mut Self, config: &Config) {
         let sdl_context = sdl2::init().expect("Couldnt create sdl context"); // SDL for graphics, sound and input
 
         let video_subsystem = sdl_context // Init Display
@@ -46,34 +320,50 @@ impl Emulator {
             .event_pump()
             .expect("Coulnt initialize event pump"); // Init Event System
 
+        let controller_subsystem = sdl_context
+            .game_controller()
+            .expect("Couldnt initialize game controller subsystem"); // Init Controllers
+
         // let sound_system = SoundSystem::initialize(&sdl_context); // Init Sound System (ownership of this one will go to sound.rs)
 
-        let cart_mbc = self.cart.read_cartridge_header(game_path).unwrap();
+        let bindings = match KeyBindings::from_config(&config.keys, &config.hotkeys) {
+            Ok(b) => b,
+            Err(e) => panic!("Invalid key binding: {}", e),
+        };
+        let mut controllers = Controllers::new(controller_subsystem);
+        match ControllerBindings::from_config(&config.controller) {
+            Ok(b) => controllers.set_bindings(b),
+            Err(e) => panic!("Invalid controller binding: {}", e),
+        };
 
         self.sdl_context = Some(sdl_context); // Just need to make sure the context doesnt die
         self.video_subsystem = Some(video_subsystem); // Just need to make sure the context doesnt die
-        self.cpu.set_mbc(cart_mbc); // Cartridge header had what mbc to use
-        self.cpu.set_joypad(event_pump); // Joypad will own the event pump
-        self.cpu.dmg_init(self.cart.checksum_val); // Setup registers
 
-        #[cfg(feature = "debug-file")]
-        {
-            self.file_writer = Some(BufWriter::new(self.setup_debug_file(game_path)));
-        }
+        // Input owns the event pump. Controllers get opened as SDL finds them
+        self.input = Some(Input::new(event_pump, bindings).with_controllers(controllers));
     }
 
     pub fn run(self: &mut Self) {
+        if self.headless {
+            self.run_headless();
+            return;
+        }
+
         // Put these in graphics somehow
         let video_subsystem = match &self.video_subsystem {
             Some(videosys) => videosys,
             None => panic!("No video subsystem was initialized"),
         };
 
-        let window = video_subsystem
-            .window("Rust-Gameboy-Emulator", SCREEN_WIDTH, SCREEN_HEIGHT)
-            .position_centered()
-            .build()
-            .unwrap();
+        let screen_width = NUM_PIXELS_X * self.scale;
+        let screen_height = NUM_PIXELS_Y * self.scale;
+
+        let mut window = video_subsystem.window(WINDOW_TITLE, screen_width, screen_height);
+        window.position_centered().resizable();
+        if self.fullscreen {
+            window.fullscreen_desktop();
+        }
+        let window = window.build().unwrap();
 
         let mut canvas = window // Canvas is the renderer
             .into_canvas()
@@ -81,13 +371,26 @@ impl Emulator {
             .build()
             .unwrap();
 
-        let creator = canvas.texture_creator();
-        let mut texture = creator
-            .create_texture_streaming(PixelFormatEnum::ARGB8888, NUM_PIXELS_X, NUM_PIXELS_Y)
-            .map_err(|e| e.to_string())
-            .unwrap();
+        if let Some(input) = &mut self.input {
+            input.set_main_window(canvas.window().id());
+        }
 
-        let rect = Some(Rect::new(0, 0, SCREEN_WIDTH, SCREEN_HEIGHT));
+        // The texture is the size of the frame after the scaler (or lcd), and
+        // gets made again whenever one with a different size is picked
+        let creator = canvas.texture_creator();
+        let new_texture = |factor: usize| {
+            let factor = factor as u32;
+            creator
+                .create_texture_streaming(
+                    PixelFormatEnum::ARGB8888,
+                    NUM_PIXELS_X * factor,
+                    NUM_PIXELS_Y * factor,
+                )
+                .map_err(|e| e.to_string())
+                .unwrap()
+        };
+        let mut texture = new_texture(self.display.factor());
+        let mut texture_display = self.display; // What is in the texture
 
         let x1 = std::time::Instant::now();
         let mut counter: u128 = 0;
@@ -97,6 +400,39 @@ impl Emulator {
 
         // Game loop
         loop {
+            if self.display != texture_display {
+                if self.display.factor() != texture_display.factor() {
+                    texture = new_texture(self.display.factor());
+                }
+                if self.paused {
+                    self.cpu.draw_frame(&mut texture, self.display);
+                }
+                texture_display = self.display;
+            }
+
+            // Steps back a snapshot each time instead of running, until the key is let go
+            if self.rewinding {
+                self.rewind_step(&mut texture);
+                self.present(&mut canvas, &texture);
+                if self.update_input() {
+                    break;
+                }
+                std::thread::sleep(PAUSED_WAIT);
+                self.perf.lap(Lap::Wait);
+                continue;
+            }
+
+            // Nothing runs while paused but the window still gets drawn and input handled
+            if self.paused && !self.advance_frame {
+                self.present(&mut canvas, &texture);
+                if self.update_input() {
+                    break;
+                }
+                std::thread::sleep(PAUSED_WAIT);
+                self.perf.lap(Lap::Wait);
+                continue;
+            }
+
             #[cfg(feature = "debug")]
             {
                 dbug.clear();
@@ -112,24 +448,27 @@ impl Emulator {
                 }
             }
 
-            if self.cpu.update_input() {
-                // Is true when we get the exit signal
-                break;
-            }
-            self.cpu.check_interrupts();
+            self.step();
 
-            if self.cpu.is_running {
-                self.cpu.curr_cycles = 0;
-                self.cpu.execute();
-            } else {
-                // Halted
-                self.cpu.curr_cycles = 4;
-                self.cpu.adv_cycles(4); // Should this be 1 or 4?
-            }
+            if let Some(cycles) = self.cpu.take_frame() {
+                self.perf.lap(Lap::Emulate);
+                self.cpu.draw_frame(&mut texture, self.display);
+                self.advance_frame = false;
+                self.perf.lap(Lap::Draw);
+                self.pacer.wait_frame(cycles);
+                self.perf.lap(Lap::Wait);
+                self.present(&mut canvas, &texture);
+                if let Some(stats) = self.pacer.take_stats() {
+                    let title = window_title(stats);
+                    canvas.window_mut().set_title(&title).unwrap();
+                }
+                self.perf.lap(Lap::Draw);
 
-            if self.cpu.update_display(&mut texture) {
-                canvas.copy(&texture, None, rect).unwrap();
-                canvas.present();
+                // Input is sampled once per frame
+                if self.update_input() || self.end_frame() {
+                    // Is true when we get the exit signal
+                    break;
+                }
             }
 
             counter = counter.wrapping_add(1);
@@ -154,6 +493,485 @@ impl Emulator {
                 }
             }
         }
+
+        // A window resized by dragging is remembered as the biggest scale that fits in it
+        if !self.fullscreen {
+            let (width, height) = canvas.window().size();
+            let scale = std::cmp::min(width / NUM_PIXELS_X, height / NUM_PIXELS_Y);
+            self.scale = scale.clamp(1, MAX_SCALE);
+        }
+        self.remember_window();
+        self.finish();
+    }
+
+    // Runs as fast as it can without opening a window, until the frame limit.
+    // Movies still play back so a bug can be recorded and then screenshotted.
+    // Benchmarks time just the frames, not the setup or saving anything after
+    fn run_headless(self: &mut Self) {
+        if self.frame_limit.is_none() {
+            panic!("Running headless needs a number of frames to stop after (--frames)");
+        }
+        let start = Instant::now();
+        loop {
+            self.step();
+            if self.cpu.take_frame().is_some() {
+                self.perf.lap(Lap::Emulate);
+                self.set_buttons(HashSet::new());
+                if self.end_frame() {
+                    break;
+                }
+            }
+        }
+        let elapsed = start.elapsed();
+        self.finish();
+        if self.benchmark {
+            println!("{}", perf::benchmark_summary(self.frame_count, elapsed));
+        }
+    }
+
+    // One instruction, or 4 cycles while halted
+    fn step(self: &mut Self) {
+        self.cpu.check_interrupts();
+
+        if self.cpu.is_running {
+            self.cpu.curr_cycles = 0;
+            self.cpu.execute();
+            self.perf.count_instruction();
+        } else {
+            // Halted
+            self.cpu.curr_cycles = 4;
+            self.cpu.adv_cycles(4); // Should this be 1 or 4?
+        }
+    }
+
+    // Returns true once the frame limit is reached
+    fn end_frame(self: &mut Self) -> bool {
+        self.frame_count += 1;
+        if let Some(rewind) = &mut self.rewind {
+            if rewind.is_snapshot_frame() {
+                let mut state = StateWriter::new();
+                self.cpu.save_state(&mut state);
+                rewind.push(self.frame_count, state.finish());
+            }
+        }
+        if self.recorder.is_some() {
+            self.record_frame();
+        }
+        if self.screenshot_frames.contains(&self.frame_count) {
+            self.save_screenshot(Some(self.frame_count));
+        }
+        self.perf.lap(Lap::Other);
+        self.update_perf();
+        return match self.frame_limit {
+            Some(limit) => self.frame_count >= limit,
+            None => false,
+        };
+    }
+
+    // A report once a second, see perf.rs
+    fn update_perf(self: &mut Self) {
+        if !self.perf.end_frame() {
+            return;
+        }
+        let report = self.perf.report(self.cpu.take_part_times());
+        if self.perf_log {
+            println!("{}", report.log_line());
+        }
+        self.perf_report = Some(report);
+    }
+
+    // Without a folder, recordings go next to the screenshots in a folder named
+    // after the game and the time
+    pub fn start_recording(self: &mut Self, dir: Option<String>) {
+        let dir = match dir {
+            Some(dir) => PathBuf::from(dir),
+            None => PathBuf::from(self.screenshots.dir.as_deref().unwrap_or("."))
+                .join(screenshot::name_now(&self.title, None)),
+        };
+        match Recorder::start(&dir, self.cpu.get_sample_rate()) {
+            Ok(recorder) => {
+                println!("Recording video to {}", dir.display());
+                self.cpu.set_audio_capture(true);
+                self.recorder = Some(recorder);
+            }
+            Err(e) => println!("{}", e),
+        }
+    }
+
+    pub fn stop_recording(self: &mut Self) {
+        if let Some(recorder) = self.recorder.take() {
+            self.cpu.set_audio_capture(false);
+            let dir = recorder.dir().to_path_buf();
+            match recorder.finish() {
+                Ok(frames) => println!(
+                    "Recorded {} frames ({:.1}s) to {}",
+                    frames,
+                    frames as f64 / FRAME_RATE,
+                    dir.display()
+                ),
+                Err(e) => println!("{}", e),
+            }
+        }
+    }
+
+    fn record_frame(self: &mut Self) {
+        let samples = self.cpu.take_audio_samples();
+        let image = self.cpu.screenshot(1);
+        if let Some(recorder) = &mut self.recorder {
+            if let Err(e) = recorder.add_frame(&image, &samples) {
+                println!("Stopped recording: {}", e);
+                self.stop_recording();
+            }
+        }
+    }
+
+    pub fn save_screenshot(self: &Self, frame: Option<u64>) {
+        let scale = if self.screenshots.window_scale {
+            self.scale as usize
+        } else {
+            1
+        };
+        let image = self.cpu.screenshot(scale);
+        match screenshot::save(&image, self.screenshots.dir.as_deref(), &self.title, frame) {
+            Ok(path) => println!("Saved screenshot to {}", path.display()),
+            Err(e) => println!("{}", e),
+        }
+    }
+
+    // Shows the texture with the on screen messages over it, and redraws the debug windows
+    fn present(self: &mut Self, canvas: &mut Canvas<Window>, texture: &Texture) {
+        if self.resize_window {
+            self.resize_window = false;
+            self.apply_window(canvas.window_mut());
+        }
+        let (x, y, width, height) = scaler::display_rect(
+            canvas.output_size().unwrap(),
+            (NUM_PIXELS_X, NUM_PIXELS_Y),
+            self.integer_scale,
+        );
+        let screen = Rect::new(x, y, width, height);
+        canvas.clear();
+        canvas.copy(texture, None, screen).unwrap();
+
+        let status = if self.rewinding {
+            Some(format!("Rewinding  frame {}", self.frame_count))
+        } else if self.paused {
+            Some(format!("Paused  frame {}", self.frame_count))
+        } else if self.uncapped {
+            Some(String::from("Unlimited speed"))
+        } else if self.fast_forward {
+            Some(format!("Fast forward {}x", self.fast_forward_speed))
+        } else if self.speed != 1.0 {
+            Some(format!("Speed {}x", self.speed))
+        } else {
+            None
+        };
+        self.osd.set_status(status.as_deref());
+        let overlay = match &self.perf_report {
+            Some(report) if self.perf_overlay => report.lines(),
+            _ => Vec::new(),
+        };
+        self.osd.set_overlay(overlay);
+        self.osd.draw(canvas, screen);
+        canvas.present();
+
+        for window in self.debug_windows.iter_mut() {
+            window.draw(&self.cpu);
+        }
+    }
+
+    // Printed and shown on screen
+    fn notify(self: &mut Self, message: &str) {
+        println!("{}", message);
+        self.osd.show(message);
+    }
+
+    // Goes back to the newest snapshot and shows it. The frame count goes back
+    // with it so the frame shown while paused stays right
+    fn rewind_step(self: &mut Self, texture: &mut Texture) {
+        let rewind = match &mut self.rewind {
+            Some(rewind) => rewind,
+            None => return,
+        };
+        if let Some((frame, snapshot)) = rewind.pop() {
+            let mut state = StateReader::new(&snapshot);
+            self.cpu.load_state(&mut state);
+            self.frame_count = frame;
+            self.cpu.draw_frame(texture, self.display);
+        }
+    }
+
+    // A movie recorded from a save state starts by loading it. The first frame
+    // runs straight after, so it gets its buttons now like it did when recording
+    fn load_movie_state(self: &mut Self) {
+        let snapshot = match self.movie.as_ref().and_then(|s| s.start_state()) {
+            Some(snapshot) => snapshot.to_vec(),
+            None => return,
+        };
+        let mut fresh = StateWriter::new();
+        self.cpu.save_state(&mut fresh);
+        if fresh.finish().len() != snapshot.len() {
+            panic!("Movie was recorded from a save state this build cant load");
+        }
+        let mut state = StateReader::new(&snapshot);
+        self.cpu.load_state(&mut state);
+        self.set_buttons(HashSet::new());
+    }
+
+    // Starts a movie from a save state of the game as it is, or saves the one
+    // being recorded. Named and placed like video recordings
+    fn toggle_movie(self: &mut Self) {
+        if let Some(session) = &mut self.movie {
+            if !session.is_recording() {
+                self.notify("Cant record a movie while one is playing");
+                return;
+            }
+            session.finish(movie::hash_bytes(&self.cpu.get_shades()));
+            self.movie = None;
+            return;
+        }
+        if self.rewinding {
+            self.notify("Cant record a movie while rewinding");
+            return;
+        }
+        let path = PathBuf::from(self.screenshots.dir.as_deref().unwrap_or("."))
+            .join(screenshot::name_now(&self.title, None) + ".gbm");
+        let path = path.to_string_lossy().to_string();
+        let mut state = StateWriter::new();
+        self.cpu.save_state(&mut state);
+        let movie = Movie::new(self.model, self.rom_hash, RtcClock::System.now())
+            .with_start_state(state.finish());
+        println!("Recording movie to {}", path);
+        self.movie = Some(MovieSession::record(path, movie));
+    }
+
+    // Starts the game over without reloading it. The cartridge ram isnt touched,
+    // like pressing the power switch quickly on a cartridge with a battery
+    fn reset(self: &mut Self) {
+        if self.movie.is_some() {
+            self.notify("Cant reset during a movie");
+            return;
+        }
+        self.cpu.reset(self.model, self.cart.checksum_val);
+        self.notify("Reset");
+    }
+
+    fn apply_window(self: &Self, window: &mut Window) {
+        if self.fullscreen {
+            window.set_fullscreen(FullscreenType::Desktop).unwrap();
+            return;
+        }
+        window.set_fullscreen(FullscreenType::Off).unwrap();
+        window
+            .set_size(NUM_PIXELS_X * self.scale, NUM_PIXELS_Y * self.scale)
+            .unwrap();
+    }
+
+    // Writes the scale and fullscreen to the config file if they were changed
+    fn remember_window(self: &Self) {
+        if (self.scale, self.fullscreen) == self.start_window {
+            return;
+        }
+        let path = match &self.config_path {
+            Some(path) => path,
+            None => return,
+        };
+        let settings = [
+            ("video", "scale", self.scale.to_string()),
+            ("video", "fullscreen", self.fullscreen.to_string()),
+        ];
+        match Config::save_settings(path, &settings) {
+            Ok(()) => println!("Saved the window size to {}", path.display()),
+            Err(e) => println!("{}", e),
+        }
+    }
+
+    fn finish(self: &mut Self) {
+        self.stop_recording();
+
+        // Recording stops when the emulator is closed
+        if let Some(session) = &mut self.movie {
+            session.finish(movie::hash_bytes(&self.cpu.get_shades()));
+        }
+
+        for (view, path) in self.dumps.iter() {
+            match self.cpu.viewer_image(*view, false).save_png(path) {
+                Ok(()) => println!("Saved {} to {}", view.name(), path),
+                Err(e) => println!("{}", e),
+            }
+            // Views with a listing get it saved next to the image
+            if let Some(listing) = self.cpu.viewer_listing(*view) {
+                let text_path = std::path::Path::new(path).with_extension("txt");
+                match std::fs::write(&text_path, listing + "\n") {
+                    Ok(()) => println!("Saved {} to {}", view.name(), text_path.display()),
+                    Err(e) => println!("Couldnt write {}: {}", text_path.display(), e),
+                }
+            }
+        }
+    }
+
+    // Handles the hotkeys, then hands the buttons held since the last frame to
+    // the joypad and passes the cartridges rumble motor on to the controllers.
+    // Buttons are only handed over when a frame is going to run so movies stay
+    // one input per frame while paused
+    fn update_input(self: &mut Self) -> bool {
+        let input = match &mut self.input {
+            Some(input) => input,
+            None => panic!("No input was initialized"),
+        };
+
+        let commands = input.poll();
+        let held = input.get_buttons();
+
+        for command in commands {
+            match command {
+                Command::Hotkey(Hotkey::Quit) => return true,
+                Command::Hotkey(Hotkey::TileViewer) => self.toggle_debug_window(View::Tiles),
+                Command::Hotkey(Hotkey::TilemapViewer) => self.toggle_debug_window(View::Tilemaps),
+                Command::Hotkey(Hotkey::SpriteViewer) => self.toggle_debug_window(View::Sprites),
+                Command::Hotkey(Hotkey::Screenshot) => self.save_screenshot(None),
+                Command::Hotkey(Hotkey::NextPalette) => self.next_palette(),
+                Command::Hotkey(Hotkey::Pause) => {
+                    self.paused = !self.paused;
+                    self.notify(if self.paused { "Paused" } else { "Resumed" });
+                }
+                Command::Hotkey(Hotkey::FrameAdvance) => match self.paused {
+                    true => self.advance_frame = true,
+                    false => {
+                        self.paused = true;
+                        self.notify("Paused");
+                    }
+                },
+                Command::Hotkey(Hotkey::Reset) => self.reset(),
+                Command::Hotkey(Hotkey::FastForward) => {
+                    self.fast_forward = true;
+                    self.update_speed();
+                }
+                Command::HotkeyReleased(Hotkey::FastForward) => {
+                    self.fast_forward = false;
+                    self.update_speed();
+                }
+                Command::Hotkey(Hotkey::Rewind) => {
+                    if self.movie.is_some() {
+                        self.notify("Cant rewind during a movie");
+                    } else if self.rewind.is_none() {
+                        self.notify("Rewind is turned off");
+                    } else {
+                        self.rewinding = true;
+                    }
+                }
+                Command::HotkeyReleased(Hotkey::Rewind) => self.rewinding = false,
+                Command::HotkeyReleased(_) => {}
+                Command::Hotkey(Hotkey::PerfOverlay) => {
+                    self.perf_overlay = !self.perf_overlay;
+                    if self.perf_overlay && self.perf_report.is_none() {
+                        self.notify("Measuring performance...");
+                    }
+                }
+                Command::Hotkey(Hotkey::ToggleUncapped) => {
+                    self.uncapped = !self.uncapped;
+                    self.update_speed();
+                    let message = match self.uncapped {
+                        true => String::from("Speed: unlimited"),
+                        false => format!("Speed: {}x", self.speed),
+                    };
+                    self.notify(&message);
+                }
+                Command::Hotkey(Hotkey::SpeedUp) => self.step_speed(true),
+                Command::Hotkey(Hotkey::SpeedDown) => self.step_speed(false),
+                Command::Hotkey(Hotkey::NextScaler) => {
+                    self.display.scaler = self.display.scaler.next();
+                    let message = format!("Scaler: {}", self.display.scaler.name());
+                    self.notify(&message);
+                }
+                Command::Hotkey(Hotkey::ToggleFrameBlend) => {
+                    self.display.frame_blend = !self.display.frame_blend;
+                    let state = if self.display.frame_blend {
+                        "on"
+                    } else {
+                        "off"
+                    };
+                    self.notify(&format!("Frame blending {}", state));
+                }
+                Command::Hotkey(Hotkey::ToggleLcd) => {
+                    self.display.lcd = !self.display.lcd;
+                    let state = if self.display.lcd { "on" } else { "off" };
+                    self.notify(&format!("LCD simulation {}", state));
+                }
+                Command::Hotkey(Hotkey::Fullscreen) => {
+                    self.fullscreen = !self.fullscreen;
+                    self.resize_window = true;
+                }
+                Command::Hotkey(Hotkey::ScaleUp) | Command::Hotkey(Hotkey::ScaleDown) => {
+                    let scale = match command {
+                        Command::Hotkey(Hotkey::ScaleUp) => self.scale + 1,
+                        _ => self.scale - 1,
+                    };
+                    self.scale = scale.clamp(1, MAX_SCALE);
+                    self.resize_window = true;
+                    self.notify(&format!("Scale: {}x", self.scale));
+                }
+                Command::Hotkey(Hotkey::RecordVideo) => match self.recorder {
+                    Some(_) => self.stop_recording(),
+                    None => self.start_recording(None),
+                },
+                Command::Hotkey(Hotkey::RecordMovie) => self.toggle_movie(),
+                Command::Hotkey(Hotkey::ToggleLayer(layer)) => {
+                    let visible = !self.cpu.is_layer_visible(layer);
+                    self.cpu.set_layer_visible(layer, visible);
+                    let state = if visible { "shown" } else { "hidden" };
+                    self.notify(&format!("{:?} {}", layer, state));
+                }
+                Command::CloseWindow(id) => self.debug_windows.retain(|w| w.id() != id),
+                Command::MouseMotion(id, x, y) => {
+                    if let Some(window) = self.debug_windows.iter_mut().find(|w| w.id() == id) {
+                        window.mouse_moved(x, y);
+                    }
+                }
+                Command::WindowKey(id, key) => {
+                    if let Some(window) = self.debug_windows.iter_mut().find(|w| w.id() == id) {
+                        window.key_pressed(key);
+                    }
+                }
+            }
+        }
+
+        // The buttons are for the next frame, so while paused (or rewinding) they
+        // wait until a frame actually runs (movies get one input per frame)
+        let running = (!self.paused || self.advance_frame) && !self.rewinding;
+        if running {
+            self.set_buttons(held);
+        }
+        let rumbling = running && self.cpu.is_rumbling();
+        if let Some(input) = &mut self.input {
+            input.set_rumble(rumbling);
+        }
+        return false;
+    }
+
+    // Once a movie is done playing the player takes over
+    fn set_buttons(self: &mut Self, held: HashSet<Button>) {
+        let mut buttons = held;
+        if let Some(session) = &mut self.movie {
+            if session.is_finished() {
+                session.finish(movie::hash_bytes(&self.cpu.get_shades()));
+                self.movie = None;
+            } else {
+                buttons = session.next_frame(buttons);
+            }
+        }
+        self.cpu.set_buttons(buttons);
+    }
+
+    fn toggle_debug_window(self: &mut Self, view: View) {
+        if self.debug_windows.iter().any(|w| w.view() == view) {
+            self.debug_windows.retain(|w| w.view() != view);
+            return;
+        }
+        if let Some(video_subsystem) = &self.video_subsystem {
+            self.debug_windows
+                .push(DebugWindow::new(video_subsystem, view));
+        }
     }
 
     #[cfg(feature = "debug-file")]
@@ -208,11 +1026,19 @@ impl Emulator {
     }
 }
 
+// Like "Rust-Gameboy-Emulator - 59.7 FPS (100%)"
+fn window_title(stats: Stats) -> String {
+    return format!(
+        "{} - {:.1} FPS ({:.0}%)",
+        WINDOW_TITLE, stats.fps, stats.speed_percent
+    );
+}
+
 /*
     thread::sleep sucks, need to do either audio or video sync to emulate
     proper speed rather than trying to sync between instructions.
 
-    Currently doing video sync but should switch to audio when implemented
+    Currently doing video sync (see pacing.rs) but should switch to audio when implemented
 
     If we choose audio it works differently (Need to figure this out)
     https://forums.nesdev.org/viewtopic.php?f=3&t=15405
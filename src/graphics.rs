@@ -1,40 +1,83 @@
 pub mod dma;
 pub mod gpu_memory;
+mod hblank;
+pub mod lcd;
+pub mod oam_bug;
 mod oam_search;
+pub mod palette;
 mod picture_generation;
-mod hblank;
-mod vblank;
 mod ppu;
+pub mod scaler;
+mod vblank;
+pub mod viewer;
 
 use super::io::Io;
-use crate::cpu::CPU_PERIOD_NANOS;
+use crate::model::Model;
+use crate::state::{StateReader, StateWriter};
 use gpu_memory::*;
+use oam_bug::OamBug;
+use palette::Palette;
 use ppu::PpuState;
 use ppu::PpuState::{HBlank, OamSearch, PictureGeneration, VBlank};
+use scaler::Scaler;
 use sdl2::render::Texture;
-use std::time::{Duration, Instant};
+use viewer::{Image, View};
 
-pub const SCALE: u32 = 3;
+pub const SCALE: u32 = 3; // Default window scale
+pub const MAX_SCALE: u32 = 8;
+pub const MIN_SPEED: f64 = 0.25;
+pub const MAX_SPEED: f64 = 8.0;
+pub const SPEEDS: [f64; 6] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0]; // Steps for speed_up/speed_down
 pub const NUM_PIXELS_X: u32 = 160;
 pub const NUM_PIXELS_Y: u32 = 144;
 pub const TOTAL_PIXELS: usize = (NUM_PIXELS_X * NUM_PIXELS_Y) as usize;
 
-pub const SCREEN_WIDTH: u32 = NUM_PIXELS_X * SCALE; // Only used by the window and rect (not in the texture)
-pub const SCREEN_HEIGHT: u32 = NUM_PIXELS_Y * SCALE; // Only used by the window and rect (not in the texture)
 pub const BYTES_PER_ROW: usize = BYTES_PER_PIXEL * (NUM_PIXELS_X as usize); // :(
 
 pub const NUM_PIXEL_BYTES: usize = TOTAL_PIXELS * BYTES_PER_PIXEL;
+pub const CYCLES_PER_FRAME: usize = 70_224; // 154 lines of 456 cycles
 
 pub const BYTES_PER_TILE: usize = 16;
 pub const BYTES_PER_TILE_SIGNED: isize = 16;
 pub const DMA_SRC_MUL: u16 = 0x0100;
 
+// How frames get turned into what is in the window, see scaler.rs and lcd.rs
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct DisplayOptions {
+    pub scaler: Scaler,
+    pub frame_blend: bool,
+    pub lcd: bool, // Takes the place of the scaler while on
+}
+
+impl DisplayOptions {
+    pub fn new() -> DisplayOptions {
+        return DisplayOptions {
+            scaler: Scaler::Nearest,
+            frame_blend: false,
+            lcd: false,
+        };
+    }
+
+    // How many times bigger than 160x144 the frames given to the texture are
+    pub fn factor(self: &Self) -> usize {
+        return if self.lcd {
+            lcd::LCD_SCALE
+        } else {
+            self.scaler.factor()
+        };
+    }
+}
+
 pub struct Graphics {
     state: PpuState,
     gpu_data: GpuMemory,
+    prev_pixels: Vec<u8>, // The frame shown before the current one, for frame blending
+    shown_pixels: Vec<u8>, // The last frame shown
+    frame_drawn: bool,    // So redrawing the same frame doesnt move prev_pixels along
     frame_ready: bool,
     cycles: usize,
-    prev_frame_time: Instant,
+    model: Model,
+    oam_bug: bool, // Emulate the oam corruption bug
 }
 
 impl Graphics {
@@ -43,9 +86,13 @@ impl Graphics {
         Graphics {
             state: ppu::init(&mut gpu_mem),
             gpu_data: GpuMemory::new(),
+            prev_pixels: vec![0; NUM_PIXEL_BYTES],
+            shown_pixels: vec![0; NUM_PIXEL_BYTES],
+            frame_drawn: false,
             frame_ready: false,
             cycles: 0,
-            prev_frame_time: Instant::now(),
+            model: Model::Dmg,
+            oam_bug: true,
         }
     }
 
@@ -125,7 +172,10 @@ impl Graphics {
             }
             STAT_REG => {
                 // For 1 cycle write 0xFF and whatever resulting interrupts
-                if self.stat_quirk(data) {
+                // The cgb doesnt have this bug so just write the value
+                if !self.model.has_stat_quirk() {
+                    self.gpu_data.write_ppu_io(addr, data);
+                } else if self.stat_quirk(data) {
                     self.gpu_data.write_ppu_io(addr, 0xFF);
                 }
             }
@@ -134,7 +184,13 @@ impl Graphics {
     }
 
     pub fn adv_cycles(self: &mut Self, io: &mut Io, cycles: usize) {
+        // The screen stays blank while the ppu is off but frames keep going so that
+        // pacing and input still happen once per frame
         if !self.gpu_data.is_ppu_enabled() {
+            self.cycles += cycles;
+            if self.cycles >= CYCLES_PER_FRAME {
+                self.frame_ready = true;
+            }
             return;
         }
 
@@ -180,15 +236,72 @@ impl Graphics {
         }
     }
 
-    pub fn dmg_init(self: &mut Self) {
+    // Back to power on, keeping the palette, hidden layers and oam bug setting.
+    // init has to be called again afterwards
+    pub fn reset(self: &mut Self) {
+        let mut graphics = Graphics::new();
+        graphics.gpu_data.set_palette(self.gpu_data.palette);
+        graphics.gpu_data.hidden_layers = self.gpu_data.hidden_layers;
+        graphics.oam_bug = self.oam_bug;
+        *self = graphics;
+    }
+
+    // The settings (palette, oam bug...) stay as they are
+    pub fn save_state(self: &Self, state: &mut StateWriter) {
+        match &self.state {
+            OamSearch(os) => {
+                state.write_u8(0);
+                os.save_state(state);
+            }
+            PictureGeneration(pg) => {
+                state.write_u8(1);
+                pg.save_state(state);
+            }
+            HBlank(hb) => {
+                state.write_u8(2);
+                hb.save_state(state);
+            }
+            VBlank(vb) => {
+                state.write_u8(3);
+                vb.save_state(state);
+            }
+            PpuState::None => panic!("Ppu state should never be None"),
+        }
+        self.gpu_data.save_state(state);
+        state.write_bool(self.frame_ready);
+        state.write_usize(self.cycles);
+    }
+
+    pub fn load_state(self: &mut Self, state: &mut StateReader) {
+        self.state = match state.read_u8() {
+            0 => oam_search::OamSearch::load_state(state),
+            1 => picture_generation::PictureGeneration::load_state(state),
+            2 => hblank::HBlank::load_state(state),
+            _ => vblank::VBlank::load_state(state),
+        };
+        self.gpu_data.load_state(state);
+        self.frame_ready = state.read_bool();
+        self.cycles = state.read_usize();
+        self.frame_drawn = false;
+    }
+
+    pub fn init(self: &mut Self, model: Model) {
+        self.model = model;
         self.gpu_data.dmg_init();
     }
 
     // https://www.reddit.com/r/Gameboy/comments/a1c8h0/what_happens_when_a_gameboy_screen_is_disabled/
     pub fn disable_ppu(self: &mut Self) {
         self.state = ppu::disable(&mut self.gpu_data);
-        self.gpu_data.pixels.iter_mut().for_each(|pix| *pix = 0);
+        let blank = self.gpu_data.palette.bg[0];
+        self.gpu_data
+            .pixels
+            .chunks_mut(BYTES_PER_PIXEL)
+            .for_each(|pix| pix.copy_from_slice(&blank));
+        self.gpu_data.shades.fill(0);
         self.gpu_data.window_line_counter = 0;
+        self.gpu_data.window_y_triggered = false;
+        self.gpu_data.window_wx166 = false;
         self.gpu_data.stat_low_to_high = false; // Just in case
 
         // ppu is disabled so comparison shouldnt occur and current compare status should not be changed
@@ -201,6 +314,7 @@ impl Graphics {
         // Not doing the 2 cycle delay yet
         self.state = ppu::enable(&mut self.gpu_data);
         self.gpu_data.set_ly(0);
+        self.gpu_data.check_window_y();
         self.gpu_data.sprite_list.clear();
         self.gpu_data.stat_low_to_high = false; // Just in case
     }
@@ -220,6 +334,62 @@ impl Graphics {
         }
     }
 
+    // The shade (0-3) of every pixel on the screen, whatever colors are being used for them
+    pub fn get_shades(self: &Self) -> Vec<u8> {
+        return self.gpu_data.shades.to_vec();
+    }
+
+    pub fn set_palette(self: &mut Self, palette: Palette) {
+        self.gpu_data.set_palette(palette);
+    }
+
+    // What is on screen right now. Pixels the ppu hasnt drawn yet (since the lcd was
+    // turned on) are left black, but every pixel is made opaque for the png
+    pub fn screenshot(self: &Self, scale: usize) -> Image {
+        let mut image = Image::new(NUM_PIXELS_X as usize, NUM_PIXELS_Y as usize);
+        image.pixels.copy_from_slice(&self.gpu_data.pixels);
+        for pixel in image.pixels.chunks_mut(BYTES_PER_PIXEL) {
+            pixel[3] = 0xFF;
+        }
+        return image.scaled(scale);
+    }
+
+    pub fn viewer_image(self: &Self, view: View, grayscale: bool) -> Image {
+        return viewer::render(&self.gpu_data, view, grayscale);
+    }
+
+    pub fn viewer_info(self: &Self, view: View, x: usize, y: usize) -> Option<String> {
+        return viewer::describe(&self.gpu_data, view, x, y);
+    }
+
+    pub fn viewer_listing(self: &Self, view: View) -> Option<String> {
+        return viewer::listing(&self.gpu_data, view);
+    }
+
+    // Hiding a layer only changes the picture, see Layer in gpu_memory.rs
+    pub fn set_layer_visible(self: &mut Self, layer: Layer, visible: bool) {
+        self.gpu_data.set_layer_visible(layer, visible);
+    }
+
+    pub fn is_layer_visible(self: &Self, layer: Layer) -> bool {
+        return self.gpu_data.is_layer_visible(layer);
+    }
+
+    pub fn set_oam_bug(self: &mut Self, enabled: bool) {
+        self.oam_bug = enabled;
+    }
+
+    // Called whenever the cpu puts addr on the bus, before the cycle is run.
+    // Only the dmg has the bug and only while the ppu is reading oam in mode 2
+    pub fn trigger_oam_bug(self: &mut Self, addr: u16, kind: OamBug) {
+        if !self.oam_bug || self.model.is_cgb() || !(0xFE00..=0xFEFF).contains(&addr) {
+            return;
+        }
+        if let OamSearch(os) = &self.state {
+            oam_bug::corrupt(&mut self.gpu_data.oam, os.current_row(), kind);
+        }
+    }
+
     // Just so that the states know if one is going on
     pub fn set_dma_transfer(self: &mut Self, status: bool) {
         self.gpu_data.dma_transfer = status;
@@ -233,23 +403,46 @@ impl Graphics {
         }
     }
 
-    pub fn update_display(self: &mut Self, texture: &mut Texture) -> bool {
-        if self.frame_ready {
-            let wait_time = (self.cycles as f64) * CPU_PERIOD_NANOS;
-            let elapsed = self.prev_frame_time.elapsed().as_nanos() as f64;
-            if elapsed < wait_time {
-                std::thread::sleep(Duration::from_nanos((wait_time - elapsed) as u64));
-            }
-
+    // Puts the current frame in the texture without waiting for a new one, also
+    // used to show changes to the display options while paused
+    pub fn draw_frame(self: &mut Self, texture: &mut Texture, display: DisplayOptions) {
+        if !self.frame_drawn {
+            std::mem::swap(&mut self.prev_pixels, &mut self.shown_pixels);
+            self.shown_pixels.copy_from_slice(&self.gpu_data.pixels);
+            self.frame_drawn = true;
+        }
+        if display == DisplayOptions::new() {
             texture
                 .update(None, &self.gpu_data.pixels, BYTES_PER_ROW)
                 .expect("updating texture didnt work");
+        } else {
+            let mut frame = Image::new(NUM_PIXELS_X as usize, NUM_PIXELS_Y as usize);
+            if display.frame_blend {
+                frame.pixels = lcd::blend_frames(&self.prev_pixels, &self.gpu_data.pixels);
+            } else {
+                frame.pixels.copy_from_slice(&self.gpu_data.pixels);
+            }
+            let frame = if display.lcd {
+                lcd::simulate(&frame)
+            } else {
+                display.scaler.apply(&frame)
+            };
+            texture
+                .update(None, &frame.pixels, frame.width * BYTES_PER_PIXEL)
+                .expect("updating texture didnt work");
+        }
+    }
 
+    // The cycles the frame took once it is finished, draw_frame puts it in the window.
+    // Usually 70224 but turning the lcd off or on cuts a frame short
+    pub fn take_frame(self: &mut Self) -> Option<usize> {
+        if self.frame_ready {
+            let cycles = self.cycles;
             self.cycles = 0;
             self.frame_ready = false;
-            self.prev_frame_time = Instant::now();
-            return true;
+            self.frame_drawn = false;
+            return Some(cycles);
         }
-        return false;
+        return None;
     }
 }
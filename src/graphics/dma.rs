@@ -2,6 +2,8 @@ use core::panic;
 
 use crate::bus::BusType;
 use crate::graphics::Graphics;
+use crate::model::Model;
+use crate::state::{StateReader, StateWriter};
 
 use super::gpu_memory::{OAM_END, OAM_START, VRAM_END, VRAM_START};
 
@@ -30,6 +32,32 @@ impl OamDma {
         }
     }
 
+    pub fn save_state(self: &Self, state: &mut StateWriter) {
+        state.write_u8(self.dma);
+        state.write_u8(self.value);
+        state.write_u16(self.cycles);
+        state.write_usize(self.delay_cycles);
+        state.write_bool(self.in_transfer);
+        state.write_u8(match self.bus_conflict {
+            BusType::Video => 0,
+            BusType::External => 1,
+            BusType::None => 2,
+        });
+    }
+
+    pub fn load_state(self: &mut Self, state: &mut StateReader) {
+        self.dma = state.read_u8();
+        self.value = state.read_u8();
+        self.cycles = state.read_u16();
+        self.delay_cycles = state.read_usize();
+        self.in_transfer = state.read_bool();
+        self.bus_conflict = match state.read_u8() {
+            0 => BusType::Video,
+            1 => BusType::External,
+            _ => BusType::None,
+        };
+    }
+
     pub fn read_dma(self: &Self, addr: u16) -> u8 {
         if addr != DMA_REG {
             panic!("dma should not write to addr: {:04X}", addr);
@@ -132,8 +160,9 @@ impl OamDma {
         };
     }
 
-    pub fn dmg_init(self: &mut Self) {
-        self.dma = 0xFF;    // Starting register value
+    pub fn init(self: &mut Self, model: Model) {
+        // Starting register value
+        self.dma = if model.is_cgb() { 0x00 } else { 0xFF };
     }
 
     pub fn has_conflict(self: &Self) -> bool {
@@ -1,7 +1,8 @@
 // For the cgb specific io we will continue to write them to Io rather than here
 use super::oam_search::Sprite;
-use super::NUM_PIXEL_BYTES;
-use std::collections::VecDeque;
+use super::palette::Palette;
+use super::{NUM_PIXEL_BYTES, TOTAL_PIXELS};
+use crate::state::{StateReader, StateWriter};
 
 pub const LCDC_REG: u16 = 0xFF40;
 pub const STAT_REG: u16 = 0xFF41; // LCD Status
@@ -40,6 +41,8 @@ pub const BYTES_PER_PIXEL: usize = 4;
 // Theres a lot of stuff. Clean it up? Split up?
 pub struct GpuMemory {
     pub pixels: [u8; NUM_PIXEL_BYTES],
+    // The shade (0 - 3) the palette registers picked for each pixel
+    pub shades: [u8; TOTAL_PIXELS],
     pub vram: [u8; 8_192], // 0x8000 - 0x9FFF
     pub oam: [u8; 160],    // OAM 0xFE00 - 0xFE9F  40 sprites, each takes 4 bytes
     pub lcdc: u8,          // 0xFF40
@@ -54,6 +57,8 @@ pub struct GpuMemory {
     pub wy: u8,            // 0xFF4A
     pub wx: u8,            // 0xFF4B
     pub window_line_counter: u8,
+    pub window_y_triggered: bool, // LY has matched WY at some point this frame
+    pub window_wx166: bool,       // The window started at WX = 166 on the line before
     pub dma_transfer: bool,
     pub stat_int: bool,
     pub stat_low_to_high: bool,
@@ -61,16 +66,29 @@ pub struct GpuMemory {
     pub dmg_stat_quirk: Option<u8>,
     pub dmg_stat_quirk_delay: bool,
     pub sprite_list: Vec<Sprite>,
-    pub bg_pixel_fifo: VecDeque<[u8; 4]>,
+    pub palette: Palette, // The colors the palette registers pick from
     pub bg_colors: [[u8; 4]; 4],
     pub obp0_colors: [[u8; 4]; 4],
     pub obp1_colors: [[u8; 4]; 4],
+    pub hidden_layers: [bool; 3], // Indexed by Layer, only for debugging
+}
+
+// Parts of the picture that can be hidden to see what is under them. A hidden
+// layer is still fetched like normal so the timing doesnt change, its pixels
+// just get treated as color 0 when mixing. This never touches lcdc so the game
+// cant tell
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Layer {
+    Background,
+    Window,
+    Sprites,
 }
 
 impl GpuMemory {
     pub fn new() -> GpuMemory {
         return GpuMemory {
             pixels: [0; NUM_PIXEL_BYTES],
+            shades: [0; TOTAL_PIXELS],
             vram: [0; 8_192],
             oam: [0; 160],
             lcdc: 0,
@@ -85,6 +103,8 @@ impl GpuMemory {
             wy: 0,
             wx: 0,
             window_line_counter: 0,
+            window_y_triggered: false,
+            window_wx166: false,
             dma_transfer: false,
             stat_int: false,
             stat_low_to_high: false,
@@ -92,10 +112,11 @@ impl GpuMemory {
             dmg_stat_quirk: None,
             dmg_stat_quirk_delay: false,
             sprite_list: Vec::<Sprite>::new(),
-            bg_pixel_fifo: VecDeque::new(),
+            palette: Palette::uniform(COLORS),
             bg_colors: COLORS.clone(),
             obp0_colors: COLORS.clone(),
             obp1_colors: COLORS.clone(),
+            hidden_layers: [false; 3],
         };
     }
 
@@ -139,14 +160,83 @@ impl GpuMemory {
             OBP0_REG => self.set_obp0_palette(data),
             OBP1_REG => self.set_obp1_palette(data),
             WY_REG => self.wy = data,
-            WX_REG => {
-                // https://gbdev.io/pandocs/pixel_fifo.html#the-window  implement this eventually
-                self.wx = data
-            }
+            WX_REG => self.wx = data,
             _ => panic!("PPU IO does not handle writes to: {:04X}", addr),
         }
     }
 
+    // The palette and hidden layers are settings so they arent saved, the colors
+    // get worked out again from the palette registers when loading
+    pub fn save_state(self: &Self, state: &mut StateWriter) {
+        state.write_bytes(&self.pixels);
+        state.write_bytes(&self.shades);
+        state.write_bytes(&self.vram);
+        state.write_bytes(&self.oam);
+        state.write_bytes(&[
+            self.lcdc,
+            self.stat,
+            self.scy,
+            self.scx,
+            self.ly,
+            self.lyc,
+            self.bgp,
+            self.obp0,
+            self.obp1,
+            self.wy,
+            self.wx,
+            self.window_line_counter,
+        ]);
+        state.write_bool(self.window_y_triggered);
+        state.write_bool(self.window_wx166);
+        state.write_bool(self.dma_transfer);
+        state.write_bool(self.stat_int);
+        state.write_bool(self.stat_low_to_high);
+        state.write_bool(self.vblank_int);
+        state.write_bool(self.dmg_stat_quirk.is_some());
+        state.write_u8(self.dmg_stat_quirk.unwrap_or(0));
+        state.write_bool(self.dmg_stat_quirk_delay);
+        state.write_usize(self.sprite_list.len());
+        for sprite in self.sprite_list.iter() {
+            sprite.save_state(state);
+        }
+    }
+
+    pub fn load_state(self: &mut Self, state: &mut StateReader) {
+        state.read_bytes(&mut self.pixels);
+        state.read_bytes(&mut self.shades);
+        state.read_bytes(&mut self.vram);
+        state.read_bytes(&mut self.oam);
+        let mut registers = [0; 12];
+        state.read_bytes(&mut registers);
+        [
+            self.lcdc,
+            self.stat,
+            self.scy,
+            self.scx,
+            self.ly,
+            self.lyc,
+            self.bgp,
+            self.obp0,
+            self.obp1,
+            self.wy,
+            self.wx,
+            self.window_line_counter,
+        ] = registers;
+        self.window_y_triggered = state.read_bool();
+        self.window_wx166 = state.read_bool();
+        self.dma_transfer = state.read_bool();
+        self.stat_int = state.read_bool();
+        self.stat_low_to_high = state.read_bool();
+        self.vblank_int = state.read_bool();
+        let has_quirk = state.read_bool();
+        let quirk = state.read_u8();
+        self.dmg_stat_quirk = if has_quirk { Some(quirk) } else { None };
+        self.dmg_stat_quirk_delay = state.read_bool();
+        let sprites = state.read_usize();
+        self.sprite_list = (0..sprites).map(|_| Sprite::load_state(state)).collect();
+        self.set_palette(self.palette);
+    }
+
     pub fn dmg_init(self: &mut Self) {
         self.lcdc = 0x91;
         self.stat = 0x85;
@@ -238,34 +328,50 @@ impl GpuMemory {
         return self.stat & 0x03;
     }
 
+    // Swap out the colors used for the screen and refresh the palettes using them
+    pub fn set_palette(self: &mut Self, palette: Palette) {
+        self.palette = palette;
+        self.set_bg_palette(self.bgp);
+        self.set_obp0_palette(self.obp0);
+        self.set_obp1_palette(self.obp1);
+    }
+
     // Im guessing the reason to assign a color to each index
     // and not have them be static is to allow for stuff like
     // inverting colors or making everything the same color
     // to make something like a silohoette appear.
     fn set_bg_palette(self: &mut Self, data: u8) {
         self.bgp = data;
-        self.bg_colors[0] = COLORS[usize::from(data & 0x03)]; // Double check these bit manip
-        self.bg_colors[1] = COLORS[usize::from((data >> 2) & 0x03)];
-        self.bg_colors[2] = COLORS[usize::from((data >> 4) & 0x03)];
-        self.bg_colors[3] = COLORS[usize::from((data >> 6) & 0x03)];
+        self.bg_colors[0] = self.palette.bg[usize::from(data & 0x03)]; // Double check these bit manip
+        self.bg_colors[1] = self.palette.bg[usize::from((data >> 2) & 0x03)];
+        self.bg_colors[2] = self.palette.bg[usize::from((data >> 4) & 0x03)];
+        self.bg_colors[3] = self.palette.bg[usize::from((data >> 6) & 0x03)];
     }
 
     fn set_obp0_palette(self: &mut Self, mut data: u8) {
         self.obp0 = data;
         data = data & 0x0FC; // For sprites color index 0 should be transparent
-        self.obp0_colors[0] = COLORS[usize::from(data & 0x03)];
-        self.obp0_colors[1] = COLORS[usize::from((data >> 2) & 0x03)];
-        self.obp0_colors[2] = COLORS[usize::from((data >> 4) & 0x03)];
-        self.obp0_colors[3] = COLORS[usize::from((data >> 6) & 0x03)];
+        self.obp0_colors[0] = self.palette.obp0[usize::from(data & 0x03)];
+        self.obp0_colors[1] = self.palette.obp0[usize::from((data >> 2) & 0x03)];
+        self.obp0_colors[2] = self.palette.obp0[usize::from((data >> 4) & 0x03)];
+        self.obp0_colors[3] = self.palette.obp0[usize::from((data >> 6) & 0x03)];
     }
 
     fn set_obp1_palette(self: &mut Self, mut data: u8) {
         self.obp1 = data;
         data = data & 0x0FC; // For sprites color index 0 should be transparent
-        self.obp1_colors[0] = COLORS[usize::from(data & 0x03)];
-        self.obp1_colors[1] = COLORS[usize::from((data >> 2) & 0x03)];
-        self.obp1_colors[2] = COLORS[usize::from((data >> 4) & 0x03)];
-        self.obp1_colors[3] = COLORS[usize::from((data >> 6) & 0x03)];
+        self.obp1_colors[0] = self.palette.obp1[usize::from(data & 0x03)];
+        self.obp1_colors[1] = self.palette.obp1[usize::from((data >> 2) & 0x03)];
+        self.obp1_colors[2] = self.palette.obp1[usize::from((data >> 4) & 0x03)];
+        self.obp1_colors[3] = self.palette.obp1[usize::from((data >> 6) & 0x03)];
+    }
+
+    pub fn set_layer_visible(self: &mut Self, layer: Layer, visible: bool) {
+        self.hidden_layers[layer as usize] = !visible;
+    }
+
+    pub fn is_layer_visible(self: &Self, layer: Layer) -> bool {
+        return !self.hidden_layers[layer as usize];
     }
 
     // When bit 0 is cleared, the background and window become white (disabled) and
@@ -340,6 +446,14 @@ impl GpuMemory {
             && (self.wy <= 143);
     }
 
+    // The window can only show up on lines after WY was equal to LY at the start of a line
+    // https://gbdev.io/pandocs/Scrolling.html#window
+    pub fn check_window_y(self: &mut Self) {
+        if self.ly == self.wy {
+            self.window_y_triggered = true;
+        }
+    }
+
     /* Just to make some things cleaner elsewhere */
     pub fn ly(self: &Self) -> usize {
         return self.ly as usize;
@@ -2,6 +2,7 @@ use super::oam_search::OamSearch;
 use super::vblank::VBlank;
 use super::ppu::{PpuState, MODE_OSEARCH, MODE_VBLANK};
 use super::*;
+use crate::state::{StateReader, StateWriter};
 
 // mode 0
 pub struct HBlank {
@@ -17,6 +18,18 @@ impl HBlank {
         });
     }
 
+    pub fn save_state(self: &Self, state: &mut StateWriter) {
+        state.write_usize(self.cycles_counter);
+        state.write_usize(self.cycles_to_run);
+    }
+
+    pub fn load_state(state: &mut StateReader) -> PpuState {
+        return PpuState::HBlank(HBlank {
+            cycles_counter: state.read_usize(),
+            cycles_to_run: state.read_usize(),
+        });
+    }
+
     // HBlank may go to either Itself, OamSearch, or VBlank
     fn next(self: Self, gpu_mem: &mut GpuMemory) -> PpuState {
         if self.cycles_counter < self.cycles_to_run {
@@ -24,16 +37,12 @@ impl HBlank {
             // a scanline takes as picturegeneration takes a variable number of cycles
             return PpuState::HBlank(self);
         } else {
-            // Do here because https://gbdev.io/pandocs/Scrolling.html#window
-            if gpu_mem.is_window_enabled() && gpu_mem.is_window_visible() {
-                gpu_mem.window_line_counter += 1;
-            }
-
             gpu_mem.set_ly(gpu_mem.ly + 1);
             gpu_mem.sprite_list.clear(); // Moving to start of next scanline, so new search will be done
 
             if gpu_mem.ly < 144 {
                 gpu_mem.set_stat_mode(MODE_OSEARCH);
+                gpu_mem.check_window_y();
                 return OamSearch::new();
             } else {
                 gpu_mem.set_stat_mode(MODE_VBLANK);
@@ -0,0 +1,104 @@
+/*
+    Effects that make the picture look more like it does on a real dmg screen.
+    Like the scalers these only change what gets shown, never the framebuffer.
+
+    Frame blending
+        The dmg lcd is slow to change, so a pixel that is only drawn every other
+        frame looks see through instead of flickering. Lots of games depend on this,
+        shooters flicker sprites when there are more than 10 on a line and some
+        games flicker on purpose to get extra shades. Averaging each frame with the
+        one before gets pretty close.
+
+    Screen simulation
+        Every pixel becomes a LCD_SCALE x LCD_SCALE square with a slightly darker
+        line along its right and bottom edge for the gaps between the pixels,
+        and each pixel bleeds a little into the ones around it.
+*/
+
+use super::gpu_memory::BYTES_PER_PIXEL;
+use super::viewer::Image;
+
+pub const LCD_SCALE: usize = 3;
+const GRID_BRIGHTNESS: u32 = 3; // Out of 4, for the lines between pixels
+const BLEED: u32 = 32; // Out of 256, how much of the pixels around each one shows in it
+
+// Averages each byte (the alpha is opaque in both so it stays the same)
+pub fn blend_frames(previous: &[u8], current: &[u8]) -> Vec<u8> {
+    return previous
+        .iter()
+        .zip(current.iter())
+        .map(|(a, b)| ((*a as u16 + *b as u16) / 2) as u8)
+        .collect();
+}
+
+pub fn simulate(image: &Image) -> Image {
+    let mut out = Image::new(image.width * LCD_SCALE, image.height * LCD_SCALE);
+    for y in 0..image.height {
+        for x in 0..image.width {
+            let color = bleed(image, x, y);
+            let mut line = color;
+            for channel in 0..3 {
+                line[channel] = ((color[channel] as u32 * GRID_BRIGHTNESS) / 4) as u8;
+            }
+
+            for dy in 0..LCD_SCALE {
+                for dx in 0..LCD_SCALE {
+                    let is_line = dx == LCD_SCALE - 1 || dy == LCD_SCALE - 1;
+                    let pixel = if is_line { line } else { color };
+                    out.set_pixel((x * LCD_SCALE) + dx, (y * LCD_SCALE) + dy, pixel);
+                }
+            }
+        }
+    }
+    return out;
+}
+
+// The pixel mixed with a little of the 4 next to it (the edges use themselves)
+fn bleed(image: &Image, x: usize, y: usize) -> [u8; 4] {
+    let get = |x: usize, y: usize| {
+        let start = ((y * image.width) + x) * BYTES_PER_PIXEL;
+        &image.pixels[start..start + BYTES_PER_PIXEL]
+    };
+    let neighbours = [
+        get(x.saturating_sub(1), y),
+        get(std::cmp::min(x + 1, image.width - 1), y),
+        get(x, y.saturating_sub(1)),
+        get(x, std::cmp::min(y + 1, image.height - 1)),
+    ];
+    let center = get(x, y);
+    let mut color = [0; 4];
+    for channel in 0..4 {
+        let around: u32 = neighbours.iter().map(|n| n[channel] as u32).sum::<u32>() / 4;
+        color[channel] =
+            (((center[channel] as u32 * (256 - BLEED)) + (around * BLEED)) / 256) as u8;
+    }
+    return color;
+}
+
+#[test]
+fn test_blend_frames() {
+    let previous = [0x00, 0xFF, 0x10, 0xFF];
+    let current = [0xFF, 0xFF, 0x20, 0xFF];
+    assert_eq!(
+        blend_frames(&previous, &current),
+        vec![0x7F, 0xFF, 0x18, 0xFF]
+    );
+}
+
+#[test]
+fn test_lcd_simulation() {
+    let mut image = Image::new(2, 1);
+    image.set_pixel(0, 0, [0xFF, 0xFF, 0xFF, 0xFF]);
+    image.set_pixel(1, 0, [0x00, 0x00, 0x00, 0xFF]);
+    let out = simulate(&image);
+    assert_eq!((out.width, out.height), (6, 3));
+
+    let pixel = |x: usize, y: usize| out.pixels[((y * out.width) + x) * BYTES_PER_PIXEL];
+    // White with a bit of the black next to it, and a darker line on the edges
+    assert_eq!(pixel(0, 0), 0xF7);
+    assert_eq!(pixel(2, 0), 0xB9);
+    assert_eq!(pixel(0, 2), 0xB9);
+    // Black with a bit of the white
+    assert_eq!(pixel(3, 0), 0x07);
+    assert_eq!(out.pixels[3], 0xFF); // Alpha
+}
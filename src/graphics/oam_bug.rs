@@ -0,0 +1,135 @@
+/*
+    OAM corruption bug (DMG only)
+    While the ppu is in mode 2 it reads OAM one row (8 bytes) at a time. If the cpu
+    puts an address between 0xFE00 and 0xFEFF on the bus at the same time, whether
+    by reading, writing, or by the 16 bit inc/dec unit (INC rr, DEC rr, HL+, HL-,
+    PUSH, POP...), the row the ppu is reading gets mangled with the rows before it.
+    The first row is never corrupted.
+
+    Words are 16 bits, 4 per row. In the patterns below
+        a = first word of the row being read by the ppu
+        b = first word of the row before it
+        c = third word of the row before it
+    https://gbdev.io/pandocs/OAM_Corruption_Bug.html
+*/
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum OamBug {
+    Read,
+    Write,
+    ReadIncDec, // A read and an inc/dec on the same cycle (LD A, (HL+) and POP)
+}
+
+const ROW_BYTES: usize = 8;
+pub const OAM_ROWS: usize = 20;
+
+fn word(oam: &[u8; 160], row: usize, index: usize) -> u16 {
+    let at = (row * ROW_BYTES) + (index * 2);
+    return u16::from_le_bytes([oam[at], oam[at + 1]]);
+}
+
+fn set_word(oam: &mut [u8; 160], row: usize, index: usize, val: u16) {
+    let at = (row * ROW_BYTES) + (index * 2);
+    oam[at..at + 2].copy_from_slice(&val.to_le_bytes());
+}
+
+// The last 3 words of a row always come from the row before it
+fn copy_row_tail(oam: &mut [u8; 160], from: usize, to: usize) {
+    let src = (from * ROW_BYTES) + 2;
+    let dst = (to * ROW_BYTES) + 2;
+    oam.copy_within(src..src + 6, dst);
+}
+
+fn copy_row(oam: &mut [u8; 160], from: usize, to: usize) {
+    oam.copy_within(from * ROW_BYTES..(from + 1) * ROW_BYTES, to * ROW_BYTES);
+}
+
+// row is the row the ppu is reading at the time (0 - 19)
+pub fn corrupt(oam: &mut [u8; 160], row: usize, kind: OamBug) {
+    if row == 0 || row >= OAM_ROWS {
+        return;
+    }
+
+    match kind {
+        OamBug::Write => {
+            let (a, b, c) = (
+                word(oam, row, 0),
+                word(oam, row - 1, 0),
+                word(oam, row - 1, 2),
+            );
+            set_word(oam, row, 0, ((a ^ c) & (b ^ c)) ^ c);
+            copy_row_tail(oam, row - 1, row);
+        }
+        OamBug::Read => {
+            let (a, b, c) = (
+                word(oam, row, 0),
+                word(oam, row - 1, 0),
+                word(oam, row - 1, 2),
+            );
+            set_word(oam, row, 0, b | (a & c));
+            copy_row_tail(oam, row - 1, row);
+        }
+        OamBug::ReadIncDec => {
+            // Only happens when the row isnt one of the first four or the last one.
+            // Here a is two rows back, b is the row before, c is the current row and
+            // d is the third word two rows back
+            if row >= 4 && row < OAM_ROWS - 1 {
+                let a = word(oam, row - 2, 0);
+                let b = word(oam, row - 1, 0);
+                let c = word(oam, row, 0);
+                let d = word(oam, row - 2, 2);
+                set_word(oam, row - 1, 0, (b & (a | c | d)) | (a & c & d));
+                copy_row(oam, row - 1, row - 2);
+                copy_row(oam, row - 1, row);
+            }
+            corrupt(oam, row, OamBug::Read);
+        }
+    }
+}
+
+#[cfg(test)]
+fn test_oam() -> [u8; 160] {
+    let mut oam = [0; 160];
+    for (i, byte) in oam.iter_mut().enumerate() {
+        *byte = i as u8;
+    }
+    return oam;
+}
+
+#[test]
+fn test_oam_bug_write_read() {
+    let mut oam = test_oam();
+    corrupt(&mut oam, 0, OamBug::Write);
+    assert_eq!(oam, test_oam());
+
+    // a = 0x1110, b = 0x0908, c = 0x0D0C
+    corrupt(&mut oam, 2, OamBug::Write);
+    assert_eq!(oam[16..18], [0x08, 0x09]);
+    assert_eq!(oam[18..24], oam[10..16]);
+    assert_eq!(oam[8..16], test_oam()[8..16]);
+
+    // a = 0xFFFF, b = 0x0908, c = 0x0D0C
+    let mut oam = test_oam();
+    oam[16..18].copy_from_slice(&[0xFF, 0xFF]);
+    corrupt(&mut oam, 2, OamBug::Read);
+    assert_eq!(oam[16..18], [0x0C, 0x0D]);
+    assert_eq!(oam[18..24], oam[10..16]);
+}
+
+#[test]
+fn test_oam_bug_read_inc_dec() {
+    // Too close to the start only does the read corruption
+    let mut oam = test_oam();
+    let mut read = test_oam();
+    corrupt(&mut oam, 3, OamBug::ReadIncDec);
+    corrupt(&mut read, 3, OamBug::Read);
+    assert_eq!(oam, read);
+
+    // a = 0x3130, b = 0x3938, c = 0x4140, d = 0x3534
+    let mut oam = test_oam();
+    corrupt(&mut oam, 8, OamBug::ReadIncDec);
+    assert_eq!(oam[56..58], [0x30, 0x31]);
+    assert_eq!(oam[48..56], oam[56..64]);
+    assert_eq!(oam[64..66], [0x30, 0x31]); // Then the read on the copied row
+    assert_eq!(oam[66..72], oam[58..64]);
+}
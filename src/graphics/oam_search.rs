@@ -1,6 +1,7 @@
 use super::picture_generation::PictureGeneration;
 use super::ppu::{PpuState, MODE_PICTGEN};
 use super::*;
+use crate::state::{StateReader, StateWriter};
 
 // mode 2
 pub struct OamSearch {
@@ -19,16 +20,27 @@ impl OamSearch {
         return PpuState::OamSearch(OamSearch { cycles_counter: 0 });
     }
 
+    pub fn save_state(self: &Self, state: &mut StateWriter) {
+        state.write_usize(self.cycles_counter);
+    }
+
+    pub fn load_state(state: &mut StateReader) -> PpuState {
+        return PpuState::OamSearch(OamSearch {
+            cycles_counter: state.read_usize(),
+        });
+    }
+
+    // The ppu reads one 8 byte row of oam every 4 cycles
+    pub fn current_row(self: &Self) -> usize {
+        return self.cycles_counter / 4;
+    }
+
     // oamsearch may return itself or picturegeneration
     fn next(self: Self, gpu_mem: &mut GpuMemory) -> PpuState {
         if self.cycles_counter < OamSearch::MAX_CYCLES {
             return PpuState::OamSearch(self);
         } else {
             gpu_mem.set_stat_mode(MODE_PICTGEN);
-
-            // https://gbdev.io/pandocs/pixel_fifo.html#mode-3-operation
-            gpu_mem.bg_pixel_fifo.clear();
-
             return PpuState::PictureGeneration(PictureGeneration::new());
         }
     }
@@ -148,4 +160,27 @@ impl Sprite {
             height: sprite_height, // Dont actually care about this
         };
     }
+
+    pub fn save_state(self: &Self, state: &mut StateWriter) {
+        state.write_bytes(&[self.ypos, self.xpos, self.tile_index, self.height]);
+        state.write_bool(self.bgw_ontop);
+        state.write_bool(self.flip_y);
+        state.write_bool(self.flip_x);
+        state.write_bool(self.palette_no);
+    }
+
+    pub fn load_state(state: &mut StateReader) -> Sprite {
+        let mut bytes = [0; 4];
+        state.read_bytes(&mut bytes);
+        return Sprite {
+            ypos: bytes[0],
+            xpos: bytes[1],
+            tile_index: bytes[2],
+            height: bytes[3],
+            bgw_ontop: state.read_bool(),
+            flip_y: state.read_bool(),
+            flip_x: state.read_bool(),
+            palette_no: state.read_bool(),
+        };
+    }
 }
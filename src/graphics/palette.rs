@@ -0,0 +1,191 @@
+/*
+    The colors the 4 shades are shown in. The background and each of the two sprite
+    palettes can have their own colors, which the palette registers (BGP, OBP0, OBP1)
+    then pick from. Colors are stored BGRA like the framebuffer (See COLORS in
+    gpu_memory.rs)
+
+    Palettes can be one of the presets below or loaded from a file, either
+        - A list of hex colors, one per line (4 for everything or 12 for the
+          background, OBP0 and OBP1 in that order). Lines starting with ; or //
+          are comments
+        - A JASC .pal file (the format Paint Shop Pro, Aseprite, and many other
+          emulators use) with 4 or 12 colors
+    Colors go from lightest to darkest.
+*/
+
+use super::gpu_memory::COLORS;
+use std::fs;
+
+pub type Colors = [[u8; 4]; 4];
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Palette {
+    pub bg: Colors,
+    pub obp0: Colors,
+    pub obp1: Colors,
+}
+
+// #9BBC0F, #8BAC0F, #306230, #0F380F
+const DMG_GREEN: Colors = [
+    [0x0F, 0xBC, 0x9B, 0xFF],
+    [0x0F, 0xAC, 0x8B, 0xFF],
+    [0x30, 0x62, 0x30, 0xFF],
+    [0x0F, 0x38, 0x0F, 0xFF],
+];
+
+// #C4CFA1, #8B956D, #4D533C, #1F1F1F
+const POCKET: Colors = [
+    [0xA1, 0xCF, 0xC4, 0xFF],
+    [0x6D, 0x95, 0x8B, 0xFF],
+    [0x3C, 0x53, 0x4D, 0xFF],
+    [0x1F, 0x1F, 0x1F, 0xFF],
+];
+
+// #00B581, #009A71, #00694A, #004F3B
+const LIGHT: Colors = [
+    [0x81, 0xB5, 0x00, 0xFF],
+    [0x71, 0x9A, 0x00, 0xFF],
+    [0x4A, 0x69, 0x00, 0xFF],
+    [0x3B, 0x4F, 0x00, 0xFF],
+];
+
+pub const PRESETS: [(&str, Colors); 4] = [
+    ("default", COLORS),
+    ("dmg", DMG_GREEN),
+    ("pocket", POCKET),
+    ("light", LIGHT),
+];
+
+impl Palette {
+    // The same colors for the background and sprites
+    pub fn uniform(colors: Colors) -> Palette {
+        return Palette {
+            bg: colors,
+            obp0: colors,
+            obp1: colors,
+        };
+    }
+
+    pub fn preset(name: &str) -> Option<Palette> {
+        let name = name.to_lowercase();
+        return PRESETS
+            .iter()
+            .find(|(preset, _)| *preset == name)
+            .map(|(_, colors)| Palette::uniform(*colors));
+    }
+
+    pub fn load(path: &str) -> Result<Palette, String> {
+        return match fs::read_to_string(path) {
+            Ok(contents) => Palette::parse(&contents).map_err(|e| format!("{}: {}", path, e)),
+            Err(e) => Err(format!("Couldnt read palette {}: {}", path, e)),
+        };
+    }
+
+    pub fn parse(contents: &str) -> Result<Palette, String> {
+        let mut lines = contents
+            .lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty() && !line.starts_with(';') && !line.starts_with("//"));
+
+        let mut colors = Vec::new();
+        if contents.trim_start().starts_with("JASC-PAL") {
+            lines.next(); // JASC-PAL
+            lines.next(); // Version (0100)
+            let count = lines.next().unwrap_or("");
+            for line in lines {
+                colors.push(parse_rgb(line)?);
+            }
+            if count.parse::<usize>() != Ok(colors.len()) {
+                return Err(format!(
+                    "Says it has {} colors but has {}",
+                    count,
+                    colors.len()
+                ));
+            }
+        } else {
+            for line in lines {
+                colors.push(parse_color(line)?);
+            }
+        }
+        return Palette::from_colors(&colors);
+    }
+
+    // 4 colors for everything, or 12 for the background, OBP0 and OBP1
+    pub fn from_colors(colors: &[[u8; 4]]) -> Result<Palette, String> {
+        let group = |start: usize| {
+            [
+                colors[start],
+                colors[start + 1],
+                colors[start + 2],
+                colors[start + 3],
+            ]
+        };
+        return match colors.len() {
+            4 => Ok(Palette::uniform(group(0))),
+            12 => Ok(Palette {
+                bg: group(0),
+                obp0: group(4),
+                obp1: group(8),
+            }),
+            n => Err(format!("A palette needs 4 or 12 colors, got {}", n)),
+        };
+    }
+}
+
+// Colors are written as #RRGGBB but stored the same way as COLORS (BGRA)
+pub fn parse_color(color: &str) -> Result<[u8; 4], String> {
+    let hex = color.trim().trim_start_matches('#');
+    if hex.len() != 6 {
+        return Err(format!("Expected a color like #RRGGBB, got: {}", color));
+    }
+    return match u32::from_str_radix(hex, 16) {
+        Ok(rgb) => Ok([rgb as u8, (rgb >> 8) as u8, (rgb >> 16) as u8, 0xFF]),
+        Err(_) => Err(format!("Expected a color like #RRGGBB, got: {}", color)),
+    };
+}
+
+// JASC colors are "R G B" in decimal
+fn parse_rgb(line: &str) -> Result<[u8; 4], String> {
+    let parts: Vec<u8> = line
+        .split_whitespace()
+        .map(|part| part.parse::<u8>())
+        .collect::<Result<Vec<u8>, _>>()
+        .map_err(|_| format!("Expected a color like 255 255 255, got: {}", line))?;
+    return match parts[..] {
+        [r, g, b] => Ok([b, g, r, 0xFF]),
+        _ => Err(format!("Expected a color like 255 255 255, got: {}", line)),
+    };
+}
+
+#[test]
+fn test_palette_presets() {
+    assert_eq!(Palette::preset("DMG").unwrap().bg, DMG_GREEN);
+    assert_eq!(Palette::preset("default").unwrap().obp1, COLORS);
+    assert_eq!(Palette::preset("gbc"), None);
+    assert_eq!(parse_color("#9BBC0F").unwrap(), DMG_GREEN[0]);
+}
+
+#[test]
+fn test_palette_files() {
+    let hex = "; Pocket\n#C4CFA1\n8B956D\n\n#4D533C\n#1F1F1F\n";
+    assert_eq!(Palette::parse(hex).unwrap(), Palette::uniform(POCKET));
+
+    let mut twelve = String::new();
+    for colors in [POCKET, DMG_GREEN, LIGHT] {
+        for c in colors {
+            twelve.push_str(&format!("#{:02X}{:02X}{:02X}\n", c[2], c[1], c[0]));
+        }
+    }
+    let palette = Palette::parse(&twelve).unwrap();
+    assert_eq!(
+        (palette.bg, palette.obp0, palette.obp1),
+        (POCKET, DMG_GREEN, LIGHT)
+    );
+
+    let jasc = "JASC-PAL\r\n0100\r\n4\r\n155 188 15\r\n139 172 15\r\n48 98 48\r\n15 56 15\r\n";
+    assert_eq!(Palette::parse(jasc).unwrap(), Palette::uniform(DMG_GREEN));
+
+    assert!(Palette::parse("#FFFFFF\n#000000").is_err());
+    assert!(Palette::parse("JASC-PAL\n0100\n5\n0 0 0\n0 0 0\n0 0 0\n0 0 0\n").is_err());
+    assert!(Palette::parse("JASC-PAL\n0100\n4\n0 0 0\n0 0 0\n0 0 0\n0 0 256\n").is_err());
+}
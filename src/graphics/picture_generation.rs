@@ -3,14 +3,13 @@
     The two fifos are only mixed when popping items
     Sprites take priority unless transparent (color 0)
     fifos are only manipulated during mode 3
-    the pixel fetcher makes sure each fifo has at least 8 pixels
 
     pixels have three properties for dmg (cgb has a fourth)
         color between 0 and 3
         palette between 0 and 7 only for sprites
         background priority: value of the OBJ-to-BG Priority bit
 
-    https://gbdev.io/pandocs/pixel_fifo.html#get-tile <--- Continue from here
+    https://gbdev.io/pandocs/pixel_fifo.html
 */
 
 /*
@@ -26,380 +25,511 @@
 /*
     Pixel Fetcher
     fetches a row of 8 background or window pixels and queues them
-    to be mixed with sprite pixels. There are 5 steps
+    to be mixed with sprite pixels. There are 4 steps
         1. Get Tile (2 Cycles)
         2. Get Tile Data Low (2 Cycles)
         3. Get Tile Data High (2 Cycles)
-        4. Sleep (2 Cycles)
-        5. Push (1 Cycle each time until complete)
+        4. Push (1 Cycle each time until the background fifo is empty)
+
+    On each dot during mode 3, either the PPU outputs a pixel or something is stalling
+    the fifos. Mode 3 is 172 dots at its shortest (160 pixels plus two tile fetches at
+    the start of the line, the first one gets thrown away) and gets longer from:
+        - SCX % 8 pixels thrown away at the start of the line (1 dot each)
+        - The window starting, which clears the fifo and restarts the fetcher (6 dots)
+        - Each sprite fetch (6 dots), plus waiting for the background fetcher to get
+          far enough into its current fetch (0 - 5 dots). This is where the 6 - 11 dot
+          sprite penalty comes from
+    HBlank takes whatever is left of the 456 dot scanline.
+    https://gbdev.io/pandocs/Rendering.html#mode-3-length
 */
-// On each dot during mode 3, either the PPU outputs a pixel or the fetcher is stalling the FIFOs
-use super::oam_search::OamSearch;
 use super::hblank::HBlank;
+use super::oam_search::OamSearch;
 use super::ppu::{PpuState, MODE_HBLANK};
 use super::*;
+use crate::state::{StateReader, StateWriter};
+use std::collections::VecDeque;
 
 // mode 3
 pub struct PictureGeneration {
-    cycles_counter: usize,
-    fifo_state: FifoState,
-    fetch_x: usize,             // x tile position in map
-    byte_index: u8,             // Index with the tile we want
-    bgw_lo: u8,                 // Lower byte of the background/window tile data
-    bgw_hi: u8,                 // Upper byte of the background/window tile data
-    scanline_pos: u8,           // Where in the scanline we are
-    push_x: u8,                 // What pixel is to be pushed to the screen
-    discard_pixels: u8,         // Number of pixels that have been discarded so far
-    spr_indicies: Vec<usize>,   // Which sprites are to be displayed
-    spr_data_lo: Vec<u8>,       // Lower byte of the sprite tile data
-    spr_data_hi: Vec<u8>,       // Upper byte of the sprite tile data
-    scx_lo: u8,                 // calculated at beggining of scanline and decides the number of pixels to discard
-    scx_fifo: usize,            // calculated at beggining of fetch
-    scy_fifo: usize,            // calculated at beggining of fetch
-    map_addr: u16,              // calculated at beggining of fetch
-    big_spr: bool,              // calculated at beggining of fetch
-    bgw_enable: bool,           // calculated at beggining of fetch
-    spr_enable: bool,           // Are sprites being rendered?
-    window_y_trigger: bool,     // Window is both enabled and visible
+    cycles_counter: usize,           // Dots spent in mode 3 so far
+    fetch_state: FetchState,         // What the background/window fetcher is doing
+    fetch_dots: usize,               // Dots spent on the current background/window fetch
+    fetch_x: usize,                  // x tile position in map (or in the window)
+    first_fetch: bool,               // The first fetch of the line gets thrown away
+    byte_index: u8,                  // Index with the tile we want
+    bgw_lo: u8,                      // Lower byte of the background/window tile data
+    bgw_hi: u8,                      // Upper byte of the background/window tile data
+    bg_fifo: VecDeque<u8>,           // Color indices (0 - 3) of background/window pixels
+    started: bool,                   // Whether the first dot of the line has happened yet
+    lx: i16,                         // x of the next pixel out of the fifo (< 0 is thrown away)
+    window_active: bool,             // The fetcher is fetching window tiles
+    window_drawn: bool,              // The window started at some point on this line
+    spr_waiting: bool,               // Sprite found, waiting on the background fetch
+    spr_fetch_dots: Option<usize>,   // Dots spent fetching a sprite
+    spr_fetched: [bool; 10],         // Which sprites in sprite_list were fetched
+    spr_fifo: VecDeque<SpritePixel>, // Sprite pixels, lined up with the background fifo output
 }
 
-pub enum FifoState {
+#[derive(PartialEq)]
+pub enum FetchState {
     GetTile,
     GetTileDataLow,
     GetTileDataHigh,
-    Sleep,
     Push,
-    None,
+}
+
+// A pixel in the sprite fifo. Color 0 is transparent
+#[derive(Clone, Copy)]
+struct SpritePixel {
+    color: u8,        // Color index between 0 and 3
+    palette_no: bool, // OBP0 or OBP1
+    bgw_ontop: bool,  // Background and window colors 1 - 3 are drawn over the sprite
+}
+
+impl SpritePixel {
+    const TRANSPARENT: SpritePixel = SpritePixel {
+        color: 0,
+        palette_no: false,
+        bgw_ontop: false,
+    };
 }
 
 impl PictureGeneration {
     const SCANLINE_CYCLES: usize = 456;
-    const FIFO_MAX_PIXELS: usize = 16;
-    const FIFO_MIN_PIXELS: usize = 8;
+    const FETCH_STEP_CYCLES: usize = 2;
+    const SPRITE_FETCH_CYCLES: usize = 6;
+    const SPRITE_WAIT_CYCLES: usize = 5; // How far the background fetch must be before a sprite fetch
 
     pub fn new() -> PictureGeneration {
         return PictureGeneration {
             cycles_counter: 0,
-            fifo_state: FifoState::GetTile,
+            fetch_state: FetchState::GetTile,
+            fetch_dots: 0,
             fetch_x: 0,
+            first_fetch: true,
             byte_index: 0,
             bgw_lo: 0,
             bgw_hi: 0,
-            scanline_pos: 0,
-            push_x: 0,
-            discard_pixels: 0,
-            spr_indicies: Vec::new(),
-            spr_data_lo: Vec::new(),
-            spr_data_hi: Vec::new(),
-            scx_lo: 0,
-            scx_fifo: 0,
-            scy_fifo: 0,
-            map_addr: 0,
-            big_spr: false,
-            bgw_enable: false,
-            spr_enable: false,
-            window_y_trigger: false,
+            bg_fifo: VecDeque::new(),
+            started: false,
+            lx: 0,
+            window_active: false,
+            window_drawn: false,
+            spr_waiting: false,
+            spr_fetch_dots: None,
+            spr_fetched: [false; 10],
+            spr_fifo: VecDeque::new(),
+        };
+    }
+
+    pub fn save_state(self: &Self, state: &mut StateWriter) {
+        state.write_usize(self.cycles_counter);
+        state.write_u8(match self.fetch_state {
+            FetchState::GetTile => 0,
+            FetchState::GetTileDataLow => 1,
+            FetchState::GetTileDataHigh => 2,
+            FetchState::Push => 3,
+        });
+        state.write_usize(self.fetch_dots);
+        state.write_usize(self.fetch_x);
+        state.write_bool(self.first_fetch);
+        state.write_bytes(&[self.byte_index, self.bgw_lo, self.bgw_hi]);
+        state.write_vec(&self.bg_fifo.iter().copied().collect::<Vec<u8>>());
+        state.write_bool(self.started);
+        state.write_i16(self.lx);
+        state.write_bool(self.window_active);
+        state.write_bool(self.window_drawn);
+        state.write_bool(self.spr_waiting);
+        state.write_bool(self.spr_fetch_dots.is_some());
+        state.write_usize(self.spr_fetch_dots.unwrap_or(0));
+        for fetched in self.spr_fetched {
+            state.write_bool(fetched);
+        }
+        state.write_usize(self.spr_fifo.len());
+        for pixel in self.spr_fifo.iter() {
+            state.write_u8(pixel.color);
+            state.write_bool(pixel.palette_no);
+            state.write_bool(pixel.bgw_ontop);
+        }
+    }
+
+    pub fn load_state(state: &mut StateReader) -> PpuState {
+        let mut pg = PictureGeneration::new();
+        pg.cycles_counter = state.read_usize();
+        pg.fetch_state = match state.read_u8() {
+            0 => FetchState::GetTile,
+            1 => FetchState::GetTileDataLow,
+            2 => FetchState::GetTileDataHigh,
+            _ => FetchState::Push,
         };
+        pg.fetch_dots = state.read_usize();
+        pg.fetch_x = state.read_usize();
+        pg.first_fetch = state.read_bool();
+        let mut bytes = [0; 3];
+        state.read_bytes(&mut bytes);
+        [pg.byte_index, pg.bgw_lo, pg.bgw_hi] = bytes;
+        pg.bg_fifo = state.read_vec().into_iter().collect();
+        pg.started = state.read_bool();
+        pg.lx = state.read_i16();
+        pg.window_active = state.read_bool();
+        pg.window_drawn = state.read_bool();
+        pg.spr_waiting = state.read_bool();
+        let fetching = state.read_bool();
+        let fetch_dots = state.read_usize();
+        pg.spr_fetch_dots = if fetching { Some(fetch_dots) } else { None };
+        for fetched in pg.spr_fetched.iter_mut() {
+            *fetched = state.read_bool();
+        }
+        let pixels = state.read_usize();
+        pg.spr_fifo = (0..pixels)
+            .map(|_| SpritePixel {
+                color: state.read_u8(),
+                palette_no: state.read_bool(),
+                bgw_ontop: state.read_bool(),
+            })
+            .collect();
+        return PpuState::PictureGeneration(pg);
+    }
+
+    fn is_done(self: &Self) -> bool {
+        return self.lx >= NUM_PIXELS_X as i16;
     }
 
     // picturegeneration may return itself or hblank
-    fn next(self: Self, gpu_mem: &mut GpuMemory) -> PpuState {
-        if (self.push_x as u32) < NUM_PIXELS_X {
+    // leftover is the number of dots from this render call that belong to hblank
+    fn next(self: Self, gpu_mem: &mut GpuMemory, leftover: usize) -> PpuState {
+        if !self.is_done() {
             return PpuState::PictureGeneration(self);
         } else {
+            if self.window_drawn {
+                gpu_mem.window_line_counter += 1;
+            } else {
+                gpu_mem.window_wx166 = false;
+            }
             gpu_mem.set_stat_mode(MODE_HBLANK);
             return HBlank::new(
-                PictureGeneration::SCANLINE_CYCLES - OamSearch::MAX_CYCLES - self.cycles_counter,
+                PictureGeneration::SCANLINE_CYCLES
+                    - OamSearch::MAX_CYCLES
+                    - self.cycles_counter
+                    - leftover,
             );
         }
     }
 
     pub fn render(mut self, gpu_mem: &mut GpuMemory, cycles: usize) -> PpuState {
-        if self.cycles_counter == 0 {
-            // first time on this scanline
-            self.scx_lo = gpu_mem.scx % 8;
-            self.window_y_trigger = gpu_mem.is_window_enabled() && gpu_mem.is_window_visible();
+        let mut leftover = cycles;
+        while leftover > 0 && !self.is_done() {
+            self.tick(gpu_mem);
+            leftover -= 1;
         }
-        for _ in 0..cycles {
-            self.cycles_counter += 1;
-            self.do_work(gpu_mem);
+        return self.next(gpu_mem, leftover);
+    }
 
-            if (self.push_x as u32) >= NUM_PIXELS_X {
-                break;
-            }
+    // A single dot of mode 3
+    fn tick(self: &mut Self, gpu_mem: &mut GpuMemory) {
+        if !self.started {
+            // Lower 3 bits of scx decide how many pixels get thrown away at the start of the line
+            self.started = true;
+            self.lx = -((gpu_mem.scx % 8) as i16);
         }
-        return self.next(gpu_mem);
-    }
+        self.cycles_counter += 1;
 
-    pub fn do_work(self: &mut Self, gpu_mem: &mut GpuMemory) {
-        // Attempt every other dot
-        if (self.cycles_counter % 2) == 0 {
-            self.fifo_state = match self.fifo_state {
-                FifoState::GetTile => self.get_tile_num(gpu_mem),
-                FifoState::GetTileDataLow => self.get_tile_data_low(gpu_mem),
-                FifoState::GetTileDataHigh => self.get_tile_data_high(gpu_mem),
-                FifoState::Sleep => self.sleep(gpu_mem),
-                FifoState::Push => self.push(gpu_mem),
-                FifoState::None => panic!("Fifo should not be in None State"),
-            };
+        // While a sprite is being fetched the background fetcher and the fifo are stuck
+        if let Some(dots) = self.spr_fetch_dots {
+            if dots + 1 < PictureGeneration::SPRITE_FETCH_CYCLES {
+                self.spr_fetch_dots = Some(dots + 1);
+                return;
+            }
+            self.spr_fetch_dots = None;
+            self.fetch_sprite_row(gpu_mem);
         } else {
-            // Attempt every dot if in the state
-            if let FifoState::Push = self.fifo_state {
-                // Might do nothing so then we just stay in push which is fine
-                self.fifo_state = self.push(gpu_mem);
+            self.fetcher_tick(gpu_mem);
+
+            if self.spr_waiting {
+                if self.fetch_dots >= PictureGeneration::SPRITE_WAIT_CYCLES {
+                    self.spr_waiting = false;
+                    self.spr_fetch_dots = Some(0);
+                }
+                return;
             }
         }
 
-        // Always attempted
         self.pop_fifo(gpu_mem);
     }
 
-    // lcdc, scx, and scy should only be sampled each time a tile is fetched
-    // Lower 3 bits of scx can only change at start of each scanline
-    pub fn get_tile_num(self: &mut Self, gpu_mem: &mut GpuMemory) -> FifoState {
-        let curr_tile;
-        let map_start;
+    fn fetcher_tick(self: &mut Self, gpu_mem: &mut GpuMemory) {
+        self.fetch_dots += 1;
 
-        // Basically storing the lcdc state at tile fetch for the remainder of the fifo
-        self.bgw_enable = gpu_mem.is_bgw_enabled();
-        self.big_spr = gpu_mem.is_big_sprite();
-        self.scx_fifo = gpu_mem.scx();
-        self.scy_fifo = gpu_mem.scy();
-        self.spr_enable = gpu_mem.is_spr_enabled();
-
-        self.spr_indicies.clear();
+        match self.fetch_state {
+            FetchState::GetTile if self.fetch_dots == PictureGeneration::FETCH_STEP_CYCLES => {
+                self.get_tile_num(gpu_mem);
+                self.fetch_state = FetchState::GetTileDataLow;
+            }
+            FetchState::GetTileDataLow
+                if self.fetch_dots == 2 * PictureGeneration::FETCH_STEP_CYCLES =>
+            {
+                self.bgw_lo = gpu_mem.vram[self.tile_data_index(gpu_mem)];
+                self.fetch_state = FetchState::GetTileDataHigh;
+            }
+            FetchState::GetTileDataHigh
+                if self.fetch_dots == 3 * PictureGeneration::FETCH_STEP_CYCLES =>
+            {
+                self.bgw_hi = gpu_mem.vram[self.tile_data_index(gpu_mem) + 1];
+                if self.first_fetch {
+                    // Nothing gets pushed, the fetcher just starts over on the same tile
+                    self.first_fetch = false;
+                    self.restart_fetch();
+                } else {
+                    self.fetch_state = FetchState::Push;
+                }
+            }
+            FetchState::Push => {
+                // The background fifo only takes new pixels once its empty
+                if self.bg_fifo.is_empty() {
+                    for shift in (0..=7).rev() {
+                        let p1 = (self.bgw_hi >> shift) & 0x01;
+                        let p0 = (self.bgw_lo >> shift) & 0x01;
+                        self.bg_fifo.push_back((p1 << 1) | p0);
+                    }
+                    self.fetch_x += 1;
+                    self.restart_fetch();
+                }
+            }
+            _ => {}
+        }
+    }
 
-        // curr_tile will be between 0 and 1023(0x3FF) inclusive
-        if self.bgw_enable {
-            curr_tile = ((self.fetch_x + (self.scx_fifo / 8)) & 0x1F)
-                + (32 * (((gpu_mem.ly() + self.scy_fifo) & 0xFF) / 8));
-            map_start = (gpu_mem.get_bg_tile_map().0 - VRAM_START) as usize;
+    fn restart_fetch(self: &mut Self) {
+        self.fetch_state = FetchState::GetTile;
+        self.fetch_dots = 0;
+    }
 
-            self.byte_index = gpu_mem.vram[map_start + curr_tile];
+    // Registers are read on the dot they get used, so writes during mode 3 show up
+    // from the next fetch on. The tile maps, scx, scy, and window enable are read here
+    fn get_tile_num(self: &mut Self, gpu_mem: &mut GpuMemory) {
+        // Turning the window off mid line goes back to background tiles, but the
+        // fetcher keeps counting from where the window left off
+        if self.window_active && !gpu_mem.is_window_enabled() {
+            self.window_active = false;
         }
 
-        if self.bgw_enable && self.window_y_trigger {
-            self.find_window_tile_num(gpu_mem);
-        }
+        // curr_tile will be between 0 and 1023(0x3FF) inclusive
+        let (map_start, curr_tile) = if self.window_active {
+            let map_start = gpu_mem.get_window_tile_map().0;
+            let row = gpu_mem.window_line_counter as usize / 8;
+            (map_start, (32 * row) + (self.fetch_x & 0x1F))
+        } else {
+            let map_start = gpu_mem.get_bg_tile_map().0;
+            let row = ((gpu_mem.ly() + gpu_mem.scy()) & 0xFF) / 8;
+            (
+                map_start,
+                (32 * row) + ((self.fetch_x + (gpu_mem.scx() / 8)) & 0x1F),
+            )
+        };
 
-        if self.spr_enable && gpu_mem.sprite_list.len() > 0 {
-            self.search_spr_list(gpu_mem);
-        }
+        self.byte_index = gpu_mem.vram[usize::from(map_start - VRAM_START) + curr_tile];
+    }
 
-        self.map_addr = PictureGeneration::calculate_addr(self.byte_index, gpu_mem);
-        self.fetch_x += 1;
-        return FifoState::GetTileDataLow;
+    // Index into vram of the low byte for the current line of the fetched tile.
+    // The addressing mode (bit 4 of lcdc) and scy are read again for both bytes
+    fn tile_data_index(self: &Self, gpu_mem: &GpuMemory) -> usize {
+        let line = if self.window_active {
+            gpu_mem.window_line_counter as usize % 8
+        } else {
+            (gpu_mem.ly() + gpu_mem.scy()) % 8
+        };
+        let addr = PictureGeneration::calculate_addr(self.byte_index, gpu_mem);
+        return usize::from(addr - VRAM_START) + (2 * line);
     }
 
     // refer to https://gbdev.io/pandocs/Scrolling.html#window
-    // at some point in this frame the value of WY was equal to LY (checked at the start of Mode 2 only)
-    // Maybe move the `gpu_mem.ly() >= gpu_mem.wy()` to the beginning of mode 2
-    fn find_window_tile_num(self: &mut Self, gpu_mem: &mut GpuMemory) {
-        let fetcher_pos = (self.fetch_x * 8) + 7;
-        if fetcher_pos >= gpu_mem.wx() {
-            let index = (32 * (gpu_mem.window_line_counter as usize / 8))
-                + (self.fetch_x - (gpu_mem.wx() / 8))
-                + usize::from(gpu_mem.get_window_tile_map().0);
-
-            self.byte_index = gpu_mem.vram[index - usize::from(VRAM_START)];
+    // The window starts once the pixel at WX - 7 is about to be shown, as long as
+    // at some point in this frame the value of WY was equal to LY. Bit 0 of lcdc
+    // only blanks the window, it still gets fetched.
+    // If the window gets turned back on after being turned off on the same line it
+    // only starts again when WX - 7 matches exactly
+    fn window_starts(self: &Self, gpu_mem: &GpuMemory) -> bool {
+        if !gpu_mem.is_window_enabled() || !gpu_mem.window_y_triggered {
+            return false;
         }
-    }
-    /*
-        Sprite X = position on screen + 8. I can either
-        subtract 8 from sprx or add 8 to the comparisons
-
-        The || with xpos + 8 is then because its possible that there are two sprites
-        almost on top of each other but with maybe the last pixel of the second sprite
-        not covered by anything. Its x position would not match up with the xpos of the
-        fetcher since we would have passed it so this makes sure we can still catch it
-    */
-    fn search_spr_list(self: &mut Self, gpu_mem: &mut GpuMemory) {
-        for (i, sprite) in gpu_mem.sprite_list.iter().enumerate() {
-            if sprite.xpos < 168 && sprite.xpos > 0 {
-                // Sprite with xpos >= 168 or x == 0 should be hidden
-                let sprx = usize::from(sprite.xpos + (self.scx_lo));
-                if ((sprx >= (self.fetch_x * 8) + 8) && (sprx < ((self.fetch_x * 8) + 16)))
-                    || ((sprx + 8 >= (self.fetch_x * 8) + 8)
-                        && (sprx + 8 < ((self.fetch_x * 8) + 16)))
-                {
-                    self.spr_indicies.push(i);
-                }
-            }
+        if gpu_mem.window_wx166 && !self.window_drawn {
+            return true;
         }
-    }
 
-    pub fn get_tile_data_low(self: &mut Self, gpu_mem: &mut GpuMemory) -> FifoState {
-        let mut offset = 0;
-        self.spr_data_lo.clear();
+        let wx = gpu_mem.wx as i16;
+        return match self.window_drawn {
+            false => wx <= 166 && self.lx + 7 >= wx,
+            true => self.lx + 7 == wx,
+        };
+    }
 
-        if self.bgw_enable {
-            offset = 2 * ((gpu_mem.ly() + self.scy_fifo) % 8) as u16;
+    // The fifo gets cleared and the fetcher starts over fetching the window tiles
+    fn start_window(self: &mut Self, gpu_mem: &mut GpuMemory) {
+        self.window_active = true;
+        self.bg_fifo.clear();
+        self.fetch_x = 0;
+        self.restart_fetch();
+
+        if gpu_mem.window_wx166 && !self.window_drawn {
+            // Leftover from WX = 166 on the line before, the window covers the whole line
+            gpu_mem.window_wx166 = false;
+        } else if gpu_mem.wx == 166 {
+            // Only the last pixel is left, but the window also spans all of the next line
+            gpu_mem.window_wx166 = true;
+        } else if gpu_mem.wx != 0 {
+            // With WX below 7 the start of the window is off the left of the screen.
+            // WX = 0 is the odd one, the window gets shifted by however many pixels of
+            // scx % 8 are still being thrown away so it stutters as scx changes
+            self.lx = self.lx.min(gpu_mem.wx as i16 - 7);
         }
+        self.window_drawn = true;
+    }
 
-        if self.bgw_enable && self.window_y_trigger {
-            offset = 2 * (gpu_mem.window_line_counter % 8) as u16;
+    // The first sprite in the list (lowest x, then oam order) that hasnt been fetched
+    // and starts at or before the next pixel
+    fn find_sprite(self: &Self, gpu_mem: &GpuMemory) -> Option<usize> {
+        if !gpu_mem.is_spr_enabled() {
+            return None;
         }
-
-        if self.spr_enable && self.spr_indicies.len() > 0 {
-            self.get_spr_tile_data(gpu_mem, 0);
+        for (i, sprite) in gpu_mem.sprite_list.iter().enumerate() {
+            if !self.spr_fetched[i] && (sprite.xpos as i16) - 8 <= self.lx {
+                return Some(i);
+            }
         }
-
-        self.bgw_lo = gpu_mem.vram[usize::from(self.map_addr + offset - VRAM_START)];
-        return FifoState::GetTileDataHigh;
+        return None;
     }
 
-    pub fn get_tile_data_high(self: &mut Self, gpu_mem: &mut GpuMemory) -> FifoState {
-        let mut offset = 0;
-        self.spr_data_hi.clear();
+    // The sprite fetch uses the fetcher so it gets the sprites tile data
+    // the same way the background does, just from the sprite attributes
+    fn fetch_sprite_row(self: &mut Self, gpu_mem: &mut GpuMemory) {
+        let index = match self.find_sprite(gpu_mem) {
+            Some(i) => i,
+            None => return,
+        };
+        self.spr_fetched[index] = true;
 
-        if self.bgw_enable {
-            offset = (2 * ((gpu_mem.ly() + self.scy_fifo) % 8) as u16) + 1;
-        }
+        let spr = &gpu_mem.sprite_list[index];
+        let spr_height = if gpu_mem.is_big_sprite() { 16 } else { 8 };
 
-        if self.bgw_enable && self.window_y_trigger {
-            offset = (2 * (gpu_mem.window_line_counter % 8) as u16) + 1;
-        }
+        // The +16 to ly is because ypos = sprite y position on screen + 16
+        // This is the difference between what line the sprite started to appear
+        // and what line is currently being rendered. Or in other words what line
+        // of the sprite is being rendered
+        let mut y_offset = (gpu_mem.ly as i32 + 16) - (spr.ypos as i32);
 
-        if self.spr_enable && self.spr_indicies.len() > 0 {
-            self.get_spr_tile_data(gpu_mem, 1);
+        // Flip so we need to take the bytes in the reverse order
+        if spr.flip_y {
+            y_offset = (spr_height - 1) - y_offset;
         }
 
-        self.bgw_hi = gpu_mem.vram[usize::from(self.map_addr + offset - VRAM_START)];
-        return FifoState::Sleep;
-    }
-
-    fn get_spr_tile_data(self: &mut Self, gpu_mem: &mut GpuMemory, offset: usize) {
-        let ly = gpu_mem.ly as i32;
-        let spr_height = if self.big_spr { 16 } else { 8 };
-        let mut tile_index;
-
-        for i in &self.spr_indicies {
-            let spr = &gpu_mem.sprite_list[*i];
-
-            // The +16 to ly is because ypos = sprite y position on screen + 16
-            // This is the difference between what line the sprite started to appear
-            // and what line is currently being rendered. Or in other words what line
-            // of the sprite is being rendered
-            let mut y_offset = (ly + 16) - (spr.ypos as i32);
-
-            /*
-                Flip so we need to take the bytes in the reverse order. The current line
-                of the sprite subtracted from the sprite height gets us the lines to
-                rendered in reverse order. (When rendering the first line of sprite: 0,
-                height - 0 will equal the height so that means we display the last line
-                of the sprite.) The -1 is because the offset are 0 indexed but the height
-                is 8 or 16.
-            */
-            if spr.flip_y {
-                y_offset = (spr_height - 1) - y_offset;
-            }
+        let tile_index = if spr_height == 16 {
+            // https://gbdev.io/pandocs/OAM.html#byte-2---tile-index
+            spr.tile_index & 0xFE
+        } else {
+            spr.tile_index
+        };
 
-            tile_index = if spr_height == 16 {
-                // https://gbdev.io/pandocs/OAM.html#byte-2---tile-index
-                spr.tile_index & 0xFE
-            } else {
-                spr.tile_index
-            };
+        // The index is already relative from 0x8000 so no need to subtract 0x8000
+        let index = (((tile_index as i32) * 16) + (y_offset * 2)) as usize;
+        let (mut lo, mut hi) = (gpu_mem.vram[index], gpu_mem.vram[index + 1]);
+        if spr.flip_x {
+            lo = lo.reverse_bits();
+            hi = hi.reverse_bits();
+        }
 
-            let index = ((tile_index as i32) * 16) + (y_offset * 2);
+        // Sprites partially off the left of the screen (or left of a sprite fetched
+        // while pixels are still being thrown away) lose the pixels already passed
+        let skip = (self.lx - ((spr.xpos as i16) - 8)) as usize;
+        while self.spr_fifo.len() < 8 - skip {
+            self.spr_fifo.push_back(SpritePixel::TRANSPARENT);
+        }
 
-            // The index is already relative from 0x8000 so no need to subtract 0x8000
-            if offset == 0 {
-                self.spr_data_lo.push(gpu_mem.vram[index as usize]);
-            } else {
-                self.spr_data_hi.push(gpu_mem.vram[index as usize + offset]);
+        // Pixels already in the fifo came from a sprite fetched earlier, which has priority.
+        // Only the transparent ones can be replaced
+        for i in skip..8 {
+            let p1 = (hi >> (7 - i)) & 0x01;
+            let p0 = (lo >> (7 - i)) & 0x01;
+            let slot = &mut self.spr_fifo[i - skip];
+            if slot.color == 0 {
+                *slot = SpritePixel {
+                    color: (p1 << 1) | p0,
+                    palette_no: spr.palette_no,
+                    bgw_ontop: spr.bgw_ontop,
+                };
             }
         }
     }
 
-    pub fn sleep(self: &mut Self, _gpu_mem: &mut GpuMemory) -> FifoState {
-        return FifoState::Push;
-    }
-
-    pub fn push(self: &mut Self, gpu_mem: &mut GpuMemory) -> FifoState {
-        if gpu_mem.bg_pixel_fifo.len() > 8 {
-            // FIFO full
-            return FifoState::Push;
+    // A sprite pixel wins unless its transparent, or its priority bit is set and
+    // the background isnt color 0. Gives the shade the palette register picks
+    // and the color that shade is shown with
+    fn mix_pixel(gpu_mem: &GpuMemory, bg_col: usize, spr: SpritePixel) -> (u8, [u8; 4]) {
+        // Turning off sprites mid line also hides sprite pixels already in the fifo
+        let spr_col = spr.color as usize;
+        if spr_col == 0 || !gpu_mem.is_spr_enabled() || (spr.bgw_ontop && bg_col != 0) {
+            return (shade(gpu_mem.bgp, bg_col), gpu_mem.bg_colors[bg_col]);
         }
-
-        self.get_color_and_push(gpu_mem);
-
-        return FifoState::GetTile;
+        return if spr.palette_no {
+            (shade(gpu_mem.obp1, spr_col), gpu_mem.obp1_colors[spr_col])
+        } else {
+            (shade(gpu_mem.obp0, spr_col), gpu_mem.obp0_colors[spr_col])
+        };
     }
 
-    // weaves the bits together to form the correct output for graphics
-    fn get_color_and_push(self: &mut Self, gpu_mem: &mut GpuMemory) {
-        for shift in 0..=7 {
-            let p1 = (self.bgw_hi >> (7 - shift)) & 0x01;
-            let p0 = (self.bgw_lo >> (7 - shift)) & 0x01;
-            let bit_col = (p1 << 1 | p0) as usize;
-
-            let bg_color = if self.bgw_enable { bit_col } else { 0 };
-            let mut color = gpu_mem.bg_colors[bg_color];
-            if !self.spr_indicies.is_empty() {
-                color = self.fetch_and_merge(gpu_mem, bg_color)
-            }
-
-            gpu_mem.bg_pixel_fifo.push_back(color);
-            self.scanline_pos += 1;
+    fn pop_fifo(self: &mut Self, gpu_mem: &mut GpuMemory) {
+        if self.bg_fifo.is_empty() {
+            return;
         }
-    }
-
-    fn fetch_and_merge(self: &mut Self, gpu_mem: &mut GpuMemory, bg_col: usize) -> [u8; 4] {
-        let mut spr_scr_xpos;
-        let mut spr;
-        for (list_idx, orig_idx) in self.spr_indicies.iter().enumerate() {
-            spr = &gpu_mem.sprite_list[*orig_idx];
-            spr_scr_xpos = (spr.xpos as i32) - 8 + (self.scx_lo) as i32;
-
-            let mut offset = self.scanline_pos as i32 - spr_scr_xpos;
-            if offset < 0 || offset > 7 {
-                // Current pixel is either past the sprite end: >7
-                // or behind the sprite starting x position: <0
-                continue;
-            }
-
-            if spr.flip_x {
-                offset = 7 - offset;
-            }
 
-            let p1 = (self.spr_data_hi[list_idx] >> (7 - offset)) & 0x01;
-            let p0 = (self.spr_data_lo[list_idx] >> (7 - offset)) & 0x01;
-            let bit_col = (p1 << 1 | p0) as usize;
+        // The fetcher starts over on the window tiles, this dot counts as its first
+        if !self.window_active && self.window_starts(gpu_mem) {
+            self.start_window(gpu_mem);
+            self.fetcher_tick(gpu_mem);
+            return;
+        }
 
-            if (!spr.bgw_ontop || bg_col == 0) && (bit_col != 0) {
-                return if spr.palette_no {
-                    gpu_mem.obp1_colors[bit_col]
-                } else {
-                    gpu_mem.obp0_colors[bit_col]
-                };
+        // A sprite at this pixel stops everything until its been fetched
+        if self.find_sprite(gpu_mem).is_some() {
+            if self.fetch_dots >= PictureGeneration::SPRITE_WAIT_CYCLES {
+                self.spr_fetch_dots = Some(0);
+            } else {
+                self.spr_waiting = true;
             }
+            return;
         }
 
-        return gpu_mem.bg_colors[bg_col]; // All candidate sprite pixels were transparent or out of bounds
-    }
-
-    // Not one of the states with mode 3 but a necessary step in mode 3 I think
-    // Probably do the merging with sprite fifo here
-    fn pop_fifo(self: &mut Self, gpu_mem: &mut GpuMemory) {
-        if gpu_mem.bg_pixel_fifo.len() > PictureGeneration::FIFO_MIN_PIXELS {
-            let pixel = gpu_mem.bg_pixel_fifo.pop_front();
-
-            if let Some(val) = pixel {
-                // Discard scx % 8 pixels at beginning of scanline (calculated at start of scanline)
-                // If window is displaying, then we don't want to discard any pixels
-                if ((self.scx_lo) <= self.discard_pixels) | self.window_y_trigger {
-                    for i in 0..=3 {
-                        gpu_mem.pixels[(usize::from(gpu_mem.ly) * BYTES_PER_ROW)
-                            + (usize::from(self.push_x) * BYTES_PER_PIXEL)
-                            + i] = val[i];
-                    }
-                    self.push_x += 1;
-                } else {
-                    self.discard_pixels += 1;
-                }
+        // Both fifos shift out together
+        let pixel = self.bg_fifo.pop_front().unwrap();
+        let mut spr_pixel = self
+            .spr_fifo
+            .pop_front()
+            .unwrap_or(SpritePixel::TRANSPARENT);
+
+        // Discard scx % 8 pixels at beginning of scanline
+        if self.lx >= 0 {
+            // When bit 0 of lcdc is cleared the background and window are white.
+            // Layers hidden for debugging are mixed as if they were see through
+            let layer = if self.window_active {
+                Layer::Window
+            } else {
+                Layer::Background
+            };
+            let bg_col = if gpu_mem.is_bgw_enabled() && gpu_mem.is_layer_visible(layer) {
+                pixel as usize
+            } else {
+                0
+            };
+            if !gpu_mem.is_layer_visible(Layer::Sprites) {
+                spr_pixel = SpritePixel::TRANSPARENT;
             }
+            let (shade, color) = PictureGeneration::mix_pixel(gpu_mem, bg_col, spr_pixel);
+
+            let index = (usize::from(gpu_mem.ly) * NUM_PIXELS_X as usize) + self.lx as usize;
+            gpu_mem.shades[index] = shade;
+            let start = index * BYTES_PER_PIXEL;
+            gpu_mem.pixels[start..start + BYTES_PER_PIXEL].copy_from_slice(&color);
         }
+        self.lx += 1;
     }
 
     pub fn read_byte(self: &Self, _gpu_mem: &GpuMemory, addr: u16) -> u8 {
@@ -429,3 +559,254 @@ impl PictureGeneration {
         return addr;
     }
 }
+
+// The shade (0 - 3) a palette register (bgp, obp0 or obp1) gives a color index
+fn shade(palette_reg: u8, color: usize) -> u8 {
+    return (palette_reg >> (color * 2)) & 0x03;
+}
+
+#[cfg(test)]
+use super::oam_search::Sprite;
+
+// Runs mode 3 for a line one dot at a time and returns how many dots it took
+#[cfg(test)]
+fn mode3_length(gpu_mem: &mut GpuMemory) -> usize {
+    let mut state = PpuState::PictureGeneration(PictureGeneration::new());
+    let mut dots = 0;
+    while let PpuState::PictureGeneration(pg) = state {
+        state = pg.render(gpu_mem, 1);
+        dots += 1;
+    }
+    return dots;
+}
+
+#[cfg(test)]
+fn test_gpu_mem(sprite_xs: &[u8]) -> GpuMemory {
+    let mut gpu_mem = GpuMemory::new();
+    gpu_mem.lcdc = 0x93; // Ppu, tile data at 0x8000, sprites and background on
+    for x in sprite_xs {
+        gpu_mem
+            .sprite_list
+            .push(super::oam_search::Sprite::new(&[16, *x, 0, 0], 8));
+    }
+    return gpu_mem;
+}
+
+#[test]
+fn test_mode3_length_scx() {
+    assert_eq!(mode3_length(&mut test_gpu_mem(&[])), 172);
+    for scx in 0..=15 {
+        let mut gpu_mem = test_gpu_mem(&[]);
+        gpu_mem.scx = scx;
+        assert_eq!(mode3_length(&mut gpu_mem), 172 + (scx as usize % 8));
+    }
+}
+
+#[test]
+fn test_mode3_length_sprites() {
+    // 6 dots plus however many dots are left until the fetcher is 5 dots into a fetch
+    let penalty = |x: u8| 6 + 5usize.saturating_sub(x as usize % 8);
+    for x in 8..=167 {
+        assert_eq!(
+            mode3_length(&mut test_gpu_mem(&[x])),
+            172 + penalty(x),
+            "x {}",
+            x
+        );
+    }
+
+    // Sprites sharing a tile only wait once
+    assert_eq!(mode3_length(&mut test_gpu_mem(&[8, 8])), 172 + 11 + 6);
+    assert_eq!(mode3_length(&mut test_gpu_mem(&[8, 10])), 172 + 11 + 6);
+    assert_eq!(mode3_length(&mut test_gpu_mem(&[8, 16])), 172 + 11 + 11);
+
+    // Off the right of the screen and disabled sprites cost nothing
+    assert_eq!(mode3_length(&mut test_gpu_mem(&[168])), 172);
+    let mut gpu_mem = test_gpu_mem(&[8, 40]);
+    gpu_mem.lcdc &= !0x02;
+    assert_eq!(mode3_length(&mut gpu_mem), 172);
+}
+
+#[test]
+fn test_mode3_length_window() {
+    let mut gpu_mem = test_gpu_mem(&[]);
+    gpu_mem.lcdc |= 0x20;
+    gpu_mem.wx = 87;
+    assert_eq!(mode3_length(&mut gpu_mem), 172); // WY hasnt matched LY yet
+
+    gpu_mem.window_y_triggered = true;
+    assert_eq!(mode3_length(&mut gpu_mem), 178);
+    assert_eq!(gpu_mem.window_line_counter, 1);
+
+    gpu_mem.wx = 7;
+    assert_eq!(mode3_length(&mut gpu_mem), 178);
+
+    gpu_mem.wx = 167;
+    assert_eq!(mode3_length(&mut gpu_mem), 172);
+}
+
+// Fills the first line of a tile with lo and hi. Tile 0 is what the whole background uses
+#[cfg(test)]
+fn set_tile_row(gpu_mem: &mut GpuMemory, tile: usize, lo: u8, hi: u8) {
+    gpu_mem.vram[tile * 16] = lo;
+    gpu_mem.vram[(tile * 16) + 1] = hi;
+}
+
+// Colors are made up so each palette index can be told apart
+#[cfg(test)]
+fn test_pixel_colors(gpu_mem: &mut GpuMemory) -> Vec<u8> {
+    for i in 0..4 {
+        gpu_mem.bg_colors[i] = [i as u8; 4];
+        gpu_mem.obp0_colors[i] = [0x10 + i as u8; 4];
+        gpu_mem.obp1_colors[i] = [0x20 + i as u8; 4];
+    }
+    mode3_length(gpu_mem);
+    return gpu_mem.pixels[..BYTES_PER_ROW]
+        .chunks(BYTES_PER_PIXEL)
+        .map(|pixel| pixel[0])
+        .collect();
+}
+
+#[test]
+fn test_sprite_fifo_overlap() {
+    let mut gpu_mem = test_gpu_mem(&[]);
+    set_tile_row(&mut gpu_mem, 1, 0x00, 0x0F); // Right half color 2
+    set_tile_row(&mut gpu_mem, 2, 0xFF, 0x00); // All color 1
+    gpu_mem.sprite_list.push(Sprite::new(&[16, 16, 1, 0x00], 8));
+    gpu_mem.sprite_list.push(Sprite::new(&[16, 16, 2, 0x10], 8)); // Later in oam, OBP1
+    gpu_mem.sprite_list.push(Sprite::new(&[16, 20, 2, 0x00], 8)); // Further right
+
+    let pixels = test_pixel_colors(&mut gpu_mem);
+    assert_eq!(pixels[7], 0x00);
+    // Transparent pixels of the first sprite get filled by the second
+    assert_eq!(pixels[8..12], [0x21; 4]);
+    // Pixels of the first sprite stay, even over the sprite with the lower x
+    assert_eq!(pixels[12..16], [0x12; 4]);
+    assert_eq!(pixels[16..20], [0x11; 4]);
+    assert_eq!(pixels[20], 0x00);
+}
+
+#[test]
+fn test_sprite_fifo_priority() {
+    let mut gpu_mem = test_gpu_mem(&[]);
+    set_tile_row(&mut gpu_mem, 0, 0xF0, 0x00); // Background is color 1 then color 0
+    set_tile_row(&mut gpu_mem, 1, 0xFF, 0xFF); // All color 3
+    gpu_mem.sprite_list.push(Sprite::new(&[16, 4, 1, 0x00], 8)); // Half off screen
+    gpu_mem.sprite_list.push(Sprite::new(&[16, 16, 1, 0x80], 8)); // Behind background
+
+    let pixels = test_pixel_colors(&mut gpu_mem);
+    assert_eq!(pixels[0..4], [0x13; 4]);
+    assert_eq!(pixels[4..8], [0x00; 4]);
+    assert_eq!(pixels[8..12], [0x01; 4]);
+    assert_eq!(pixels[12..16], [0x13; 4]);
+
+    // With the background off the sprite behind it shows up everywhere
+    gpu_mem.lcdc &= !0x01;
+    let pixels = test_pixel_colors(&mut gpu_mem);
+    assert_eq!(pixels[8..16], [0x13; 8]);
+}
+
+#[test]
+fn test_mid_line_registers() {
+    let mut gpu_mem = test_gpu_mem(&[]);
+    set_tile_row(&mut gpu_mem, 0, 0xFF, 0x00); // Background is all color 1
+    set_tile_row(&mut gpu_mem, 1, 0xFF, 0xFF); // All color 3
+    gpu_mem
+        .sprite_list
+        .push(Sprite::new(&[16, 108, 1, 0x00], 8));
+    test_pixel_colors(&mut gpu_mem);
+
+    // Without sprites or the window, pixel x comes out on dot 13 + x
+    let mut state = PpuState::PictureGeneration(PictureGeneration::new());
+    if let PpuState::PictureGeneration(pg) = state {
+        state = pg.render(&mut gpu_mem, 13 + 40);
+    }
+    gpu_mem.bg_colors[1] = [0x05; 4]; // Like writing to BGP
+    gpu_mem.lcdc &= !0x02; // Sprites off before the sprite at x 100 was fetched
+    while let PpuState::PictureGeneration(pg) = state {
+        state = pg.render(&mut gpu_mem, 4);
+    }
+
+    let pixels: Vec<u8> = gpu_mem.pixels[..BYTES_PER_ROW]
+        .chunks(BYTES_PER_PIXEL)
+        .map(|pixel| pixel[0])
+        .collect();
+    assert_eq!(pixels[39..41], [0x01, 0x01]);
+    assert_eq!(pixels[41..], [0x05; 119]);
+}
+
+#[test]
+fn test_window_edge_cases() {
+    // WX = 166 puts the window on the last pixel and over all of the next line
+    let mut gpu_mem = test_gpu_mem(&[]);
+    gpu_mem.lcdc |= 0x20;
+    gpu_mem.wx = 166;
+    gpu_mem.window_y_triggered = true;
+    assert_eq!(mode3_length(&mut gpu_mem), 178);
+    assert!(gpu_mem.window_wx166);
+    gpu_mem.wx = 200;
+    assert_eq!(mode3_length(&mut gpu_mem), 178);
+    assert!(!gpu_mem.window_wx166);
+    assert_eq!(gpu_mem.window_line_counter, 2);
+
+    // WX = 0 shifts the window by what is left of scx % 8 instead of 7 pixels
+    gpu_mem.wx = 0;
+    gpu_mem.scx = 3;
+    assert_eq!(mode3_length(&mut gpu_mem), 172 + 6 + 3);
+    gpu_mem.wx = 1;
+    assert_eq!(mode3_length(&mut gpu_mem), 172 + 6 + 6);
+}
+
+#[test]
+fn test_hidden_layers() {
+    let mut gpu_mem = test_gpu_mem(&[]);
+    gpu_mem.lcdc |= 0x60; // Window on, using the 0x9C00 map
+    gpu_mem.wx = 87;
+    gpu_mem.window_y_triggered = true;
+    gpu_mem.vram[0x1C00] = 2; // Window starts with tile 2
+    set_tile_row(&mut gpu_mem, 0, 0xFF, 0x00); // Background is all color 1
+    set_tile_row(&mut gpu_mem, 1, 0xFF, 0xFF); // All color 3
+    for line in 0..8 {
+        // All color 2, on every line since the window line counter keeps going up
+        gpu_mem.vram[(2 * 16) + (line * 2) + 1] = 0xFF;
+    }
+    gpu_mem.sprite_list.push(Sprite::new(&[16, 8, 1, 0x00], 8));
+    gpu_mem.sprite_list.push(Sprite::new(&[16, 16, 1, 0x80], 8)); // Behind background
+
+    let pixels = test_pixel_colors(&mut gpu_mem);
+    assert_eq!(pixels[0..16], [[0x13; 8], [0x01; 8]].concat());
+    assert_eq!(pixels[80], 0x02);
+
+    // Hiding the background lets the sprite behind it through, but not over the window
+    gpu_mem.set_layer_visible(Layer::Background, false);
+    let pixels = test_pixel_colors(&mut gpu_mem);
+    assert_eq!(pixels[8..16], [0x13; 8]);
+    assert_eq!(pixels[79..81], [0x00, 0x02]);
+    gpu_mem.set_layer_visible(Layer::Background, true);
+
+    gpu_mem.set_layer_visible(Layer::Window, false);
+    assert_eq!(test_pixel_colors(&mut gpu_mem)[79..81], [0x01, 0x00]);
+    gpu_mem.set_layer_visible(Layer::Sprites, false);
+    assert_eq!(test_pixel_colors(&mut gpu_mem)[0..8], [0x01; 8]);
+
+    // The ppu still does the same amount of work and the game sees the same lcdc
+    assert_eq!(mode3_length(&mut gpu_mem), 172 + 6 + 11 + 11);
+    assert_eq!(gpu_mem.lcdc, 0x93 | 0x60);
+}
+
+#[test]
+fn test_shades() {
+    let mut gpu_mem = test_gpu_mem(&[]);
+    set_tile_row(&mut gpu_mem, 0, 0xFF, 0x00); // Background is all color 1
+    set_tile_row(&mut gpu_mem, 1, 0x00, 0xFF); // All color 2
+    gpu_mem.sprite_list.push(Sprite::new(&[16, 16, 1, 0x10], 8)); // OBP1
+    gpu_mem.bgp = 0b00_00_11_00; // Color 1 is shade 3
+    gpu_mem.obp1 = 0b00_01_00_00; // Color 2 is shade 1
+
+    // The shades come from the registers whatever colors they are shown with
+    test_pixel_colors(&mut gpu_mem);
+    assert_eq!(gpu_mem.shades[0..8], [3; 8]);
+    assert_eq!(gpu_mem.shades[8..16], [1; 8]);
+    assert_eq!(gpu_mem.shades[16], 3);
+    assert_eq!(gpu_mem.pixels[8 * BYTES_PER_PIXEL], 0x22);
+}
@@ -0,0 +1,474 @@
+/*
+    Pixel art upscalers that run on the cpu, so they work on any renderer
+    (including software ones) without needing shaders. They take the 160x144
+    framebuffer and give back a bigger image, which SDL then stretches to the
+    window with nearest neighbour like it does with the plain framebuffer.
+
+        nearest  The framebuffer as is, SDL does all the scaling
+        scale2x  (AdvMAME2x) Fills in corners where two same colored edges meet
+        scale3x  (AdvMAME3x) The same at 3x, also fixes up the middle of each side
+        smooth2x Blends edges using how different the colors look (in YUV). It
+                 borrows the color test and blends from hq2x, but decides each
+                 corner from the pixels next to it instead of a lookup table
+        xbr      2xBR by Hyllian. Finds edges from the 5x5 pixels around each one
+                 and blends along them, keeping shallow and steep lines smooth
+
+    Every filter works one corner (or side) of the output at a time. The rules
+    are written for the bottom right corner and the neighbours get turned 90
+    degrees for each of the others, see rotate.
+
+    The neighbours are named like this, with E the pixel being scaled
+                A1 B1 C1
+             A0 A  B  C  C4
+             D0 D  E  F  F4
+             G0 G  H  I  I4
+                G5 H5 I5
+*/
+
+use super::gpu_memory::BYTES_PER_PIXEL;
+use super::viewer::Image;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Scaler {
+    Nearest,
+    Scale2x,
+    Scale3x,
+    Smooth2x,
+    Xbr,
+}
+
+impl Scaler {
+    pub const ALL: [Scaler; 5] = [
+        Scaler::Nearest,
+        Scaler::Scale2x,
+        Scaler::Scale3x,
+        Scaler::Smooth2x,
+        Scaler::Xbr,
+    ];
+
+    // Name used in the config file and with --scaler
+    pub fn name(self: &Self) -> &'static str {
+        return match self {
+            Scaler::Nearest => "nearest",
+            Scaler::Scale2x => "scale2x",
+            Scaler::Scale3x => "scale3x",
+            Scaler::Smooth2x => "smooth2x",
+            Scaler::Xbr => "xbr",
+        };
+    }
+
+    pub fn from_name(name: &str) -> Option<Scaler> {
+        let name = name.to_lowercase();
+        return Scaler::ALL.into_iter().find(|scaler| scaler.name() == name);
+    }
+
+    // How many times bigger the image gets
+    pub fn factor(self: &Self) -> usize {
+        return match self {
+            Scaler::Nearest => 1,
+            Scaler::Scale3x => 3,
+            _ => 2,
+        };
+    }
+
+    pub fn next(self: &Self) -> Scaler {
+        let index = Scaler::ALL.iter().position(|s| s == self).unwrap();
+        return Scaler::ALL[(index + 1) % Scaler::ALL.len()];
+    }
+
+    pub fn apply(self: &Self, image: &Image) -> Image {
+        let grid = Grid::from_image(image);
+        let factor = self.factor();
+        let (width, height) = (grid.width * factor, grid.height * factor);
+        let mut out = vec![0; width * height];
+
+        for y in 0..grid.height as i32 {
+            for x in 0..grid.width as i32 {
+                let block = match self {
+                    Scaler::Nearest => vec![grid.get(x, y).color],
+                    Scaler::Scale2x => scale2x(&grid, x, y),
+                    Scaler::Scale3x => scale3x(&grid, x, y),
+                    Scaler::Smooth2x => smooth2x(&grid, x, y),
+                    Scaler::Xbr => xbr(&grid, x, y),
+                };
+                for (i, pixel) in block.into_iter().enumerate() {
+                    let out_x = (x as usize * factor) + (i % factor);
+                    let out_y = (y as usize * factor) + (i / factor);
+                    out[(out_y * width) + out_x] = pixel;
+                }
+            }
+        }
+        return to_image(width, height, &out);
+    }
+}
+
+// Where the picture goes in a window of the given size. Keeps the 160x144 shape
+// and centers it, with black bars on whatever is left over. With integer_scale
+// it only grows in whole multiples so every gameboy pixel is the same size
+pub fn display_rect(
+    window: (u32, u32),
+    picture: (u32, u32),
+    integer_scale: bool,
+) -> (i32, i32, u32, u32) {
+    let (win_w, win_h) = window;
+    let (pic_w, pic_h) = picture;
+    let (width, height) = if integer_scale {
+        let scale = std::cmp::max(1, std::cmp::min(win_w / pic_w, win_h / pic_h));
+        (pic_w * scale, pic_h * scale)
+    } else if win_w * pic_h > win_h * pic_w {
+        (win_h * pic_w / pic_h, win_h) // Window is wider than the picture
+    } else {
+        (win_w, win_w * pic_h / pic_w)
+    };
+    let x = (win_w as i32 - width as i32) / 2;
+    let y = (win_h as i32 - height as i32) / 2;
+    return (x, y, width, height);
+}
+
+// Colors are 0xAARRGGBB so they can be compared and blended in one go. The
+// YUV is worked out once per pixel since xbr compares each one many times
+#[derive(Clone, Copy, PartialEq)]
+struct Pixel {
+    color: u32,
+    yuv: (i32, i32, i32),
+}
+
+struct Grid {
+    width: usize,
+    height: usize,
+    pixels: Vec<Pixel>,
+}
+
+impl Grid {
+    fn from_image(image: &Image) -> Grid {
+        let pixels = image
+            .pixels
+            .chunks(BYTES_PER_PIXEL)
+            .map(|p| {
+                let color = u32::from_le_bytes([p[0], p[1], p[2], p[3]]);
+                Pixel {
+                    color: color,
+                    yuv: yuv(color),
+                }
+            })
+            .collect();
+        return Grid {
+            width: image.width,
+            height: image.height,
+            pixels: pixels,
+        };
+    }
+
+    // Pixels off the edge are the same as the closest one on the edge
+    fn get(self: &Self, x: i32, y: i32) -> Pixel {
+        let x = x.clamp(0, self.width as i32 - 1) as usize;
+        let y = y.clamp(0, self.height as i32 - 1) as usize;
+        return self.pixels[(y * self.width) + x];
+    }
+}
+
+fn to_image(width: usize, height: usize, colors: &[u32]) -> Image {
+    let mut image = Image::new(width, height);
+    for (i, color) in colors.iter().enumerate() {
+        let start = i * BYTES_PER_PIXEL;
+        image.pixels[start..start + BYTES_PER_PIXEL].copy_from_slice(&color.to_le_bytes());
+    }
+    return image;
+}
+
+// Turns an offset from the bottom right corner's point of view into the one for
+// corner `turns` (0 bottom right, 1 top right, 2 top left, 3 bottom left)
+fn rotate(turns: usize, x: i32, y: i32) -> (i32, i32) {
+    return match turns {
+        0 => (x, y),
+        1 => (y, -x),
+        2 => (-x, -y),
+        _ => (-y, x),
+    };
+}
+
+// The neighbours of (x, y) as seen from one corner
+struct Neighbours<'a> {
+    grid: &'a Grid,
+    x: i32,
+    y: i32,
+    turns: usize,
+}
+
+impl<'a> Neighbours<'a> {
+    fn new(grid: &'a Grid, x: i32, y: i32, turns: usize) -> Neighbours<'a> {
+        return Neighbours {
+            grid: grid,
+            x: x,
+            y: y,
+            turns: turns,
+        };
+    }
+
+    fn at(self: &Self, dx: i32, dy: i32) -> Pixel {
+        let (dx, dy) = rotate(self.turns, dx, dy);
+        return self.grid.get(self.x + dx, self.y + dy);
+    }
+}
+
+// Index into an output block of `size` x `size` for an offset from its middle
+// (-1 or 1 for 2x, -1, 0 or 1 for 3x)
+fn block_index(size: usize, turns: usize, x: i32, y: i32) -> usize {
+    let (x, y) = rotate(turns, x, y);
+    let (x, y) = if size == 2 {
+        ((x + 1) / 2, (y + 1) / 2)
+    } else {
+        (x + 1, y + 1)
+    };
+    return (y as usize * size) + x as usize;
+}
+
+fn scale2x(grid: &Grid, x: i32, y: i32) -> Vec<u32> {
+    let mut block = vec![grid.get(x, y).color; 4];
+    for turns in 0..4 {
+        let n = Neighbours::new(grid, x, y, turns);
+        let (b, d, f, h) = (n.at(0, -1), n.at(-1, 0), n.at(1, 0), n.at(0, 1));
+        if f == h && f != b && h != d {
+            block[block_index(2, turns, 1, 1)] = f.color;
+        }
+    }
+    return block;
+}
+
+fn scale3x(grid: &Grid, x: i32, y: i32) -> Vec<u32> {
+    let mut block = vec![grid.get(x, y).color; 9];
+    for turns in 0..4 {
+        let n = Neighbours::new(grid, x, y, turns);
+        let (b, c, d, e) = (n.at(0, -1), n.at(1, -1), n.at(-1, 0), n.at(0, 0));
+        let (f, h, i) = (n.at(1, 0), n.at(0, 1), n.at(1, 1));
+        let corner = f == h && f != b && h != d;
+        if corner {
+            block[block_index(3, turns, 1, 1)] = f.color;
+        }
+        if (corner && e != c) || (b == f && b != d && f != h && e != i) {
+            block[block_index(3, turns, 1, 0)] = f.color;
+        }
+    }
+    return block;
+}
+
+fn smooth2x(grid: &Grid, x: i32, y: i32) -> Vec<u32> {
+    let mut block = vec![0; 4];
+    for turns in 0..4 {
+        let n = Neighbours::new(grid, x, y, turns);
+        let (e, f, h, i) = (n.at(0, 0), n.at(1, 0), n.at(0, 1), n.at(1, 1));
+        let pixel = if !similar(e, f) && !similar(e, h) {
+            if similar(f, h) && similar(e, i) {
+                blend(&[(e.color, 6), (f.color, 1), (h.color, 1)]) // Two lines crossing
+            } else {
+                blend(&[(e.color, 2), (f.color, 1), (h.color, 1)]) // An edge cutting off the corner
+            }
+        } else if !similar(e, i) {
+            blend(&[(e.color, 3), (i.color, 1)])
+        } else {
+            e.color
+        };
+        block[block_index(2, turns, 1, 1)] = pixel;
+    }
+    return block;
+}
+
+// Each corner can also blend into the two next to it, so the
+// order the corners are done in matters (the same as the original)
+fn xbr(grid: &Grid, x: i32, y: i32) -> Vec<u32> {
+    let mut block = vec![grid.get(x, y).color; 4];
+    for turns in 0..4 {
+        let n = Neighbours::new(grid, x, y, turns);
+        let (b, c, d, e) = (n.at(0, -1), n.at(1, -1), n.at(-1, 0), n.at(0, 0));
+        let (f, g, h, i) = (n.at(1, 0), n.at(-1, 1), n.at(0, 1), n.at(1, 1));
+        let (f4, i4, h5, i5) = (n.at(2, 0), n.at(2, 1), n.at(0, 2), n.at(1, 2));
+
+        if e == h || e == f {
+            continue;
+        }
+        let along = distance(e, c)
+            + distance(e, g)
+            + distance(i, h5)
+            + distance(i, f4)
+            + (distance(h, f) * 4);
+        let across = distance(h, d)
+            + distance(h, i5)
+            + distance(f, i4)
+            + distance(f, b)
+            + (distance(e, i) * 4);
+        let px = if distance(e, f) <= distance(e, h) {
+            f.color
+        } else {
+            h.color
+        };
+
+        let n3 = block_index(2, turns, 1, 1);
+        let n2 = block_index(2, turns, -1, 1);
+        let n1 = block_index(2, turns, 1, -1);
+        let is_edge = (!similar(f, b) && !similar(h, d))
+            || (similar(e, i) && !similar(f, i4) && !similar(h, i5))
+            || similar(e, g)
+            || similar(e, c);
+
+        if along < across && is_edge {
+            let ke = distance(f, g);
+            let ki = distance(h, c);
+            let shallow = (ke * 2) <= ki && e != g && d != g;
+            let steep = ke >= (ki * 2) && e != c && b != c;
+            if shallow && steep {
+                block[n3] = mix(block[n3], px, 224);
+                block[n2] = mix(block[n2], px, 64);
+                block[n1] = block[n2];
+            } else if shallow {
+                block[n3] = mix(block[n3], px, 192);
+                block[n2] = mix(block[n2], px, 64);
+            } else if steep {
+                block[n3] = mix(block[n3], px, 192);
+                block[n1] = mix(block[n1], px, 64);
+            } else {
+                block[n3] = mix(block[n3], px, 128);
+            }
+        } else if along <= across {
+            block[n3] = mix(block[n3], px, 128);
+        }
+    }
+    return block;
+}
+
+fn yuv(pixel: u32) -> (i32, i32, i32) {
+    let r = ((pixel >> 16) & 0xFF) as i32;
+    let g = ((pixel >> 8) & 0xFF) as i32;
+    let b = (pixel & 0xFF) as i32;
+    let y = ((299 * r) + (587 * g) + (114 * b)) / 1000;
+    let u = ((-169 * r) - (331 * g) + (500 * b)) / 1000 + 128;
+    let v = ((500 * r) - (419 * g) - (81 * b)) / 1000 + 128;
+    return (y, u, v);
+}
+
+// The thresholds hqx uses for two colors looking the same
+fn similar(a: Pixel, b: Pixel) -> bool {
+    let ((ya, ua, va), (yb, ub, vb)) = (a.yuv, b.yuv);
+    return (ya - yb).abs() <= 0x30 && (ua - ub).abs() <= 7 && (va - vb).abs() <= 6;
+}
+
+// How different two colors look, brightness counting the most
+fn distance(a: Pixel, b: Pixel) -> i32 {
+    let ((ya, ua, va), (yb, ub, vb)) = (a.yuv, b.yuv);
+    return (48 * (ya - yb).abs()) + (7 * (ua - ub).abs()) + (6 * (va - vb).abs());
+}
+
+// Weighted average of each channel
+fn blend(colors: &[(u32, u32)]) -> u32 {
+    let total: u32 = colors.iter().map(|(_, weight)| weight).sum();
+    let mut pixel = 0;
+    for shift in [0, 8, 16, 24] {
+        let sum: u32 = colors
+            .iter()
+            .map(|(color, weight)| ((color >> shift) & 0xFF) * weight)
+            .sum();
+        pixel |= (sum / total) << shift;
+    }
+    return pixel;
+}
+
+// amount out of 256 of src mixed into dst
+fn mix(dst: u32, src: u32, amount: u32) -> u32 {
+    return blend(&[(dst, 256 - amount), (src, amount)]);
+}
+
+#[test]
+fn test_scaler_names_and_sizes() {
+    assert_eq!(Scaler::from_name("XBR"), Some(Scaler::Xbr));
+    assert_eq!(Scaler::from_name("hq4x"), None);
+    assert_eq!(Scaler::Xbr.next(), Scaler::Nearest);
+
+    let image = Image::new(160, 144);
+    for scaler in Scaler::ALL {
+        let scaled = scaler.apply(&image);
+        assert_eq!(scaled.width, 160 * scaler.factor());
+        assert_eq!(scaled.height, 144 * scaler.factor());
+        assert!(scaled.pixels.iter().all(|b| *b == 0)); // Flat colors stay flat
+    }
+}
+
+#[test]
+fn test_scale2x_diagonal() {
+    const W: u32 = 0xFFFFFFFF;
+    const K: u32 = 0xFF000000;
+    // A diagonal line of black going down to the right
+    let image = to_image(3, 3, &[K, W, W, W, K, W, W, W, K]);
+    let scaled = Grid::from_image(&Scaler::Scale2x.apply(&image));
+    let middle: Vec<u32> = [(2, 2), (3, 2), (2, 3), (3, 3)]
+        .iter()
+        .map(|(x, y)| scaled.get(*x, *y).color)
+        .collect();
+    assert_eq!(middle, vec![K, K, K, K]); // White on all 4 sides so it stays
+
+    // The white pixel right of the middle gets its bottom left corner filled in
+    assert_eq!(scaled.get(4, 3).color, K);
+    assert_eq!(scaled.get(5, 2).color, W);
+
+    let scaled = Grid::from_image(&Scaler::Scale3x.apply(&image));
+    assert_eq!(scaled.get(6, 3).color, W); // Top left of the pixel to the right
+    assert_eq!(scaled.get(6, 5).color, K); // Bottom left
+}
+
+#[test]
+fn test_smooth2x_diagonal() {
+    const W: u32 = 0xFFFFFFFF;
+    const K: u32 = 0xFF000000;
+    let image = to_image(3, 3, &[K, W, W, W, K, W, W, W, K]);
+    let scaled = Grid::from_image(&Scaler::Smooth2x.apply(&image));
+    let middle: Vec<u32> = [(2, 2), (3, 2), (2, 3), (3, 3)]
+        .iter()
+        .map(|(x, y)| scaled.get(*x, *y).color)
+        .collect();
+    // Corners along the line are 6 black to 2 white, the ones across it 2 to 2
+    assert_eq!(middle, vec![0xFF3F3F3F, 0xFF7F7F7F, 0xFF7F7F7F, 0xFF3F3F3F]);
+
+    // The white pixel right of the middle gets a little black in its bottom left
+    assert_eq!(scaled.get(4, 3).color, 0xFFBFBFBF);
+    assert_eq!(scaled.get(5, 2).color, W);
+}
+
+#[test]
+fn test_xbr_diagonal() {
+    const W: u32 = 0xFFFFFFFF;
+    const K: u32 = 0xFF000000;
+    let image = to_image(3, 3, &[K, W, W, W, K, W, W, W, K]);
+    let scaled = Grid::from_image(&Scaler::Xbr.apply(&image));
+    let middle: Vec<u32> = [(2, 2), (3, 2), (2, 3), (3, 3)]
+        .iter()
+        .map(|(x, y)| scaled.get(*x, *y).color)
+        .collect();
+    // The corners along the line stay black and the ones across it go half way
+    assert_eq!(middle, vec![K, 0xFF7F7F7F, 0xFF7F7F7F, K]);
+
+    // The white pixel right of the middle blends most of the way to black in its
+    // bottom left, and a little in its bottom right to keep the line smooth
+    assert_eq!(scaled.get(4, 3).color, 0xFF3F3F3F);
+    assert_eq!(scaled.get(5, 3).color, 0xFFBFBFBF);
+    assert_eq!(scaled.get(4, 2).color, W);
+    assert_eq!(scaled.get(5, 2).color, W);
+}
+
+#[test]
+fn test_display_rect() {
+    assert_eq!(
+        display_rect((480, 432), (160, 144), false),
+        (0, 0, 480, 432)
+    );
+    // Wider window, bars on the sides
+    assert_eq!(
+        display_rect((800, 432), (160, 144), false),
+        (160, 0, 480, 432)
+    );
+    assert_eq!(
+        display_rect((800, 600), (160, 144), true),
+        (80, 12, 640, 576)
+    );
+    assert_eq!(
+        display_rect((100, 100), (160, 144), true),
+        (-30, -22, 160, 144)
+    );
+}
@@ -1,6 +1,7 @@
 use super::oam_search::OamSearch;
 use super::ppu::{PpuState, MODE_OSEARCH};
 use super::*;
+use crate::state::{StateReader, StateWriter};
 
 // mode 1
 pub struct VBlank {
@@ -19,6 +20,18 @@ impl VBlank {
         });
     }
 
+    pub fn save_state(self: &Self, state: &mut StateWriter) {
+        state.write_usize(self.cycles_counter);
+        state.write_usize(self.line_counter);
+    }
+
+    pub fn load_state(state: &mut StateReader) -> PpuState {
+        return PpuState::VBlank(VBlank {
+            cycles_counter: state.read_usize(),
+            line_counter: state.read_usize(),
+        });
+    }
+
     // On boot, only emulate 53 cycles. Ran another emulator to determine this
     // but dont truly know if its correct. Makes more sense than starting
     // in oam_search state though to get mooneye boot_hwio-dmgABCmgb to pass
@@ -33,8 +46,11 @@ impl VBlank {
     fn next(mut self, gpu_mem: &mut GpuMemory) -> PpuState {
         if self.cycles_counter >= VBlank::MAX_VBLANK_CYCLES {
             gpu_mem.window_line_counter = 0;
+            gpu_mem.window_y_triggered = false;
+            gpu_mem.window_wx166 = false;
             gpu_mem.set_stat_mode(MODE_OSEARCH);
             gpu_mem.set_ly(0); // I think this is supposed to be set earlier. Research more
+            gpu_mem.check_window_y();
             gpu_mem.sprite_list.clear();
             return OamSearch::new();
         }
@@ -0,0 +1,562 @@
+/*
+    Debug views of what is in vram. These only read GpuMemory and never touch
+    the state of the ppu so they can be drawn at any point without changing
+    how the game runs.
+
+    Images use the same BGRA byte order as the framebuffer so they can go straight
+    into an SDL texture or through png::encode.
+*/
+
+use super::gpu_memory::*;
+use super::oam_search::Sprite;
+use super::BYTES_PER_TILE;
+use crate::png;
+
+// Neutral shades for when the palette registers make things hard to see
+const GRAYSCALE: [[u8; 4]; 4] = [
+    [0xFF, 0xFF, 0xFF, 0xFF],
+    [0xAA, 0xAA, 0xAA, 0xFF],
+    [0x55, 0x55, 0x55, 0xFF],
+    [0x00, 0x00, 0x00, 0xFF],
+];
+
+// Overlays on the tilemaps
+const VIEWPORT_COLOR: [u8; 4] = [0x00, 0x00, 0xFF, 0xFF]; // Red
+const WINDOW_COLOR: [u8; 4] = [0xFF, 0x60, 0x00, 0xFF]; // Blue
+
+// Sprite inspector
+const SPRITE_CLEAR_COLOR: [u8; 4] = [0x60, 0x30, 0x60, 0xFF]; // Purple, color 0 of a sprite
+const DROPPED_COLOR: [u8; 4] = [0x00, 0x00, 0xFF, 0xFF]; // Red
+
+const NUM_TILES: usize = 384; // 0x8000 - 0x97FF
+const TILES_PER_ROW: usize = 16;
+const MAP_SIZE: usize = 256; // 32x32 tiles
+const MAP_GAP: usize = 8; // Between the two maps
+const MAP_BAR: usize = 4; // Under a map, shows whether the background and/or window use it
+const MAP_STARTS: [u16; 2] = [0x9800, 0x9C00];
+const NUM_SPRITES: usize = 40;
+const SPRITES_PER_ROW: usize = 8;
+const SPRITE_CELL: (usize, usize) = (12, 20); // Room for an 8x16 sprite and a border
+const MAX_LINE_SPRITES: usize = 10;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum View {
+    Tiles,
+    Tilemaps,
+    Sprites,
+}
+
+impl View {
+    pub fn name(self: &Self) -> &'static str {
+        return match self {
+            View::Tiles => "Tiles",
+            View::Tilemaps => "Tilemaps",
+            View::Sprites => "Sprites",
+        };
+    }
+
+    pub fn size(self: &Self) -> (usize, usize) {
+        return match self {
+            View::Tiles => (TILES_PER_ROW * 8, (NUM_TILES / TILES_PER_ROW) * 8),
+            View::Tilemaps => ((MAP_SIZE * 2) + MAP_GAP, MAP_SIZE + MAP_BAR),
+            View::Sprites => (
+                SPRITES_PER_ROW * SPRITE_CELL.0,
+                (NUM_SPRITES / SPRITES_PER_ROW) * SPRITE_CELL.1,
+            ),
+        };
+    }
+}
+
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>, // BGRA
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize) -> Image {
+        return Image {
+            width: width,
+            height: height,
+            pixels: vec![0; width * height * BYTES_PER_PIXEL],
+        };
+    }
+
+    pub fn set_pixel(self: &mut Self, x: usize, y: usize, color: [u8; 4]) {
+        let start = ((y * self.width) + x) * BYTES_PER_PIXEL;
+        self.pixels[start..start + BYTES_PER_PIXEL].copy_from_slice(&color);
+    }
+
+    // Each pixel becomes a scale x scale square
+    pub fn scaled(self: &Self, scale: usize) -> Image {
+        let mut image = Image::new(self.width * scale, self.height * scale);
+        for y in 0..image.height {
+            for x in 0..image.width {
+                let start = (((y / scale) * self.width) + (x / scale)) * BYTES_PER_PIXEL;
+                let mut color = [0; 4];
+                color.copy_from_slice(&self.pixels[start..start + BYTES_PER_PIXEL]);
+                image.set_pixel(x, y, color);
+            }
+        }
+        return image;
+    }
+
+    pub fn save_png(self: &Self, path: &str) -> Result<(), String> {
+        return png::save(path, self.width, self.height, &self.pixels);
+    }
+}
+
+pub fn render(gpu_mem: &GpuMemory, view: View, grayscale: bool) -> Image {
+    let palette = if grayscale {
+        GRAYSCALE
+    } else {
+        gpu_mem.bg_colors
+    };
+    return match view {
+        View::Tiles => tiles(gpu_mem, &palette),
+        View::Tilemaps => tilemaps(gpu_mem, &palette),
+        View::Sprites => sprites(gpu_mem, grayscale),
+    };
+}
+
+// What is under (x, y) of the image, for showing while hovering over it
+pub fn describe(gpu_mem: &GpuMemory, view: View, x: usize, y: usize) -> Option<String> {
+    let (width, height) = view.size();
+    if x >= width || y >= height {
+        return None;
+    }
+    return match view {
+        View::Tiles => Some(describe_tile((y / 8) * TILES_PER_ROW + (x / 8))),
+        View::Tilemaps => describe_map_tile(gpu_mem, x, y),
+        View::Sprites => {
+            let sprite = ((y / SPRITE_CELL.1) * SPRITES_PER_ROW) + (x / SPRITE_CELL.0);
+            Some(describe_sprite(gpu_mem, sprite))
+        }
+    };
+}
+
+// A text version of the view, for the views that have more to show than fits in an image
+pub fn listing(gpu_mem: &GpuMemory, view: View) -> Option<String> {
+    return match view {
+        View::Sprites => Some(
+            (0..NUM_SPRITES)
+                .map(|sprite| describe_sprite(gpu_mem, sprite))
+                .collect::<Vec<String>>()
+                .join("\n"),
+        ),
+        _ => None,
+    };
+}
+
+// Color index (0 - 3) of pixel (x, y) of the tile at vram offset tile_start
+fn tile_pixel(gpu_mem: &GpuMemory, tile_start: usize, x: usize, y: usize) -> usize {
+    let lo = gpu_mem.vram[tile_start + (y * 2)];
+    let hi = gpu_mem.vram[tile_start + (y * 2) + 1];
+    return ((((hi >> (7 - x)) & 0x01) << 1) | ((lo >> (7 - x)) & 0x01)) as usize;
+}
+
+fn draw_tile(
+    image: &mut Image,
+    gpu_mem: &GpuMemory,
+    tile_start: usize,
+    left: usize,
+    top: usize,
+    palette: &[[u8; 4]; 4],
+) {
+    for y in 0..8 {
+        for x in 0..8 {
+            let color = palette[tile_pixel(gpu_mem, tile_start, x, y)];
+            image.set_pixel(left + x, top + y, color);
+        }
+    }
+}
+
+// All 384 tiles, 16 to a row in the order they are in vram
+fn tiles(gpu_mem: &GpuMemory, palette: &[[u8; 4]; 4]) -> Image {
+    let (width, height) = View::Tiles.size();
+    let mut image = Image::new(width, height);
+    for tile in 0..NUM_TILES {
+        let (left, top) = ((tile % TILES_PER_ROW) * 8, (tile / TILES_PER_ROW) * 8);
+        draw_tile(
+            &mut image,
+            gpu_mem,
+            tile * BYTES_PER_TILE,
+            left,
+            top,
+            palette,
+        );
+    }
+    return image;
+}
+
+// Tiles 0 - 255 are indexed from 0x8000 and tiles 128 - 383 from 0x9000 (signed)
+fn describe_tile(tile: usize) -> String {
+    let addr = VRAM_START as usize + (tile * BYTES_PER_TILE);
+    let mut info = format!("Tile {} at 0x{:04X}", tile, addr);
+    if tile < 256 {
+        info.push_str(&format!(", 0x8000 index 0x{:02X}", tile));
+    }
+    if tile >= 128 {
+        info.push_str(&format!(", 0x8800 index 0x{:02X}", tile % 256));
+    }
+    return info;
+}
+
+// vram offset of the tile data for a tile map entry, using lcdc bit 4 like the ppu does
+fn map_tile_start(gpu_mem: &GpuMemory, index: u8) -> usize {
+    let addr = match gpu_mem.get_addr_mode_start() {
+        0x8000 => 0x8000 + (usize::from(index) * BYTES_PER_TILE),
+        _ => (0x9000 + (isize::from(index as i8) * BYTES_PER_TILE as isize)) as usize,
+    };
+    return addr - VRAM_START as usize;
+}
+
+// Which map (if any) is under x of the tilemaps view, and where it starts in the image
+fn map_at(x: usize) -> Option<(u16, usize)> {
+    return match x {
+        x if x < MAP_SIZE => Some((MAP_STARTS[0], 0)),
+        x if x >= MAP_SIZE + MAP_GAP => Some((MAP_STARTS[1], MAP_SIZE + MAP_GAP)),
+        _ => None,
+    };
+}
+
+// Draws the outline of a rectangle that wraps around at the edges of the map
+fn draw_wrapped_rect(
+    image: &mut Image,
+    left: usize,
+    (x, y): (usize, usize),
+    (width, height): (usize, usize),
+    color: [u8; 4],
+) {
+    for i in 0..width {
+        let px = left + ((x + i) % MAP_SIZE);
+        image.set_pixel(px, y % MAP_SIZE, color);
+        image.set_pixel(px, (y + height - 1) % MAP_SIZE, color);
+    }
+    for i in 0..height {
+        let py = (y + i) % MAP_SIZE;
+        image.set_pixel(left + (x % MAP_SIZE), py, color);
+        image.set_pixel(left + ((x + width - 1) % MAP_SIZE), py, color);
+    }
+}
+
+// Both 32x32 tile maps side by side. The map the background uses has the area the
+// screen shows (scx, scy) outlined in red, and the map the window uses has the part
+// of the window on screen outlined in blue. The bars under the maps show the same
+fn tilemaps(gpu_mem: &GpuMemory, palette: &[[u8; 4]; 4]) -> Image {
+    let (width, height) = View::Tilemaps.size();
+    let mut image = Image::new(width, height);
+    let bg_map = gpu_mem.get_bg_tile_map().0;
+    let window_map = gpu_mem.get_window_tile_map().0;
+
+    for (i, map_start) in MAP_STARTS.iter().enumerate() {
+        let left = i * (MAP_SIZE + MAP_GAP);
+        for tile in 0..1024 {
+            let index = gpu_mem.vram[usize::from(map_start - VRAM_START) + tile];
+            let tile_start = map_tile_start(gpu_mem, index);
+            let (x, y) = (left + ((tile % 32) * 8), (tile / 32) * 8);
+            draw_tile(&mut image, gpu_mem, tile_start, x, y, palette);
+        }
+
+        // Left half of the bar for the background, right half for the window
+        for x in 0..MAP_SIZE {
+            let color = match x < MAP_SIZE / 2 {
+                true if *map_start == bg_map => VIEWPORT_COLOR,
+                false if *map_start == window_map => WINDOW_COLOR,
+                _ => [0x00, 0x00, 0x00, 0x00],
+            };
+            for y in MAP_SIZE..MAP_SIZE + MAP_BAR {
+                image.set_pixel(left + x, y, color);
+            }
+        }
+    }
+
+    let (window_x, window_y) = gpu_mem.get_window_pos();
+    if gpu_mem.is_window_enabled() && window_x <= 166 && window_y <= 143 {
+        let left = if window_map == MAP_STARTS[0] {
+            0
+        } else {
+            MAP_SIZE + MAP_GAP
+        };
+        let size = (167 - usize::from(window_x), 144 - usize::from(window_y));
+        draw_wrapped_rect(
+            &mut image,
+            left,
+            (0, 0),
+            (size.0.min(160), size.1),
+            WINDOW_COLOR,
+        );
+    }
+
+    // Drawn last so the screen area is always visible
+    let left = if bg_map == MAP_STARTS[0] {
+        0
+    } else {
+        MAP_SIZE + MAP_GAP
+    };
+    let scroll = (gpu_mem.scx(), gpu_mem.scy());
+    draw_wrapped_rect(&mut image, left, scroll, (160, 144), VIEWPORT_COLOR);
+
+    return image;
+}
+
+fn describe_map_tile(gpu_mem: &GpuMemory, x: usize, y: usize) -> Option<String> {
+    let (map_start, left) = map_at(x)?;
+    if y >= MAP_SIZE {
+        return None;
+    }
+
+    let (col, row) = ((x - left) / 8, y / 8);
+    let addr = map_start + (row * 32 + col) as u16;
+    let index = gpu_mem.vram[usize::from(addr - VRAM_START)];
+    let tile_addr = map_tile_start(gpu_mem, index) + VRAM_START as usize;
+
+    let mut users = Vec::new();
+    if map_start == gpu_mem.get_bg_tile_map().0 {
+        users.push("BG");
+    }
+    if map_start == gpu_mem.get_window_tile_map().0 {
+        users.push("Window");
+    }
+    let used_by = match users.is_empty() {
+        true => String::new(),
+        false => format!(" ({})", users.join(", ")),
+    };
+
+    return Some(format!(
+        "Map 0x{:04X}{}: ({}, {}) at 0x{:04X}, tile 0x{:02X} at 0x{:04X}",
+        map_start, used_by, col, row, addr, index, tile_addr
+    ));
+}
+
+fn oam_sprite(gpu_mem: &GpuMemory, sprite: usize) -> Sprite {
+    let height = if gpu_mem.is_big_sprite() { 16 } else { 8 };
+    return Sprite::new(&gpu_mem.oam[sprite * 4..(sprite * 4) + 4], height);
+}
+
+// For each sprite, the lines it would be on but isnt drawn because 10 sprites before
+// it in oam are already on that line. Like find_sprites this doesnt care about x, so
+// sprites off the side of the screen still count towards the 10
+fn dropped_lines(gpu_mem: &GpuMemory) -> Vec<Vec<usize>> {
+    let mut dropped = vec![Vec::new(); NUM_SPRITES];
+    for ly in 0..144 {
+        let mut on_line = 0;
+        for (i, lines) in dropped.iter_mut().enumerate() {
+            let sprite = oam_sprite(gpu_mem, i);
+            let (top, height) = (usize::from(sprite.ypos), usize::from(sprite.height));
+            if ly + 16 >= top && ly + 16 < top + height {
+                if on_line == MAX_LINE_SPRITES {
+                    lines.push(ly);
+                } else {
+                    on_line += 1;
+                }
+            }
+        }
+    }
+    return dropped;
+}
+
+// All 40 sprites in oam order, drawn the way they show up on screen (flipped, with
+// their palette). Color 0 is see through so it gets its own color, and sprites that
+// the 10 per line limit stops from being drawn on some line are outlined in red
+fn sprites(gpu_mem: &GpuMemory, grayscale: bool) -> Image {
+    let (width, height) = View::Sprites.size();
+    let mut image = Image::new(width, height);
+    let dropped = dropped_lines(gpu_mem);
+
+    for i in 0..NUM_SPRITES {
+        let sprite = oam_sprite(gpu_mem, i);
+        let mut palette = match (grayscale, sprite.palette_no) {
+            (true, _) => GRAYSCALE,
+            (false, false) => gpu_mem.obp0_colors,
+            (false, true) => gpu_mem.obp1_colors,
+        };
+        palette[0] = SPRITE_CLEAR_COLOR;
+
+        let left = (i % SPRITES_PER_ROW) * SPRITE_CELL.0;
+        let top = (i / SPRITES_PER_ROW) * SPRITE_CELL.1;
+        let sprite_height = usize::from(sprite.height);
+        // 8x16 sprites ignore bit 0 of the tile index
+        let first_tile = match sprite_height {
+            16 => sprite.tile_index & 0xFE,
+            _ => sprite.tile_index,
+        };
+        for y in 0..sprite_height {
+            let row = if sprite.flip_y {
+                sprite_height - 1 - y
+            } else {
+                y
+            };
+            let tile_start = (usize::from(first_tile) + (row / 8)) * BYTES_PER_TILE;
+            for x in 0..8 {
+                let col = if sprite.flip_x { 7 - x } else { x };
+                let color = palette[tile_pixel(gpu_mem, tile_start, col, row % 8)];
+                image.set_pixel(left + 2 + x, top + 2 + y, color);
+            }
+        }
+
+        if !dropped[i].is_empty() {
+            for x in 0..SPRITE_CELL.0 {
+                image.set_pixel(left + x, top, DROPPED_COLOR);
+                image.set_pixel(left + x, top + SPRITE_CELL.1 - 1, DROPPED_COLOR);
+            }
+            for y in 0..SPRITE_CELL.1 {
+                image.set_pixel(left, top + y, DROPPED_COLOR);
+                image.set_pixel(left + SPRITE_CELL.0 - 1, top + y, DROPPED_COLOR);
+            }
+        }
+    }
+    return image;
+}
+
+fn describe_sprite(gpu_mem: &GpuMemory, index: usize) -> String {
+    let sprite = oam_sprite(gpu_mem, index);
+    let mut info = format!(
+        "Sprite {} at 0x{:04X}: X {} Y {}, tile 0x{:02X}, {}",
+        index,
+        OAM_START as usize + (index * 4),
+        sprite.xpos,
+        sprite.ypos,
+        sprite.tile_index,
+        if sprite.palette_no { "OBP1" } else { "OBP0" }
+    );
+    if sprite.flip_x {
+        info.push_str(", flip X");
+    }
+    if sprite.flip_y {
+        info.push_str(", flip Y");
+    }
+    if sprite.bgw_ontop {
+        info.push_str(", behind BG");
+    }
+
+    let dropped = &dropped_lines(gpu_mem)[index];
+    if let (Some(first), Some(last)) = (dropped.first(), dropped.last()) {
+        info.push_str(&format!(
+            ", dropped on {} lines (LY {} - {})",
+            dropped.len(),
+            first,
+            last
+        ));
+    }
+    return info;
+}
+
+#[test]
+fn test_tile_viewer() {
+    let mut gpu_mem = GpuMemory::new();
+    gpu_mem.vram[16] = 0x80; // Tile 1, top left pixel is color 1
+    gpu_mem.vram[383 * 16 + 15] = 0x01; // Tile 383, bottom right pixel is color 2
+
+    let image = render(&gpu_mem, View::Tiles, true);
+    assert_eq!((image.width, image.height), (128, 192));
+    let pixel = |x: usize, y: usize| image.pixels[((y * 128) + x) * 4];
+    assert_eq!(pixel(8, 0), 0xAA);
+    assert_eq!(pixel(9, 0), 0xFF);
+    assert_eq!(pixel(127, 191), 0x55);
+
+    assert_eq!(
+        describe(&gpu_mem, View::Tiles, 8, 0).unwrap(),
+        "Tile 1 at 0x8010, 0x8000 index 0x01"
+    );
+    assert_eq!(
+        describe(&gpu_mem, View::Tiles, 0, 64).unwrap(),
+        "Tile 128 at 0x8800, 0x8000 index 0x80, 0x8800 index 0x80"
+    );
+    assert_eq!(
+        describe(&gpu_mem, View::Tiles, 127, 191).unwrap(),
+        "Tile 383 at 0x97F0, 0x8800 index 0x7F"
+    );
+    assert_eq!(describe(&gpu_mem, View::Tiles, 128, 0), None);
+}
+
+#[test]
+fn test_tilemap_viewer() {
+    let mut gpu_mem = GpuMemory::new();
+    gpu_mem.lcdc = 0x80 | 0x40 | 0x20; // Window on and using 0x9C00, tile data from 0x8800
+    gpu_mem.vram[0x1000] = 0x80; // Tile 0 from 0x9000, top left pixel is color 1
+    gpu_mem.vram[0x0801] = 0x80; // Tile 0x80 from 0x8800, top left pixel is color 2
+    gpu_mem.vram[0x1C00 + 33] = 0x80; // 0x9C00 map, column 1 row 1 uses tile 0x80
+    gpu_mem.scx = 250;
+    gpu_mem.scy = 10;
+    gpu_mem.wx = 87;
+    gpu_mem.wy = 100;
+
+    let image = render(&gpu_mem, View::Tilemaps, true);
+    assert_eq!((image.width, image.height), (520, 260));
+    let pixel =
+        |x: usize, y: usize| image.pixels[((y * 520) + x) * 4..((y * 520) + x) * 4 + 4].to_vec();
+
+    // Map 0x9800 is all tile 0, map 0x9C00 has tile 0x80 at (1, 1)
+    assert_eq!(pixel(8, 0), vec![0xAA, 0xAA, 0xAA, 0xFF]);
+    assert_eq!(pixel(264 + 8, 8), vec![0x55, 0x55, 0x55, 0xFF]);
+
+    // Viewport wraps around to the left side of the background map
+    assert_eq!(pixel(250, 10), VIEWPORT_COLOR.to_vec());
+    assert_eq!(pixel((250 + 159) % 256, 10 + 143), VIEWPORT_COLOR.to_vec());
+    assert_eq!(pixel(0, 10), VIEWPORT_COLOR.to_vec());
+
+    // Window covers 80x44 of the screen
+    assert_eq!(pixel(264 + 79, 43), WINDOW_COLOR.to_vec());
+    assert_eq!(pixel(264 + 80, 43), pixel(264 + 81, 43));
+
+    // Bars under the maps
+    assert_eq!(pixel(0, 257), VIEWPORT_COLOR.to_vec());
+    assert_eq!(pixel(200, 257), vec![0; 4]);
+    assert_eq!(pixel(264 + 200, 257), WINDOW_COLOR.to_vec());
+
+    assert_eq!(
+        describe(&gpu_mem, View::Tilemaps, 264 + 9, 9).unwrap(),
+        "Map 0x9C00 (Window): (1, 1) at 0x9C21, tile 0x80 at 0x8800"
+    );
+    assert_eq!(
+        describe(&gpu_mem, View::Tilemaps, 0, 0).unwrap(),
+        "Map 0x9800 (BG): (0, 0) at 0x9800, tile 0x00 at 0x9000"
+    );
+    assert_eq!(describe(&gpu_mem, View::Tilemaps, 258, 0), None);
+}
+
+#[test]
+fn test_sprite_viewer() {
+    let mut gpu_mem = GpuMemory::new();
+    gpu_mem.lcdc = 0x80 | 0x04; // 8x16 sprites
+    gpu_mem.vram[2 * 16] = 0x80; // Tile 2, top left pixel is color 1
+    gpu_mem.vram[3 * 16 + 15] = 0x01; // Tile 3, bottom right pixel is color 2
+
+    // 11 sprites on lines 0 - 15, the last one in oam doesnt get drawn
+    for i in 0..11 {
+        gpu_mem.oam[i * 4..(i * 4) + 4].copy_from_slice(&[16, 8 * i as u8, 3, 0x00]);
+    }
+    gpu_mem.oam[1 * 4 + 3] = 0x60; // Sprite 1 is flipped both ways
+
+    let image = render(&gpu_mem, View::Sprites, true);
+    assert_eq!((image.width, image.height), (96, 100));
+    let pixel =
+        |x: usize, y: usize| image.pixels[((y * 96) + x) * 4..((y * 96) + x) * 4 + 4].to_vec();
+
+    // Tile 3 is ignored by 8x16 sprites, tile 2 is on top
+    assert_eq!(pixel(2, 2), GRAYSCALE[1].to_vec());
+    assert_eq!(pixel(9, 17), GRAYSCALE[2].to_vec());
+    assert_eq!(pixel(3, 2), SPRITE_CLEAR_COLOR.to_vec());
+    assert_eq!(pixel(12 + 9, 17), GRAYSCALE[1].to_vec());
+    assert_eq!(pixel(12 + 2, 2), GRAYSCALE[2].to_vec());
+
+    // Sprite 10 is on the second row and outlined, sprite 9 isnt
+    assert_eq!(pixel(24, 20), DROPPED_COLOR.to_vec());
+    assert_eq!(pixel(12, 20), vec![0; 4]);
+    assert_eq!(pixel(12 * 8 - 1, 0), vec![0; 4]);
+
+    assert_eq!(
+        describe(&gpu_mem, View::Sprites, 12 + 5, 5).unwrap(),
+        "Sprite 1 at 0xFE04: X 8 Y 16, tile 0x03, OBP0, flip X, flip Y"
+    );
+    assert_eq!(
+        describe(&gpu_mem, View::Sprites, 24 + 5, 25).unwrap(),
+        "Sprite 10 at 0xFE28: X 80 Y 16, tile 0x03, OBP0, dropped on 16 lines (LY 0 - 15)"
+    );
+    assert_eq!(
+        listing(&gpu_mem, View::Sprites).unwrap().lines().count(),
+        40
+    );
+    assert_eq!(listing(&gpu_mem, View::Tiles), None);
+}
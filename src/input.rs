@@ -0,0 +1,368 @@
+/*
+    Everything that comes from the person playing. SDL events get drained once per
+    frame and turned into the set of gameboy buttons being held, which is what the
+    joypad gets to see. The joypad itself knows nothing about keyboards or controllers.
+
+    A button counts as held if its held on the keyboard or on a controller.
+
+    Anything meant for the emulator instead of the game (hotkeys, debug windows
+    being closed or hovered over) gets handed back from poll as a Command.
+    Hotkeys come back when pressed, and again when released for the ones that
+    only last while held (fast_forward, rewind).
+*/
+
+mod controller;
+
+use crate::config::{ButtonNames, HotkeyNames};
+use crate::graphics::gpu_memory::Layer;
+use crate::joypad::Button;
+pub use controller::{ControllerBindings, Controllers};
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::Keycode;
+use sdl2::EventPump;
+use std::collections::HashSet;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Hotkey {
+    Quit,
+    TileViewer,
+    TilemapViewer,
+    SpriteViewer,
+    ToggleLayer(Layer),
+    Screenshot,
+    RecordVideo,
+    RecordMovie,
+    NextPalette,
+    NextScaler,
+    ToggleFrameBlend,
+    ToggleLcd,
+    Fullscreen,
+    ScaleUp,
+    ScaleDown,
+    Pause,
+    FrameAdvance,
+    Reset,
+    FastForward,
+    ToggleUncapped,
+    SpeedUp,
+    SpeedDown,
+    Rewind,
+    PerfOverlay,
+}
+
+impl Hotkey {
+    const ALL: [Hotkey; 26] = [
+        Hotkey::Quit,
+        Hotkey::TileViewer,
+        Hotkey::TilemapViewer,
+        Hotkey::SpriteViewer,
+        Hotkey::ToggleLayer(Layer::Background),
+        Hotkey::ToggleLayer(Layer::Window),
+        Hotkey::ToggleLayer(Layer::Sprites),
+        Hotkey::Screenshot,
+        Hotkey::RecordVideo,
+        Hotkey::RecordMovie,
+        Hotkey::NextPalette,
+        Hotkey::NextScaler,
+        Hotkey::ToggleFrameBlend,
+        Hotkey::ToggleLcd,
+        Hotkey::Fullscreen,
+        Hotkey::ScaleUp,
+        Hotkey::ScaleDown,
+        Hotkey::Pause,
+        Hotkey::FrameAdvance,
+        Hotkey::Reset,
+        Hotkey::FastForward,
+        Hotkey::ToggleUncapped,
+        Hotkey::SpeedUp,
+        Hotkey::SpeedDown,
+        Hotkey::Rewind,
+        Hotkey::PerfOverlay,
+    ];
+
+    // Name used in the config file and with --hotkey
+    fn name(self: &Self) -> &'static str {
+        return match self {
+            Hotkey::Quit => "quit",
+            Hotkey::TileViewer => "tile_viewer",
+            Hotkey::TilemapViewer => "tilemap_viewer",
+            Hotkey::SpriteViewer => "sprite_viewer",
+            Hotkey::ToggleLayer(Layer::Background) => "toggle_background",
+            Hotkey::ToggleLayer(Layer::Window) => "toggle_window",
+            Hotkey::ToggleLayer(Layer::Sprites) => "toggle_sprites",
+            Hotkey::Screenshot => "screenshot",
+            Hotkey::RecordVideo => "record_video",
+            Hotkey::RecordMovie => "record_movie",
+            Hotkey::NextPalette => "next_palette",
+            Hotkey::NextScaler => "next_scaler",
+            Hotkey::ToggleFrameBlend => "toggle_frame_blend",
+            Hotkey::ToggleLcd => "toggle_lcd",
+            Hotkey::Fullscreen => "fullscreen",
+            Hotkey::ScaleUp => "scale_up",
+            Hotkey::ScaleDown => "scale_down",
+            Hotkey::Pause => "pause",
+            Hotkey::FrameAdvance => "frame_advance",
+            Hotkey::Reset => "reset",
+            Hotkey::FastForward => "fast_forward",
+            Hotkey::ToggleUncapped => "toggle_uncapped",
+            Hotkey::SpeedUp => "speed_up",
+            Hotkey::SpeedDown => "speed_down",
+            Hotkey::Rewind => "rewind",
+            Hotkey::PerfOverlay => "perf_overlay",
+        };
+    }
+}
+
+pub enum Command {
+    Hotkey(Hotkey),
+    HotkeyReleased(Hotkey),
+    CloseWindow(u32),           // A window other than the main one was closed
+    MouseMotion(u32, i32, i32), // Window id, x, y
+    WindowKey(u32, Keycode),    // Key pressed while a window other than the main one had focus
+}
+
+pub struct KeyBindings {
+    pub up: Keycode,
+    pub down: Keycode,
+    pub left: Keycode,
+    pub right: Keycode,
+    pub a: Keycode,
+    pub b: Keycode,
+    pub start: Keycode,
+    pub select: Keycode,
+    pub hotkeys: Vec<(Keycode, Hotkey)>,
+}
+
+impl KeyBindings {
+    pub fn new() -> KeyBindings {
+        return KeyBindings {
+            up: Keycode::Up,
+            down: Keycode::Down,
+            left: Keycode::Left,
+            right: Keycode::Right,
+            a: Keycode::F,
+            b: Keycode::D,
+            start: Keycode::RShift,
+            select: Keycode::Return,
+            hotkeys: vec![
+                (Keycode::Escape, Hotkey::Quit),
+                (Keycode::F1, Hotkey::TileViewer),
+                (Keycode::F2, Hotkey::TilemapViewer),
+                (Keycode::F3, Hotkey::SpriteViewer),
+                (Keycode::Num1, Hotkey::ToggleLayer(Layer::Background)),
+                (Keycode::Num2, Hotkey::ToggleLayer(Layer::Window)),
+                (Keycode::Num3, Hotkey::ToggleLayer(Layer::Sprites)),
+                (Keycode::F12, Hotkey::Screenshot),
+                (Keycode::F9, Hotkey::RecordVideo),
+                (Keycode::F5, Hotkey::RecordMovie),
+                (Keycode::F7, Hotkey::NextPalette),
+                (Keycode::F6, Hotkey::NextScaler),
+                (Keycode::F8, Hotkey::ToggleFrameBlend),
+                (Keycode::F4, Hotkey::ToggleLcd),
+                (Keycode::F11, Hotkey::Fullscreen),
+                (Keycode::Equals, Hotkey::ScaleUp),
+                (Keycode::Minus, Hotkey::ScaleDown),
+                (Keycode::P, Hotkey::Pause),
+                (Keycode::N, Hotkey::FrameAdvance),
+                (Keycode::Backspace, Hotkey::Reset),
+                (Keycode::Tab, Hotkey::FastForward),
+                (Keycode::Backquote, Hotkey::ToggleUncapped),
+                (Keycode::RightBracket, Hotkey::SpeedUp),
+                (Keycode::LeftBracket, Hotkey::SpeedDown),
+                (Keycode::R, Hotkey::Rewind),
+                (Keycode::F10, Hotkey::PerfOverlay),
+            ],
+        };
+    }
+
+    pub fn from_config(keys: &ButtonNames, hotkeys: &HotkeyNames) -> Result<KeyBindings, String> {
+        let find = |name: &str| match Keycode::from_name(name) {
+            Some(key) => Ok(key),
+            None => Err(format!("Unknown key name: {}", name)),
+        };
+        let mut bound_hotkeys = Vec::new();
+        for hotkey in Hotkey::ALL {
+            match hotkeys.get(hotkey.name()) {
+                Some(name) => bound_hotkeys.push((find(name)?, hotkey)),
+                None => return Err(format!("No key for hotkey: {}", hotkey.name())),
+            }
+        }
+        let bindings = KeyBindings {
+            up: find(&keys.up)?,
+            down: find(&keys.down)?,
+            left: find(&keys.left)?,
+            right: find(&keys.right)?,
+            a: find(&keys.a)?,
+            b: find(&keys.b)?,
+            start: find(&keys.start)?,
+            select: find(&keys.select)?,
+            hotkeys: bound_hotkeys,
+        };
+
+        // Config::parse only knows the names, two names can still be the same key
+        // and --key or --hotkey can bind one again
+        let mut used = vec![
+            (bindings.up, "up"),
+            (bindings.down, "down"),
+            (bindings.left, "left"),
+            (bindings.right, "right"),
+            (bindings.a, "a"),
+            (bindings.b, "b"),
+            (bindings.start, "start"),
+            (bindings.select, "select"),
+        ];
+        used.extend(
+            bindings
+                .hotkeys
+                .iter()
+                .map(|(key, hotkey)| (*key, hotkey.name())),
+        );
+        for (i, (key, name)) in used.iter().enumerate() {
+            if let Some((_, other)) = used[..i].iter().find(|(k, _)| k == key) {
+                return Err(format!(
+                    "{} and {} are both bound to {}",
+                    other,
+                    name,
+                    key.name()
+                ));
+            }
+        }
+        return Ok(bindings);
+    }
+
+    fn hotkey(self: &Self, key: Keycode) -> Option<Hotkey> {
+        return self
+            .hotkeys
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, hotkey)| *hotkey);
+    }
+
+    fn button(self: &Self, key: Keycode) -> Option<Button> {
+        return match key {
+            k if k == self.right => Some(Button::Right),
+            k if k == self.left => Some(Button::Left),
+            k if k == self.up => Some(Button::Up),
+            k if k == self.down => Some(Button::Down),
+            k if k == self.a => Some(Button::A),
+            k if k == self.b => Some(Button::B),
+            k if k == self.select => Some(Button::Select),
+            k if k == self.start => Some(Button::Start),
+            _ => None,
+        };
+    }
+}
+
+pub struct Input {
+    event_pump: EventPump,
+    bindings: KeyBindings,
+    controllers: Option<Controllers>,
+    keys_held: HashSet<Button>,
+    main_window: Option<u32>,
+}
+
+impl Input {
+    pub fn new(event_pump: EventPump, bindings: KeyBindings) -> Input {
+        return Input {
+            event_pump: event_pump,
+            bindings: bindings,
+            controllers: None,
+            keys_held: HashSet::new(),
+            main_window: None,
+        };
+    }
+
+    pub fn with_controllers(mut self: Self, controllers: Controllers) -> Input {
+        self.controllers = Some(controllers);
+        return self;
+    }
+
+    // Events from other windows (debug viewers) dont go to the game
+    pub fn set_main_window(self: &mut Self, window_id: u32) {
+        self.main_window = Some(window_id);
+    }
+
+    // Handles every event that arrived since the last call and
+    // returns whatever the emulator needs to deal with
+    pub fn poll(self: &mut Self) -> Vec<Command> {
+        let mut commands = Vec::new();
+        let main_window = self.main_window;
+        let is_main_window = |window_id: u32| main_window.map_or(true, |id| id == window_id);
+
+        for event in self.event_pump.poll_iter() {
+            match event {
+                Event::Quit { .. } => commands.push(Command::Hotkey(Hotkey::Quit)),
+                Event::Window {
+                    window_id,
+                    win_event: WindowEvent::Close,
+                    ..
+                } => {
+                    if is_main_window(window_id) {
+                        commands.push(Command::Hotkey(Hotkey::Quit));
+                    } else {
+                        commands.push(Command::CloseWindow(window_id));
+                    }
+                }
+                Event::MouseMotion {
+                    window_id, x, y, ..
+                } if !is_main_window(window_id) => {
+                    commands.push(Command::MouseMotion(window_id, x, y));
+                }
+                Event::KeyDown {
+                    keycode: Some(x),
+                    window_id,
+                    ..
+                } if !is_main_window(window_id) && self.bindings.hotkey(x).is_none() => {
+                    commands.push(Command::WindowKey(window_id, x));
+                }
+                Event::KeyDown {
+                    keycode: Some(x),
+                    repeat: false,
+                    ..
+                } if self.bindings.hotkey(x).is_some() => {
+                    commands.push(Command::Hotkey(self.bindings.hotkey(x).unwrap()));
+                }
+                Event::KeyDown {
+                    keycode: Some(x), ..
+                } => {
+                    if let Some(button) = self.bindings.button(x) {
+                        self.keys_held.insert(button);
+                    }
+                }
+                Event::KeyUp {
+                    keycode: Some(x), ..
+                } if self.bindings.hotkey(x).is_some() => {
+                    commands.push(Command::HotkeyReleased(self.bindings.hotkey(x).unwrap()));
+                }
+                Event::KeyUp {
+                    keycode: Some(x), ..
+                } => {
+                    if let Some(button) = self.bindings.button(x) {
+                        self.keys_held.remove(&button);
+                    }
+                }
+                _ => {
+                    if let Some(controllers) = &mut self.controllers {
+                        controllers.handle_event(&event);
+                    }
+                }
+            }
+        }
+
+        return commands;
+    }
+
+    pub fn get_buttons(self: &Self) -> HashSet<Button> {
+        let mut buttons = self.keys_held.clone();
+        if let Some(controllers) = &self.controllers {
+            buttons.extend(controllers.get_buttons());
+        }
+        return buttons;
+    }
+
+    pub fn set_rumble(self: &mut Self, on: bool) {
+        if let Some(controllers) = &mut self.controllers {
+            controllers.set_rumble(on);
+        }
+    }
+}
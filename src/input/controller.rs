@@ -0,0 +1,187 @@
+/*
+    Game controllers through SDL's GameController api. SDL already knows the
+    layout of most common pads so we only need to deal with its button names.
+
+    Controllers can be plugged in and out while the emulator is running. SDL sends
+    a ControllerDeviceAdded event for every controller already connected at startup
+    aswell, so there is no need to go looking for them ourselves.
+
+    The left stick and the dpad both control the directions, and a direction
+    counts as pressed if either of them are pressing it.
+*/
+
+use crate::config::ControllerConfig;
+use crate::joypad::Button;
+use sdl2::controller::{Axis, Button as PadButton, GameController};
+use sdl2::event::Event;
+use sdl2::GameControllerSubsystem;
+use std::collections::{HashMap, HashSet};
+
+const RUMBLE_STRENGTH: u16 = 0xFFFF;
+const RUMBLE_DURATION_MS: u32 = 1000; // Gets refreshed while the cart keeps the motor on
+
+pub struct ControllerBindings {
+    pub up: PadButton,
+    pub down: PadButton,
+    pub left: PadButton,
+    pub right: PadButton,
+    pub a: PadButton,
+    pub b: PadButton,
+    pub start: PadButton,
+    pub select: PadButton,
+    pub deadzone: i16,
+    pub rumble: bool,
+}
+
+impl ControllerBindings {
+    pub fn new() -> ControllerBindings {
+        return ControllerBindings {
+            up: PadButton::DPadUp,
+            down: PadButton::DPadDown,
+            left: PadButton::DPadLeft,
+            right: PadButton::DPadRight,
+            a: PadButton::B,
+            b: PadButton::A,
+            start: PadButton::Start,
+            select: PadButton::Back,
+            deadzone: 8000,
+            rumble: true,
+        };
+    }
+
+    pub fn from_config(config: &ControllerConfig) -> Result<ControllerBindings, String> {
+        let find = |name: &str| match PadButton::from_string(name) {
+            Some(button) => Ok(button),
+            None => Err(format!("Unknown controller button name: {}", name)),
+        };
+        let buttons = &config.buttons;
+        return Ok(ControllerBindings {
+            up: find(&buttons.up)?,
+            down: find(&buttons.down)?,
+            left: find(&buttons.left)?,
+            right: find(&buttons.right)?,
+            a: find(&buttons.a)?,
+            b: find(&buttons.b)?,
+            start: find(&buttons.start)?,
+            select: find(&buttons.select)?,
+            deadzone: config.deadzone,
+            rumble: config.rumble,
+        });
+    }
+
+    fn button(self: &Self, pad_button: PadButton) -> Option<Button> {
+        return match pad_button {
+            b if b == self.right => Some(Button::Right),
+            b if b == self.left => Some(Button::Left),
+            b if b == self.up => Some(Button::Up),
+            b if b == self.down => Some(Button::Down),
+            b if b == self.a => Some(Button::A),
+            b if b == self.b => Some(Button::B),
+            b if b == self.select => Some(Button::Select),
+            b if b == self.start => Some(Button::Start),
+            _ => None,
+        };
+    }
+}
+
+pub struct Controllers {
+    subsystem: GameControllerSubsystem,
+    pads: HashMap<u32, GameController>, // Keyed by joystick instance id
+    bindings: ControllerBindings,
+    held: HashSet<Button>,  // Held with the pad buttons
+    stick: HashSet<Button>, // Directions held by the left stick
+    rumbling: bool,
+}
+
+impl Controllers {
+    pub fn new(subsystem: GameControllerSubsystem) -> Controllers {
+        return Controllers {
+            subsystem: subsystem,
+            pads: HashMap::new(),
+            bindings: ControllerBindings::new(),
+            held: HashSet::new(),
+            stick: HashSet::new(),
+            rumbling: false,
+        };
+    }
+
+    pub fn set_bindings(self: &mut Self, bindings: ControllerBindings) {
+        self.bindings = bindings;
+    }
+
+    pub fn get_buttons(self: &Self) -> HashSet<Button> {
+        return self.held.union(&self.stick).copied().collect();
+    }
+
+    pub fn handle_event(self: &mut Self, event: &Event) {
+        match event {
+            Event::ControllerDeviceAdded { which, .. } => self.add_pad(*which),
+            Event::ControllerDeviceRemoved { which, .. } => self.remove_pad(*which),
+            Event::ControllerButtonDown { button, .. } => {
+                if let Some(b) = self.bindings.button(*button) {
+                    self.held.insert(b);
+                }
+            }
+            Event::ControllerButtonUp { button, .. } => {
+                if let Some(b) = self.bindings.button(*button) {
+                    self.held.remove(&b);
+                }
+            }
+            Event::ControllerAxisMotion { axis, value, .. } => self.move_stick(*axis, *value),
+            _ => {}
+        }
+    }
+
+    fn add_pad(self: &mut Self, joystick_index: u32) {
+        match self.subsystem.open(joystick_index) {
+            Ok(pad) => {
+                println!("Controller connected: {}", pad.name());
+                self.pads.insert(pad.instance_id(), pad);
+            }
+            Err(e) => println!("Couldnt open controller {}: {}", joystick_index, e),
+        }
+    }
+
+    fn remove_pad(self: &mut Self, instance_id: u32) {
+        if let Some(pad) = self.pads.remove(&instance_id) {
+            println!("Controller disconnected: {}", pad.name());
+        }
+        // Dont leave buttons stuck down from the pad that was unplugged
+        if self.pads.is_empty() {
+            self.held.clear();
+            self.stick.clear();
+        }
+    }
+
+    // Anything inside of the deadzone lets go of both directions on that axis
+    fn move_stick(self: &mut Self, axis: Axis, value: i16) {
+        let (negative, positive) = match axis {
+            Axis::LeftX => (Button::Left, Button::Right),
+            Axis::LeftY => (Button::Up, Button::Down),
+            _ => return,
+        };
+        self.stick.remove(&negative);
+        self.stick.remove(&positive);
+
+        if value < -self.bindings.deadzone {
+            self.stick.insert(negative);
+        } else if value > self.bindings.deadzone {
+            self.stick.insert(positive);
+        }
+    }
+
+    // The cartridge controls a motor so turn it on or off on every pad.
+    // SDL rumble has a duration so keep refreshing it while the motor is on
+    pub fn set_rumble(self: &mut Self, on: bool) {
+        if !self.bindings.rumble || (!on && !self.rumbling) {
+            return;
+        }
+        self.rumbling = on;
+
+        let strength = if on { RUMBLE_STRENGTH } else { 0 };
+        for pad in self.pads.values_mut() {
+            // Not every controller has rumble and thats fine
+            let _ = pad.set_rumble(strength, strength, RUMBLE_DURATION_MS);
+        }
+    }
+}
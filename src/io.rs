@@ -2,6 +2,9 @@
 // This is for both registers that have and dont have dedicated purposes
 // https://github.com/Gekkio/mooneye-test-suite/blob/main/acceptance/bits/unused_hwio-GS.s#L21
 
+use crate::model::Model;
+use crate::state::{StateReader, StateWriter};
+
 pub const IO_START: u16 = 0xFF00;
 pub const IF_REG: u16 = 0xFF0F;
 pub const DIV_REG: u16 = 0xFF04; // Writing any value to this register resets it to 0
@@ -37,6 +40,16 @@ impl Io {
         }
     }
 
+    pub fn save_state(self: &Self, state: &mut StateWriter) {
+        state.write_bytes(&self.io);
+        state.write_bool(self.ifired_dirty);
+    }
+
+    pub fn load_state(self: &mut Self, state: &mut StateReader) {
+        state.read_bytes(&mut self.io);
+        self.ifired_dirty = state.read_bool();
+    }
+
     pub fn clean_ifired(self: &mut Self) {
         self.ifired_dirty = false;
     }
@@ -71,7 +84,7 @@ impl Io {
         self.io[ifired] = self.io[ifired] | 0xE1;
     }
 
-    pub fn dmg_init(self: &mut Self) {
+    pub fn init(self: &mut Self, model: Model) {
         self.io[usize::from(IF_REG - IO_START)] = 0xE1;
 
         // Not sure
@@ -97,5 +110,13 @@ impl Io {
         self.io[usize::from(0xFF73 - IO_START)] = 0xFF;
         self.io[usize::from(0xFF74 - IO_START)] = 0xFF; // R/W in cgb, otherwise read only as 0xFF
         self.io[usize::from(0xFF75 - IO_START)] = 0xFF;
+
+        // The cgb boot rom leaves its own registers (KEY1, VBK, SVBK) in a different state
+        // https://gbdev.io/pandocs/Power_Up_Sequence.html#hardware-registers
+        if model.is_cgb() {
+            self.io[usize::from(0xFF4D - IO_START)] = 0x7E;
+            self.io[usize::from(0xFF4F - IO_START)] = 0xFE;
+            self.io[usize::from(0xFF70 - IO_START)] = 0xF8;
+        }
     }
 }
@@ -7,49 +7,90 @@
     Bit 2 - P12 Input: Up    or Select   (0=Pressed) (Read Only)
     Bit 1 - P11 Input: Left  or B        (0=Pressed) (Read Only)
     Bit 0 - P10 Input: Right or A        (0=Pressed) (Read Only)
+
+    The buttons are a matrix. Each selected group pulls the P10-P13 lines low for the
+    buttons held in that group, so with both groups selected the lines are shared.
+    The joypad interrupt fires when any of those lines go from high to low, which can
+    happen by pressing a button or by selecting a group that has a button held.
+    https://gbdev.io/pandocs/Joypad_Input.html
+
+    The frontend decides which buttons are held (keyboard, controller, movie...)
+    and hands them over once per frame with set_buttons.
 */
 
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
-use sdl2::EventPump;
+use crate::state::{StateReader, StateWriter};
+use std::collections::HashSet;
 
 pub const JOYP_REG: u16 = 0xFF00;
 
+const SELECT_DIRECTIONS: u8 = 0x10;
+const SELECT_ACTIONS: u8 = 0x20;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Button {
+    Right,
+    Left,
+    Up,
+    Down,
+    A,
+    B,
+    Select,
+    Start,
+}
+
+impl Button {
+    const ALL: [Button; 8] = [
+        Button::Right,
+        Button::Left,
+        Button::Up,
+        Button::Down,
+        Button::A,
+        Button::B,
+        Button::Select,
+        Button::Start,
+    ];
+
+    // Returns the select bit of the group the button is in and the line it pulls low
+    fn line(self: &Self) -> (u8, u8) {
+        return match self {
+            Button::Right => (SELECT_DIRECTIONS, 0x01),
+            Button::Left => (SELECT_DIRECTIONS, 0x02),
+            Button::Up => (SELECT_DIRECTIONS, 0x04),
+            Button::Down => (SELECT_DIRECTIONS, 0x08),
+            Button::A => (SELECT_ACTIONS, 0x01),
+            Button::B => (SELECT_ACTIONS, 0x02),
+            Button::Select => (SELECT_ACTIONS, 0x04),
+            Button::Start => (SELECT_ACTIONS, 0x08),
+        };
+    }
+}
+
 pub struct Joypad {
-    event_pump: Option<EventPump>,
-    joyp: u8,
-    directs: u8,
-    actions: u8,
-    high_to_low: bool,
-    something_selected: bool,
+    select: u8,               // P14 and P15 as written by the game
+    lines: u8,                // P10 - P13 (0 = pulled low)
+    pressed: HashSet<Button>, // Buttons currently held down
+    interrupt: bool,          // A line went from high to low since the last check
 }
 
 impl Joypad {
     pub fn new() -> Joypad {
         return Joypad {
-            event_pump: None,
-            joyp: 0xCF,
-            directs: 0x0F,
-            actions: 0x0F,
-            high_to_low: false,
-            something_selected: false,
+            select: 0x00,
+            lines: 0x0F,
+            pressed: HashSet::new(),
+            interrupt: false,
         };
     }
 
     pub fn dmg_init(self: &mut Self) {
-        self.joyp = 0xCF;
-    }
-
-    pub fn set_joypad(self: &mut Self, event_pump: EventPump) {
-        self.event_pump = Some(event_pump);
+        self.select = 0x00;
+        self.update_lines();
+        self.interrupt = false;
     }
 
     pub fn read_byte(self: &Self, addr: u16) -> u8 {
-        if !self.something_selected {
-            return self.joyp | 0x0F;
-        }
         let byte = match addr {
-            JOYP_REG => self.joyp,
+            JOYP_REG => 0xC0 | self.select | self.lines,
             _ => panic!("Joypad cannot read from addr: {:04X}", addr),
         };
         return byte;
@@ -58,84 +99,111 @@ impl Joypad {
     pub fn write_byte(self: &mut Self, addr: u16, data: u8) {
         match addr {
             JOYP_REG => {
-                self.joyp = (data & 0x30) | (self.joyp & 0xCF);
-                self.something_selected = (self.joyp & 0x20 == 0x20) ^ (self.joyp & 0x10 == 0x10);
+                self.select = data & (SELECT_DIRECTIONS | SELECT_ACTIONS);
+                self.update_lines();
             }
             _ => panic!("Joypad cannot write addr: {:04X}", addr),
         };
     }
 
-    pub fn is_joypad_interrupt(self: &Self) -> bool {
-        return self.something_selected && self.high_to_low;
+    pub fn set_buttons(self: &mut Self, pressed: HashSet<Button>) {
+        self.pressed = pressed;
+        self.update_lines();
+    }
+
+    pub fn get_buttons(self: &Self) -> &HashSet<Button> {
+        return &self.pressed;
     }
 
-    pub fn update_input(self: &mut Self) -> bool {
-        let mut should_exit = false;
-
-        if let Some(joypad) = &mut self.event_pump {
-            let event = joypad.poll_event();
-
-            if let Some(e) = event {
-                match e {
-                    Event::Quit { .. }
-                    | Event::KeyDown {
-                        keycode: Some(Keycode::Escape),
-                        ..
-                    } => {
-                        should_exit = true;
-                    }
-                    Event::KeyDown {
-                        keycode: Some(x), ..
-                    } => {
-                        self.handle_keydown_event(x);
-                    }
-                    Event::KeyUp {
-                        keycode: Some(x), ..
-                    } => {
-                        self.handle_keyup_event(x);
-                    }
-                    _ => self.high_to_low = false,
-                }
+    // The held buttons are saved as one bit each, in the order of Button::ALL
+    pub fn save_state(self: &Self, state: &mut StateWriter) {
+        let mut pressed = 0;
+        for (i, button) in Button::ALL.iter().enumerate() {
+            if self.pressed.contains(button) {
+                pressed |= 1 << i;
             }
         }
+        state.write_u8(self.select);
+        state.write_u8(self.lines);
+        state.write_u8(pressed);
+        state.write_bool(self.interrupt);
+    }
 
-        if self.joyp & 0x10 == 0x00 {
-            self.joyp = (self.joyp & 0xF0) | self.directs;
-        }
-        if self.joyp & 0x20 == 0x00 {
-            self.joyp = (self.joyp & 0xF0) | self.actions;
-        }
+    pub fn load_state(self: &mut Self, state: &mut StateReader) {
+        self.select = state.read_u8();
+        self.lines = state.read_u8();
+        let pressed = state.read_u8();
+        self.pressed = Button::ALL
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| pressed & (1 << i) != 0)
+            .map(|(_, button)| *button)
+            .collect();
+        self.interrupt = state.read_bool();
+    }
 
-        return should_exit;
+    // Clears the interrupt once its been seen so it only gets requested once per edge
+    pub fn take_interrupt(self: &mut Self) -> bool {
+        let interrupt = self.interrupt;
+        self.interrupt = false;
+        return interrupt;
     }
 
-    fn handle_keydown_event(self: &mut Self, key: Keycode) {
-        self.high_to_low = true;
-        match key {
-            Keycode::Right => self.directs &= !(1 << 0),
-            Keycode::F => self.actions &= !(1 << 0),
-            Keycode::Left => self.directs &= !(1 << 1),
-            Keycode::D => self.actions &= !(1 << 1),
-            Keycode::Up => self.directs &= !(1 << 2),
-            Keycode::Return => self.actions &= !(1 << 2),
-            Keycode::Down => self.directs &= !(1 << 3),
-            Keycode::RShift => self.actions &= !(1 << 3),
-            _ => self.high_to_low = false,
+    fn update_lines(self: &mut Self) {
+        let mut lines = 0x0F;
+        for button in self.pressed.iter() {
+            let (group, line) = button.line();
+            if self.select & group == 0 {
+                lines &= !line;
+            }
         }
-    }
 
-    fn handle_keyup_event(self: &mut Self, key: Keycode) {
-        self.high_to_low = false;
-        match key {
-            Keycode::Right => self.directs |= 1 << 0,
-            Keycode::F => self.actions |= 1 << 0,
-            Keycode::Left => self.directs |= 1 << 1,
-            Keycode::D => self.actions |= 1 << 1,
-            Keycode::Up => self.directs |= 1 << 2,
-            Keycode::Return => self.actions |= 1 << 2,
-            Keycode::Down => self.directs |= 1 << 3,
-            Keycode::RShift => self.actions |= 1 << 3,
-            _ => self.high_to_low = false,
+        if self.lines & !lines != 0 {
+            self.interrupt = true;
         }
+        self.lines = lines;
     }
 }
+
+#[test]
+fn test_joypad_lines() {
+    let mut joypad = Joypad::new();
+    joypad.write_byte(JOYP_REG, 0x20); // Directions
+    joypad.set_buttons(HashSet::from([Button::Left, Button::A]));
+    assert_eq!(joypad.read_byte(JOYP_REG), 0xED);
+
+    joypad.write_byte(JOYP_REG, 0x10); // Actions
+    assert_eq!(joypad.read_byte(JOYP_REG), 0xDE);
+
+    joypad.write_byte(JOYP_REG, 0x00); // Both groups share the lines
+    assert_eq!(joypad.read_byte(JOYP_REG), 0xCC);
+
+    joypad.write_byte(JOYP_REG, 0x30); // Nothing selected
+    assert_eq!(joypad.read_byte(JOYP_REG), 0xFF);
+}
+
+#[test]
+fn test_joypad_interrupt() {
+    let mut joypad = Joypad::new();
+    joypad.write_byte(JOYP_REG, 0x10); // Actions
+    joypad.take_interrupt();
+
+    // Button in a group that isnt selected doesnt change any lines
+    joypad.set_buttons(HashSet::from([Button::Up]));
+    assert!(!joypad.take_interrupt());
+
+    // Selecting the group with the held button pulls a line low
+    joypad.write_byte(JOYP_REG, 0x20);
+    assert!(joypad.take_interrupt());
+
+    // Holding it doesnt fire again, releasing doesnt fire either
+    joypad.set_buttons(HashSet::from([Button::Up]));
+    assert!(!joypad.take_interrupt());
+    joypad.set_buttons(HashSet::new());
+    assert!(!joypad.take_interrupt());
+
+    // Pressing two buttons in between checks still only fires once
+    joypad.set_buttons(HashSet::from([Button::Up, Button::Down]));
+    assert!(joypad.take_interrupt());
+    assert!(!joypad.take_interrupt());
+}
@@ -2,32 +2,136 @@
 use std::env;
 
 mod bus;
+mod config;
 mod cpu;
+mod debug_window;
 mod emulator;
 
 mod mbc;
 mod memory;
+mod model;
+mod movie;
+mod osd;
+mod pacing;
+mod perf;
+mod png;
+mod recorder;
+mod rewind;
+mod screenshot;
 
 mod graphics;
+mod input;
 mod io;
 mod joypad;
 mod serial;
 mod sound;
+mod state;
 mod timer;
+mod wav;
 
 extern crate sdl2;
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let args: Vec<String> = env::args().skip(1).collect();
 
-    if args.len() < 2 {
-        panic!("Not enough arguments! What game do you want to play!");
-    }
-    if args.len() > 2 {
-        panic!("Too many arguments!");
+    let mut config = match config::Config::load(config::Config::config_path_arg(&args)) {
+        Ok(c) => c,
+        Err(e) => panic!("Error loading config: {}", e),
+    };
+    let args = match config.apply_args(args) {
+        Ok(rest) => rest,
+        Err(e) => panic!("{}", e),
+    };
+
+    let mut game_path = None;
+    let mut model = None;
+    let mut movie_mode = None;
+    let mut dumps = Vec::new();
+    let mut headless = false;
+    let mut frame_limit = None;
+    let mut screenshot_frames = Vec::new();
+    let mut video_dir = None;
+    let mut perf_log = false;
+    let mut benchmark = false;
+
+    let mut args_iter = args.iter();
+    while let Some(arg) = args_iter.next() {
+        match arg.as_str() {
+            "--model" => {
+                let name = args_iter.next().expect("--model needs a value");
+                model = match model::Model::from_name(name) {
+                    Some(m) => Some(m),
+                    None => panic!("Unknown model: {} (dmg0, dmg, mgb, sgb, sgb2, cgb)", name),
+                };
+            }
+            "--record" => {
+                let path = args_iter.next().expect("--record needs a movie path");
+                movie_mode = Some(movie::MovieMode::Record(path.clone()));
+            }
+            "--play" => {
+                let path = args_iter.next().expect("--play needs a movie path");
+                movie_mode = Some(movie::MovieMode::Play(path.clone()));
+            }
+            "--dump-tiles" => {
+                let path = args_iter.next().expect("--dump-tiles needs a png path");
+                dumps.push((graphics::viewer::View::Tiles, path.clone()));
+            }
+            "--dump-tilemaps" => {
+                let path = args_iter.next().expect("--dump-tilemaps needs a png path");
+                dumps.push((graphics::viewer::View::Tilemaps, path.clone()));
+            }
+            "--dump-sprites" => {
+                let path = args_iter.next().expect("--dump-sprites needs a png path");
+                dumps.push((graphics::viewer::View::Sprites, path.clone()));
+            }
+            "--headless" => headless = true,
+            "--perf-log" => perf_log = true,
+            "--benchmark" => benchmark = true,
+            "--frames" => {
+                let frames = args_iter.next().expect("--frames needs a number of frames");
+                match frames.parse::<u64>() {
+                    Ok(n) => frame_limit = Some(n),
+                    Err(_) => panic!("Invalid number of frames: {}", frames),
+                }
+            }
+            "--screenshot-at" => {
+                let frame = args_iter
+                    .next()
+                    .expect("--screenshot-at needs a frame number");
+                match frame.parse::<u64>() {
+                    Ok(n) if n > 0 => screenshot_frames.push(n),
+                    _ => panic!("Invalid frame number: {} (frames start at 1)", frame),
+                }
+            }
+            "--record-video" => {
+                let dir = args_iter.next().expect("--record-video needs a folder");
+                video_dir = Some(dir.clone());
+            }
+            _ if game_path.is_none() => game_path = Some(arg),
+            _ => panic!("Too many arguments!"),
+        }
     }
-    let game_path = &args[1];
+
+    let game_path = match game_path {
+        Some(path) => path,
+        None => panic!("Not enough arguments! What game do you want to play!"),
+    };
     let mut gameboy = emulator::Emulator::new();
-    gameboy.setup_emulator(game_path);
+    gameboy.set_headless(headless);
+    gameboy.set_benchmark(benchmark);
+    gameboy.set_perf_log(perf_log);
+    gameboy.setup_emulator(game_path, model, &config, movie_mode);
+    for (view, path) in dumps {
+        gameboy.add_dump(view, path);
+    }
+    if let Some(frames) = frame_limit {
+        gameboy.set_frame_limit(frames);
+    }
+    for frame in screenshot_frames {
+        gameboy.add_screenshot_frame(frame);
+    }
+    if let Some(dir) = video_dir {
+        gameboy.start_recording(Some(dir));
+    }
     gameboy.run();
 }
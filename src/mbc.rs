@@ -6,6 +6,10 @@ pub mod mbc5;
 pub mod mbc_none;
 mod mbc_timer;
 
+pub use mbc_timer::RtcClock;
+
+use crate::state::{StateReader, StateWriter};
+
 pub trait Mbc {
     fn read_ram_byte(self: &Self, addr: u16) -> u8;
     fn write_ram_byte(self: &mut Self, addr: u16, val: u8);
@@ -22,4 +26,16 @@ pub trait Mbc {
         ram_size: usize,
         ram_banks: usize,
     );
+
+    // The banks, registers and ram but not the rom, see state.rs
+    fn save_state(self: &Self, state: &mut StateWriter);
+    fn load_state(self: &mut Self, state: &mut StateReader);
+
+    // Only carts with an rtc care what time it is. Call before load_game
+    fn set_rtc_clock(self: &mut Self, _clock: RtcClock) {}
+
+    // Only rumble carts have a motor
+    fn is_rumbling(self: &Self) -> bool {
+        return false;
+    }
 }
@@ -150,6 +150,7 @@ impl Battery {
         self: &mut Self,
         latched_rtc: &MbcTimer,
         updated_rtc: &MbcTimer,
+        current_time: u64,
     ) -> Result<usize, std::io::Error> {
         if let Some(rtc_file) = &mut self.rtc_file {
             return match rtc_file.seek(SeekFrom::Start(0)) {
@@ -157,7 +158,7 @@ impl Battery {
                     // Is there a simpler way to do this?
                     let latch_bytes = latched_rtc.to_secs().to_le_bytes();
                     let update_bytes = updated_rtc.to_secs().to_le_bytes();
-                    let save_bytes = MbcTimer::get_current_time().to_le_bytes();
+                    let save_bytes = current_time.to_le_bytes();
                     let bytes_written = rtc_file.write(&latch_bytes)?
                         + rtc_file.write(&update_bytes)?
                         + rtc_file.write(&save_bytes)?;
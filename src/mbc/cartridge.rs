@@ -2,14 +2,18 @@ use crate::mbc::mbc1::Mbc1;
 use crate::mbc::mbc3::Mbc3;
 use crate::mbc::mbc5::Mbc5;
 use crate::mbc::mbc_none::MbcNone;
-use crate::mbc::Mbc;
+use crate::mbc::{Mbc, RtcClock};
+use crate::model::{Model, CGB_FLAG_ADDR, SGB_FLAG_ADDR};
 use std::fs;
+use std::path::Path;
 
 pub struct Cartridge {
     entry_point: [u8; 4],
     logo: [u8; 48],
     title: [u8; 16],
     new_lisc_code: [u8; 2],
+    cgb_flag: u8,
+    sgb_flag: u8,
     cartridge_type: u8,
     rom_size: u8,
     ram_size: u8,
@@ -17,6 +21,9 @@ pub struct Cartridge {
     old_lisc_code: u8,
     rom_version: u8,
     pub checksum_val: u8,
+    save_dir: Option<String>,
+    battery_enabled: bool,
+    rtc_clock: RtcClock,
 }
 
 impl Cartridge {
@@ -26,6 +33,8 @@ impl Cartridge {
             logo: [0; 48],
             title: [0; 16],
             new_lisc_code: [0; 2],
+            cgb_flag: 0,
+            sgb_flag: 0,
             cartridge_type: 0,
             rom_size: 0,
             ram_size: 0,
@@ -33,6 +42,39 @@ impl Cartridge {
             old_lisc_code: 0,
             rom_version: 0,
             checksum_val: 0,
+            save_dir: None,
+            battery_enabled: true,
+            rtc_clock: RtcClock::System,
+        };
+    }
+
+    // Without the battery nothing gets loaded from or saved to disk and the
+    // cartridge ram starts out empty every time
+    pub fn set_battery_enabled(self: &mut Self, enabled: bool) {
+        self.battery_enabled = enabled;
+    }
+
+    pub fn set_rtc_clock(self: &mut Self, clock: RtcClock) {
+        self.rtc_clock = clock;
+    }
+
+    // Battery saves go into this directory instead of next to the rom
+    pub fn set_save_dir(self: &mut Self, save_dir: Option<String>) {
+        self.save_dir = save_dir;
+    }
+
+    // The mbcs name their save files after this path (swapping .gb for .gbsav/.gbrtc)
+    fn get_save_path(self: &Self, game_path: &str) -> Result<String, String> {
+        let dir = match &self.save_dir {
+            Some(dir) => dir,
+            None => return Ok(String::from(game_path)),
+        };
+        if let Err(e) = fs::create_dir_all(dir) {
+            return Err(format!("Couldnt create save directory {}: {}", dir, e));
+        }
+        return match Path::new(game_path).file_name() {
+            Some(name) => Ok(Path::new(dir).join(name).to_string_lossy().to_string()),
+            None => Err(format!("No file name in game path: {}", game_path)),
         };
     }
 
@@ -45,6 +87,8 @@ impl Cartridge {
         self.title[..16].clone_from_slice(&game_bytes[0x0134..=0x0143]);
 
         self.new_lisc_code[..2].clone_from_slice(&game_bytes[0x0144..=0x0145]);
+        self.cgb_flag = game_bytes[CGB_FLAG_ADDR];
+        self.sgb_flag = game_bytes[SGB_FLAG_ADDR];
         self.cartridge_type = game_bytes[0x0147];
         self.rom_size = game_bytes[0x0148];
         self.ram_size = game_bytes[0x0149];
@@ -67,15 +111,20 @@ impl Cartridge {
             None => return Err(format!("ROM Size: {} is not supported", self.ram_size)),
         };
 
-        let (mut mbc, features) = match self.get_cartridge_type() {
+        let (mut mbc, mut features) = match self.get_cartridge_type() {
             (Some(new_mbc), features) => (new_mbc, features),
             (None, features) => {
                 return Err(format!("MBC Type with {:?} is not supported", features))
             }
         };
+        if !self.battery_enabled {
+            features.retain(|f| *f != "BATTERY");
+        }
+        mbc.set_rtc_clock(self.rtc_clock);
 
+        let save_path = self.get_save_path(game_path)?;
         mbc.load_game(
-            game_path, game_bytes, features, rom_size, rom_banks, ram_size, ram_banks,
+            &save_path, game_bytes, features, rom_size, rom_banks, ram_size, ram_banks,
         );
 
         return Ok(mbc);
@@ -123,14 +172,17 @@ impl Cartridge {
             0x19 => (Some(Box::new(Mbc5::new())), vec!["MBC5"]),
             0x1A => (Some(Box::new(Mbc5::new())), vec!["MBC5", "RAM"]),
             0x1B => (Some(Box::new(Mbc5::new())), vec!["MBC5", "RAM", "BATTERY"]),
-            0x1C => (None, vec!["MBC5", "RUMBLE"]), // Not Implementing
-            0x1D => (None, vec!["MBC5", "RUMBLE", "RAM"]), // Not Implementing
-            0x1E => (None, vec!["MBC5", "RUMBLE", "RAM", "BATTERY"]), // Not Implementing
-            0x20 => (None, vec!["MBC6"]),           // Not Implementing
+            0x1C => (Some(Box::new(Mbc5::new())), vec!["MBC5", "RUMBLE"]),
+            0x1D => (Some(Box::new(Mbc5::new())), vec!["MBC5", "RUMBLE", "RAM"]),
+            0x1E => (
+                Some(Box::new(Mbc5::new())),
+                vec!["MBC5", "RUMBLE", "RAM", "BATTERY"],
+            ),
+            0x20 => (None, vec!["MBC6"]), // Not Implementing
             0x22 => (None, vec!["MBC7", "SENSOR", "RUMBLE", "RAM", "BATTERY"]), // Not Implementing
-            0xFC => (None, vec!["POCKET_CAMERA"]),  // Not Implementing
-            0xFD => (None, vec!["BANDAI_TAMA5"]),   // Not Implementing
-            0xFE => (None, vec!["HuC3"]),           // Not Implementing
+            0xFC => (None, vec!["POCKET_CAMERA"]), // Not Implementing
+            0xFD => (None, vec!["BANDAI_TAMA5"]), // Not Implementing
+            0xFE => (None, vec!["HuC3"]), // Not Implementing
             0xFF => (None, vec!["HuC1", "RAM", "BATTERY"]), // Not Implementing
             _ => panic!("Invalid cartridge type byte"),
         }
@@ -239,6 +291,28 @@ impl Cartridge {
         }
     }
 
+    // What model to emulate when one wasnt picked on the command line
+    pub fn get_model(self: &Self) -> Model {
+        return Model::from_header(self.cgb_flag, self.sgb_flag);
+    }
+
+    // Title from the header, cut off at the first 0 and made safe for file names
+    pub fn get_title(self: &Self) -> String {
+        let title: String = self
+            .title
+            .iter()
+            .take_while(|c| **c != 0)
+            .map(|c| match *c as char {
+                c if c.is_ascii_alphanumeric() => c,
+                _ => '_',
+            })
+            .collect();
+        return match title.trim_matches('_') {
+            "" => String::from("UNTITLED"),
+            title => String::from(title),
+        };
+    }
+
     pub fn get_logo(self: &Self) -> [u8; 48] {
         return self.logo;
     }
@@ -279,3 +353,12 @@ fn test_checksum() {
 
     cart.checksum(&game_bytes[0x0134..=0x014C]).unwrap();
 }
+
+#[test]
+fn test_get_title() {
+    let mut cart = Cartridge::new();
+    assert_eq!(cart.get_title(), "UNTITLED");
+    cart.title[..12].copy_from_slice(b"POKEMON RED\0");
+    cart.title[12..].copy_from_slice(b"\x80\x80\x80\x80");
+    assert_eq!(cart.get_title(), "POKEMON_RED");
+}
@@ -3,6 +3,7 @@ const RAM_BANK_SIZE: usize = 8_192;
 
 use super::battery::Battery;
 use crate::mbc::Mbc;
+use crate::state::{StateReader, StateWriter};
 
 pub struct Mbc1 {
     rom: Vec<u8>, // bank 0 0x0000 - 0x3FFF(16384) and bank 1 0x4000 - 0x7FFF (bank1 is swappable)
@@ -123,6 +124,28 @@ impl Mbc for Mbc1 {
         return;
     }
 
+    fn save_state(self: &Self, state: &mut StateWriter) {
+        state.write_bytes(&self.ram);
+        state.write_usize(self.rom_offset);
+        state.write_usize(self.ram_offset);
+        state.write_usize(self.rom_bank);
+        state.write_usize(self.ram_bank);
+        state.write_usize(self.ext_bank);
+        state.write_u8(self.mode);
+        state.write_bool(self.ram_enabled);
+    }
+
+    fn load_state(self: &mut Self, state: &mut StateReader) {
+        state.read_bytes(&mut self.ram);
+        self.rom_offset = state.read_usize();
+        self.ram_offset = state.read_usize();
+        self.rom_bank = state.read_usize();
+        self.ram_bank = state.read_usize();
+        self.ext_bank = state.read_usize();
+        self.mode = state.read_u8();
+        self.ram_enabled = state.read_bool();
+    }
+
     fn load_game(
         self: &mut Self,
         game_path: &str,
@@ -3,9 +3,10 @@ const RAM_BANK_SIZE: usize = 8_192;
 const CPU_CYCLES_PER_RTC_CYCLE: usize = CPU_FREQ / RTC_FREQ;
 
 use super::battery::Battery;
-use super::mbc_timer::{MbcTimer, COUNTER_MAX_SECONDS, RTC_FREQ};
+use super::mbc_timer::{MbcTimer, RtcClock, COUNTER_MAX_SECONDS, RTC_FREQ};
 use crate::cpu::CPU_FREQ;
 use crate::mbc::Mbc;
+use crate::state::{StateReader, StateWriter};
 
 /*
     Max 2MByte ROM  (128 Banks)
@@ -29,6 +30,7 @@ pub struct Mbc3 {
     latched_timer: Option<MbcTimer>,
     secs_at_latch: u64,
     latch: bool,
+    clock: RtcClock,
 }
 
 impl Mbc3 {
@@ -47,16 +49,21 @@ impl Mbc3 {
             latched_timer: None,
             secs_at_latch: 0,
             latch: false,
+            clock: RtcClock::System,
         }
     }
 
-    fn load_and_set_timers(self: &mut Self, battery: &mut Battery) {
+    // Without a battery the timers start out the same as a brand new save file would
+    fn load_and_set_timers(self: &mut Self, battery: Option<&mut Battery>) {
         let mut rtc = MbcTimer::new();
         let mut latched_rtc = MbcTimer::new();
-        let save_time = battery.load_rtc(&mut latched_rtc, &mut rtc);
+        let save_time = match battery {
+            Some(battery) => battery.load_rtc(&mut latched_rtc, &mut rtc),
+            None => 0,
+        };
 
         // It should be impossible for the save_time to be earlier than current
-        let time_offline = MbcTimer::get_current_time() - save_time;
+        let time_offline = self.clock.now().saturating_sub(save_time);
         let carry = if time_offline > COUNTER_MAX_SECONDS {
             true
         } else {
@@ -229,6 +236,39 @@ impl Mbc for Mbc3 {
         }
     }
 
+    // Carts without ram or a timer just dont write those parts
+    fn save_state(self: &Self, state: &mut StateWriter) {
+        if let Some(ram) = &self.ram {
+            state.write_bytes(ram);
+        }
+        state.write_bool(self.ram_and_timer_enable);
+        state.write_usize(self.rom_bank_num);
+        state.write_usize(self.ram_bank_num);
+        state.write_u8(self.latch_reg);
+        if let (Some(rtc), Some(l_rtc)) = (&self.timer, &self.latched_timer) {
+            rtc.save_state(state);
+            l_rtc.save_state(state);
+        }
+        state.write_u64(self.secs_at_latch);
+        state.write_bool(self.latch);
+    }
+
+    fn load_state(self: &mut Self, state: &mut StateReader) {
+        if let Some(ram) = &mut self.ram {
+            state.read_bytes(ram);
+        }
+        self.ram_and_timer_enable = state.read_bool();
+        self.rom_bank_num = state.read_usize();
+        self.ram_bank_num = state.read_usize();
+        self.latch_reg = state.read_u8();
+        if let (Some(rtc), Some(l_rtc)) = (&mut self.timer, &mut self.latched_timer) {
+            rtc.load_state(state);
+            l_rtc.load_state(state);
+        }
+        self.secs_at_latch = state.read_u64();
+        self.latch = state.read_bool();
+    }
+
     fn load_game(
         self: &mut Self,
         game_path: &str,
@@ -259,12 +299,18 @@ impl Mbc for Mbc3 {
                 self.battery = Some(battery);
                 self.max_ram_banks = ram_banks;
             }
+            ["MBC3", "TIMER"] => self.load_and_set_timers(None),
+            ["MBC3", "TIMER", "RAM"] => {
+                self.ram = Some(vec![0; ram_size]);
+                self.max_ram_banks = ram_banks;
+                self.load_and_set_timers(None);
+            }
             ["MBC3", "TIMER", "BATTERY"] => {
                 // Will create a second file within MbcTimer for storing the RTC registers
                 let rtc_path = String::from(game_path).replace(".gb", ".gbrtc");
                 let mut battery = Battery::new().with_rtc(rtc_path);
 
-                self.load_and_set_timers(&mut battery);
+                self.load_and_set_timers(Some(&mut battery));
                 self.battery = Some(battery);
             }
             ["MBC3", "TIMER", "RAM", "BATTERY"] => {
@@ -278,12 +324,16 @@ impl Mbc for Mbc3 {
 
                 self.ram = Some(battery.load_ram());
                 self.max_ram_banks = ram_banks;
-                self.load_and_set_timers(&mut battery);
+                self.load_and_set_timers(Some(&mut battery));
                 self.battery = Some(battery);
             }
             _ => panic!("Feature array not possible for MBC3"),
         }
     }
+
+    fn set_rtc_clock(self: &mut Self, clock: RtcClock) {
+        self.clock = clock;
+    }
 }
 
 // When the program ends for whatever reason, if we have battery backed ram
@@ -296,7 +346,7 @@ impl Drop for Mbc3 {
                 None => {}
             }
             match (&mut self.latched_timer, &mut self.timer) {
-                (Some(l_rtc), Some(rtc)) => match battery.save_rtc(l_rtc, rtc) {
+                (Some(l_rtc), Some(rtc)) => match battery.save_rtc(l_rtc, rtc, self.clock.now()) {
                     Ok(_) => { /* Nice */ }
                     Err(_err) => println!("Failed to save the rtc registers"),
                 },
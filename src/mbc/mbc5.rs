@@ -1,10 +1,12 @@
-// Not doing rumble
+// Rumble carts use bit 3 of the ram bank register to turn the motor on and off,
+// which leaves them with only 8 ram banks
 
 const ROM_BANK_SIZE: usize = 16_384;
 const RAM_BANK_SIZE: usize = 8_192;
 
 use super::battery::Battery;
 use crate::mbc::Mbc;
+use crate::state::{StateReader, StateWriter};
 
 pub struct Mbc5 {
     rom: Vec<u8>, // bank 0 0x0000 - 0x3FFF(16384) and bank 1 0x4000 - 0x7FFF (bank1 is swappable)
@@ -17,6 +19,8 @@ pub struct Mbc5 {
     max_rom_banks: usize,
     max_ram_banks: usize,
     ram_enabled: bool,
+    has_rumble: bool,
+    rumbling: bool,
     battery: Option<Battery>,
 }
 
@@ -33,6 +37,8 @@ impl Mbc5 {
             max_rom_banks: 0x00,
             max_ram_banks: 0x00,
             ram_enabled: false,
+            has_rumble: false,
+            rumbling: false,
             battery: None,
         }
     }
@@ -52,6 +58,10 @@ impl Mbc for Mbc5 {
             0x0000..=0x1FFF => self.ram_enabled = val == 0x0A,
             0x2000..=0x2FFF => self.rom_bank_lo = usize::from(val & 0xFF),
             0x3000..=0x3FFF => self.rom_bank_hi = usize::from(val & 0x01),
+            0x4000..=0x5FFF if self.has_rumble => {
+                self.rumbling = val & 0x08 == 0x08;
+                self.ram_bank = usize::from(val & 0x07);
+            }
             0x4000..=0x5FFF => self.ram_bank = usize::from(val & 0x0F),
             0x6000..=0x7FFF => { /* Nothing */ }
             _ => panic!("MbcNone: rom cannot read from addr {:#04X}", addr),
@@ -97,6 +107,28 @@ impl Mbc for Mbc5 {
         return;
     }
 
+    fn save_state(self: &Self, state: &mut StateWriter) {
+        state.write_bytes(&self.ram);
+        state.write_usize(self.rom_offset);
+        state.write_usize(self.ram_offset);
+        state.write_usize(self.rom_bank_lo);
+        state.write_usize(self.rom_bank_hi);
+        state.write_usize(self.ram_bank);
+        state.write_bool(self.ram_enabled);
+        state.write_bool(self.rumbling);
+    }
+
+    fn load_state(self: &mut Self, state: &mut StateReader) {
+        state.read_bytes(&mut self.ram);
+        self.rom_offset = state.read_usize();
+        self.ram_offset = state.read_usize();
+        self.rom_bank_lo = state.read_usize();
+        self.rom_bank_hi = state.read_usize();
+        self.ram_bank = state.read_usize();
+        self.ram_enabled = state.read_bool();
+        self.rumbling = state.read_bool();
+    }
+
     fn load_game(
         self: &mut Self,
         game_path: &str,
@@ -111,6 +143,10 @@ impl Mbc for Mbc5 {
         self.max_rom_banks = rom_banks;
         self.rom = game_bytes;
 
+        // Rumble doesnt change anything else about the cart
+        self.has_rumble = features.contains(&"RUMBLE");
+        let features: Vec<&str> = features.into_iter().filter(|f| *f != "RUMBLE").collect();
+
         match features[..] {
             ["MBC5"] => { /* Nothing to do */ }
             ["MBC5", "RAM"] => {
@@ -130,6 +166,10 @@ impl Mbc for Mbc5 {
             _ => panic!("Feature array not possible for MBC5"),
         }
     }
+
+    fn is_rumbling(self: &Self) -> bool {
+        return self.rumbling;
+    }
 }
 
 // When the program ends for whatever reason, if we have battery backed ram
@@ -1,4 +1,5 @@
 use crate::mbc::Mbc;
+use crate::state::{StateReader, StateWriter};
 
 pub struct MbcNone {
     rom: [u8; 32_768], // 0x0000 - 0x7FFF
@@ -50,6 +51,16 @@ impl Mbc for MbcNone {
         return;
     }
 
+    fn save_state(self: &Self, state: &mut StateWriter) {
+        state.write_bytes(&self.ram);
+        state.write_bool(self.ram_enabled);
+    }
+
+    fn load_state(self: &mut Self, state: &mut StateReader) {
+        state.read_bytes(&mut self.ram);
+        self.ram_enabled = state.read_bool();
+    }
+
     fn load_game(
         self: &mut Self,
         _game_path: &str,
@@ -28,12 +28,30 @@
        possible to stop the quartz oscillator from ticking forwards in time so I'm guessing
        that halt only means to no latch the data
 */
+use crate::state::{StateReader, StateWriter};
 use std::time::SystemTime;
 
 pub const RTC_FREQ: usize = 32_768;
 pub const RTC_PERIOD_MICROS: f64 = 30.51757;
 pub const COUNTER_MAX_SECONDS: u64 = 44_236_799;
 
+// Where the rtc gets the time from when the game is loaded and saved.
+// Movies use a fixed time so that every playback sees the same clock
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RtcClock {
+    System,
+    Fixed(u64), // Seconds since the unix epoch
+}
+
+impl RtcClock {
+    pub fn now(self: &Self) -> u64 {
+        return match self {
+            RtcClock::System => MbcTimer::get_current_time(),
+            RtcClock::Fixed(secs) => *secs,
+        };
+    }
+}
+
 pub struct MbcTimer {
     pub seconds: u8,
     pub minutes: u8,
@@ -58,6 +76,32 @@ impl MbcTimer {
         };
     }
 
+    pub fn save_state(self: &Self, state: &mut StateWriter) {
+        state.write_bytes(&[
+            self.seconds,
+            self.minutes,
+            self.hours,
+            self.days_lo,
+            self.days_hi,
+        ]);
+        state.write_usize(self.cycles);
+        state.write_usize(self.int_cycles);
+    }
+
+    pub fn load_state(self: &mut Self, state: &mut StateReader) {
+        let mut registers = [0; 5];
+        state.read_bytes(&mut registers);
+        [
+            self.seconds,
+            self.minutes,
+            self.hours,
+            self.days_lo,
+            self.days_hi,
+        ] = registers;
+        self.cycles = state.read_usize();
+        self.int_cycles = state.read_usize();
+    }
+
     pub fn is_halted(self: &Self) -> bool {
         return (self.days_hi >> 6) == 0x01;
     }
@@ -1,5 +1,6 @@
 use super::mbc::Mbc;
 use crate::mbc::mbc_none::MbcNone;
+use crate::state::{StateReader, StateWriter};
 
 pub struct Memory {
     mbc: Box<dyn Mbc>,      // MBC will contain ROM and RAM aswell as banks
@@ -24,6 +25,10 @@ impl Memory {
         self.mbc = cart_mbc;
     }
 
+    pub fn is_rumbling(self: &Self) -> bool {
+        return self.mbc.is_rumbling();
+    }
+
     pub fn read_byte(self: &Self, addr: u16) -> u8 {
         let byte = match addr {
             0x0000..=0x7FFF => self.mbc.read_rom_byte(addr),
@@ -77,6 +82,40 @@ impl Memory {
         }
     }
 
+    // Like turning it off and on again, but the cartridge keeps its ram. The
+    // mbc registers go back to their power on values (rom bank 1, ram disabled)
+    pub fn reset(self: &mut Self) {
+        self.wram = [0; 8_192];
+        self.echo_wram = [0; 7_680];
+        self.hram = [0; 127];
+        self.i_enable = 0;
+        for (addr, value) in [
+            (0x0000, 0x00),
+            (0x3000, 0x00),
+            (0x2000, 0x01),
+            (0x4000, 0x00),
+        ] {
+            self.mbc.write_rom_byte(addr, value);
+        }
+    }
+
+    // The rom isnt part of it, just the mbc registers and cartridge ram
+    pub fn save_state(self: &Self, state: &mut StateWriter) {
+        self.mbc.save_state(state);
+        state.write_bytes(&self.wram);
+        state.write_bytes(&self.echo_wram);
+        state.write_bytes(&self.hram);
+        state.write_u8(self.i_enable);
+    }
+
+    pub fn load_state(self: &mut Self, state: &mut StateReader) {
+        self.mbc.load_state(state);
+        state.read_bytes(&mut self.wram);
+        state.read_bytes(&mut self.echo_wram);
+        state.read_bytes(&mut self.hram);
+        self.i_enable = state.read_u8();
+    }
+
     pub fn dmg_init(self: &mut Self) {
         self.i_enable = 0x00;
     }
@@ -0,0 +1,130 @@
+/*
+    The different gameboy models each have their own boot rom which leaves
+    the cpu registers, the internal div counter and some of the io registers
+    in a different state when it hands off to the cartridge at 0x0100.
+
+    Since we skip the boot rom, we need to recreate that state ourselves.
+    https://gbdev.io/pandocs/Power_Up_Sequence.html#console-state-after-boot-rom-hand-off
+
+    Only dmg emulation is supported so picking the Cgb or Sgb (or a header
+    asking for them) will only give their initial state (the mooneye boot
+    tests check for this), not cgb colors or sgb borders.
+*/
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Model {
+    Dmg0, // Early japanese dmg, different boot rom from the rest
+    Dmg,  // DMG-CPU A, B and C
+    Mgb,  // Gameboy Pocket
+    Sgb,  // Super Gameboy
+    Sgb2, // Super Gameboy 2
+    Cgb,  // Gameboy Color
+}
+
+pub const CGB_FLAG_ADDR: usize = 0x0143;
+pub const SGB_FLAG_ADDR: usize = 0x0146;
+
+impl Model {
+    // Names accepted on the command line with --model
+    pub fn from_name(name: &str) -> Option<Model> {
+        return match name.to_lowercase().as_str() {
+            "dmg0" => Some(Model::Dmg0),
+            "dmg" | "dmgabc" => Some(Model::Dmg),
+            "mgb" => Some(Model::Mgb),
+            "sgb" => Some(Model::Sgb),
+            "sgb2" => Some(Model::Sgb2),
+            "cgb" => Some(Model::Cgb),
+            _ => None,
+        };
+    }
+
+    // A game with cgb support (0x80) or that is cgb only (0xC0) would be put in a
+    // cgb, otherwise a game with sgb support (0x03) in a super gameboy
+    // https://gbdev.io/pandocs/The_Cartridge_Header.html#0143--cgb-flag
+    // https://gbdev.io/pandocs/The_Cartridge_Header.html#0146--sgb-flag
+    pub fn from_header(cgb_flag: u8, sgb_flag: u8) -> Model {
+        if cgb_flag == 0x80 || cgb_flag == 0xC0 {
+            return Model::Cgb;
+        }
+        if sgb_flag == 0x03 {
+            return Model::Sgb;
+        }
+        return Model::Dmg;
+    }
+
+    // Used when the model needs to be stored in a file (movies)
+    pub fn to_id(self: &Self) -> u8 {
+        return match self {
+            Model::Dmg0 => 0,
+            Model::Dmg => 1,
+            Model::Mgb => 2,
+            Model::Sgb => 3,
+            Model::Sgb2 => 4,
+            Model::Cgb => 5,
+        };
+    }
+
+    pub fn from_id(id: u8) -> Option<Model> {
+        return match id {
+            0 => Some(Model::Dmg0),
+            1 => Some(Model::Dmg),
+            2 => Some(Model::Mgb),
+            3 => Some(Model::Sgb),
+            4 => Some(Model::Sgb2),
+            5 => Some(Model::Cgb),
+            _ => None,
+        };
+    }
+
+    pub fn is_cgb(self: &Self) -> bool {
+        return *self == Model::Cgb;
+    }
+
+    pub fn is_sgb(self: &Self) -> bool {
+        return *self == Model::Sgb || *self == Model::Sgb2;
+    }
+
+    // Writing to stat on the dmg will briefly write 0xFF. This was fixed on the cgb
+    // https://gbdev.io/pandocs/STAT.html#spurious-stat-interrupts
+    pub fn has_stat_quirk(self: &Self) -> bool {
+        return !self.is_cgb();
+    }
+}
+
+#[test]
+fn test_model_from_name() {
+    assert_eq!(Model::from_name("dmg0"), Some(Model::Dmg0));
+    assert_eq!(Model::from_name("DMG"), Some(Model::Dmg));
+    assert_eq!(Model::from_name("dmgABC"), Some(Model::Dmg));
+    assert_eq!(Model::from_name("mgb"), Some(Model::Mgb));
+    assert_eq!(Model::from_name("sgb"), Some(Model::Sgb));
+    assert_eq!(Model::from_name("Sgb2"), Some(Model::Sgb2));
+    assert_eq!(Model::from_name("cgb"), Some(Model::Cgb));
+    assert_eq!(Model::from_name("agb"), None);
+}
+
+#[test]
+fn test_model_from_header() {
+    assert_eq!(Model::from_header(0x00, 0x00), Model::Dmg);
+    assert_eq!(Model::from_header(0x80, 0x03), Model::Cgb);
+    assert_eq!(Model::from_header(0xC0, 0x00), Model::Cgb);
+    assert_eq!(Model::from_header(0x00, 0x03), Model::Sgb);
+    assert_eq!(Model::from_header(0x42, 0x00), Model::Dmg); // Part of an old title
+}
+
+#[test]
+fn test_model_ids() {
+    let models = [
+        Model::Dmg0,
+        Model::Dmg,
+        Model::Mgb,
+        Model::Sgb,
+        Model::Sgb2,
+        Model::Cgb,
+    ];
+    for (id, model) in models.into_iter().enumerate() {
+        assert_eq!(model.to_id(), id as u8);
+        assert_eq!(Model::from_id(id as u8), Some(model));
+    }
+    assert_eq!(Model::from_id(6), None);
+}
@@ -0,0 +1,337 @@
+/*
+    Movies are recordings of the buttons held on every frame, so a play session
+    can be replayed exactly. Everything else the emulator does is deterministic
+    as long as it starts from the same place, which the header takes care of.
+
+    File layout (.gbm, little endian):
+        0x00  4  Magic "GBMV"
+        0x04  1  Version
+        0x05  1  Model
+        0x06  1  Start (0 = power on, 1 = save state)
+        0x07  1  Unused
+        0x08  8  Rom hash
+        0x10  8  Rtc clock (Seconds since the unix epoch when recording started)
+        0x18  8  Framebuffer hash of the last frame
+        0x20  4  Number of frames
+        0x24  n  One byte per frame with the buttons held (See BUTTONS for the bits)
+
+    Save state movies follow the frames with the snapshot they start from:
+        +n    4  Snapshot length
+        +n+4  m  Snapshot of the whole machine (see state.rs)
+
+    Movies from --record start from power on, the record_movie hotkey starts one
+    from a save state of the game as it is. Either way they play back with the
+    battery disabled, so the save file on the testers computer doesnt matter and
+    doesnt get touched, and the rtc reads its time from the header instead of the
+    system clock. A save state movie has the cartridge ram and rtc in its snapshot.
+
+    Snapshots have no version, so a save state movie only plays back on a build
+    that writes snapshots of the same size for the rom.
+
+    The framebuffer hash is taken from the shades (0-3) the palette registers pick
+    for each pixel (see GpuMemory::shades) and not the colors, so the palette
+    used while recording doesnt matter.
+*/
+
+use crate::joypad::Button;
+use crate::model::Model;
+use std::collections::HashSet;
+use std::fs;
+
+const MAGIC: &[u8; 4] = b"GBMV";
+const VERSION: u8 = 1;
+const HEADER_SIZE: usize = 0x24;
+const START_POWER_ON: u8 = 0;
+const START_SAVE_STATE: u8 = 1;
+
+// Bit 0 to bit 7 of a frame byte
+const BUTTONS: [Button; 8] = [
+    Button::Right,
+    Button::Left,
+    Button::Up,
+    Button::Down,
+    Button::A,
+    Button::B,
+    Button::Select,
+    Button::Start,
+];
+
+pub enum MovieMode {
+    Record(String),
+    Play(String),
+}
+
+pub struct Movie {
+    pub model: Model,
+    pub rom_hash: u64,
+    pub rtc_start: u64,
+    pub final_hash: u64,
+    pub frames: Vec<u8>,
+    pub start_state: Option<Vec<u8>>, // None when starting from power on
+}
+
+impl Movie {
+    pub fn new(model: Model, rom_hash: u64, rtc_start: u64) -> Movie {
+        return Movie {
+            model: model,
+            rom_hash: rom_hash,
+            rtc_start: rtc_start,
+            final_hash: 0,
+            frames: Vec::new(),
+            start_state: None,
+        };
+    }
+
+    pub fn with_start_state(mut self: Self, snapshot: Vec<u8>) -> Movie {
+        self.start_state = Some(snapshot);
+        return self;
+    }
+
+    pub fn load(path: &str) -> Result<Movie, String> {
+        return match fs::read(path) {
+            Ok(bytes) => Movie::from_bytes(&bytes),
+            Err(e) => Err(format!("Couldnt read movie {}: {}", path, e)),
+        };
+    }
+
+    pub fn save(self: &Self, path: &str) -> Result<(), String> {
+        return fs::write(path, self.to_bytes())
+            .map_err(|e| format!("Couldnt write movie {}: {}", path, e));
+    }
+
+    pub fn to_bytes(self: &Self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_SIZE + self.frames.len());
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(VERSION);
+        bytes.push(self.model.to_id());
+        bytes.push(match self.start_state {
+            Some(_) => START_SAVE_STATE,
+            None => START_POWER_ON,
+        });
+        bytes.push(0x00);
+        bytes.extend_from_slice(&self.rom_hash.to_le_bytes());
+        bytes.extend_from_slice(&self.rtc_start.to_le_bytes());
+        bytes.extend_from_slice(&self.final_hash.to_le_bytes());
+        bytes.extend_from_slice(&(self.frames.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&self.frames);
+        if let Some(snapshot) = &self.start_state {
+            bytes.extend_from_slice(&(snapshot.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(snapshot);
+        }
+        return bytes;
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Movie, String> {
+        if bytes.len() < HEADER_SIZE || &bytes[0x00..0x04] != MAGIC {
+            return Err(String::from("Not a movie file"));
+        }
+        if bytes[0x04] != VERSION {
+            return Err(format!("Unsupported movie version: {}", bytes[0x04]));
+        }
+        let model = match Model::from_id(bytes[0x05]) {
+            Some(model) => model,
+            None => return Err(format!("Unknown model in movie: {}", bytes[0x05])),
+        };
+        let from_state = match bytes[0x06] {
+            START_POWER_ON => false,
+            START_SAVE_STATE => true,
+            start => return Err(format!("Unknown movie start: {}", start)),
+        };
+
+        let read_u32 = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+        let read_u64 = |at: usize| u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap());
+        let num_frames = read_u32(0x20) as usize;
+        let frames_end = HEADER_SIZE + num_frames;
+        let end = match from_state {
+            true if bytes.len() >= frames_end + 4 => frames_end + 4 + read_u32(frames_end) as usize,
+            true => return Err(String::from("Movie is missing its save state")),
+            false => frames_end,
+        };
+        if bytes.len() != end {
+            return Err(format!(
+                "Movie should be {} bytes for {} frames but is {}",
+                end,
+                num_frames,
+                bytes.len()
+            ));
+        }
+
+        return Ok(Movie {
+            model: model,
+            rom_hash: read_u64(0x08),
+            rtc_start: read_u64(0x10),
+            final_hash: read_u64(0x18),
+            frames: bytes[HEADER_SIZE..frames_end].to_vec(),
+            start_state: match from_state {
+                true => Some(bytes[frames_end + 4..].to_vec()),
+                false => None,
+            },
+        });
+    }
+}
+
+// Sits between the input and the joypad while a movie is recording or playing
+pub struct MovieSession {
+    movie: Movie,
+    path: String,
+    recording: bool,
+    frame: usize,
+}
+
+impl MovieSession {
+    pub fn record(path: String, movie: Movie) -> MovieSession {
+        return MovieSession {
+            movie: movie,
+            path: path,
+            recording: true,
+            frame: 0,
+        };
+    }
+
+    pub fn play(path: String, movie: Movie) -> MovieSession {
+        return MovieSession {
+            movie: movie,
+            path: path,
+            recording: false,
+            frame: 0,
+        };
+    }
+
+    pub fn is_recording(self: &Self) -> bool {
+        return self.recording;
+    }
+
+    pub fn start_state(self: &Self) -> Option<&[u8]> {
+        return self.movie.start_state.as_deref();
+    }
+
+    // Playback is over once every frame has been handed to the joypad
+    pub fn is_finished(self: &Self) -> bool {
+        return !self.recording && self.frame >= self.movie.frames.len();
+    }
+
+    // Takes the buttons held by the player and gives back the ones the joypad should see
+    pub fn next_frame(self: &mut Self, held: HashSet<Button>) -> HashSet<Button> {
+        if self.recording {
+            self.movie.frames.push(buttons_to_byte(&held));
+            self.frame += 1;
+            return held;
+        }
+
+        let byte = self.movie.frames[self.frame];
+        self.frame += 1;
+        return byte_to_buttons(byte);
+    }
+
+    // Recording saves the movie, playback checks that it ended up on the same frame.
+    // Playback stopped early (closed or out of --frames) has nothing to check
+    pub fn finish(self: &mut Self, frame_hash: u64) {
+        if self.recording {
+            self.movie.final_hash = frame_hash;
+            match self.movie.save(&self.path) {
+                Ok(()) => println!("Recorded {} frames to {}", self.frame, self.path),
+                Err(e) => println!("{}", e),
+            }
+        } else if !self.is_finished() {
+            println!(
+                "Movie {} playback stopped at frame {} of {}",
+                self.path,
+                self.frame,
+                self.movie.frames.len()
+            );
+        } else if frame_hash == self.movie.final_hash {
+            println!(
+                "Movie {} finished after {} frames: framebuffer matches",
+                self.path, self.frame
+            );
+        } else {
+            println!(
+                "Movie {} finished after {} frames: framebuffer MISMATCH (expected {:016X}, got {:016X})",
+                self.path, self.frame, self.movie.final_hash, frame_hash
+            );
+        }
+    }
+}
+
+pub fn buttons_to_byte(buttons: &HashSet<Button>) -> u8 {
+    let mut byte = 0;
+    for (bit, button) in BUTTONS.iter().enumerate() {
+        if buttons.contains(button) {
+            byte |= 1 << bit;
+        }
+    }
+    return byte;
+}
+
+pub fn byte_to_buttons(byte: u8) -> HashSet<Button> {
+    let mut buttons = HashSet::new();
+    for (bit, button) in BUTTONS.iter().enumerate() {
+        if byte & (1 << bit) != 0 {
+            buttons.insert(*button);
+        }
+    }
+    return buttons;
+}
+
+// 64 bit FNV-1a. Not for security, just to tell roms and frames apart
+pub fn hash_bytes(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xCBF29CE484222325;
+    for byte in bytes {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x00000100000001B3);
+    }
+    return hash;
+}
+
+#[test]
+fn test_movie_bytes() {
+    let mut movie = Movie::new(Model::Mgb, 0x0123456789ABCDEF, 1_700_000_000);
+    movie.final_hash = 0xFEDCBA9876543210;
+    movie.frames = vec![0x00, 0x11, 0x80, 0xFF];
+
+    let bytes = movie.to_bytes();
+    assert_eq!(bytes.len(), HEADER_SIZE + 4);
+
+    let loaded = Movie::from_bytes(&bytes).unwrap();
+    assert_eq!(loaded.model, Model::Mgb);
+    assert_eq!(loaded.rom_hash, 0x0123456789ABCDEF);
+    assert_eq!(loaded.rtc_start, 1_700_000_000);
+    assert_eq!(loaded.final_hash, 0xFEDCBA9876543210);
+    assert_eq!(loaded.frames, vec![0x00, 0x11, 0x80, 0xFF]);
+    assert_eq!(loaded.start_state, None);
+
+    assert!(Movie::from_bytes(&bytes[..HEADER_SIZE + 2]).is_err());
+    assert!(Movie::from_bytes(b"GBMX").is_err());
+}
+
+#[test]
+fn test_movie_save_state_bytes() {
+    let mut movie = Movie::new(Model::Dmg, 0x0123456789ABCDEF, 1_700_000_000)
+        .with_start_state(vec![0xAA, 0xBB, 0xCC]);
+    movie.frames = vec![0x01, 0x02];
+
+    let bytes = movie.to_bytes();
+    assert_eq!(bytes[0x06], START_SAVE_STATE);
+    assert_eq!(bytes.len(), HEADER_SIZE + 2 + 4 + 3);
+
+    let loaded = Movie::from_bytes(&bytes).unwrap();
+    assert_eq!(loaded.frames, vec![0x01, 0x02]);
+    assert_eq!(loaded.start_state, Some(vec![0xAA, 0xBB, 0xCC]));
+
+    assert!(Movie::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    assert!(Movie::from_bytes(&bytes[..HEADER_SIZE + 2]).is_err());
+}
+
+#[test]
+fn test_movie_buttons() {
+    let buttons = HashSet::from([Button::Right, Button::A, Button::Start]);
+    assert_eq!(buttons_to_byte(&buttons), 0x91);
+    assert_eq!(byte_to_buttons(0x91), buttons);
+    assert_eq!(byte_to_buttons(0x00), HashSet::new());
+}
+
+#[test]
+fn test_hash_bytes() {
+    assert_eq!(hash_bytes(b""), 0xCBF29CE484222325);
+    assert_eq!(hash_bytes(b"a"), 0xAF63DC4C8601EC8C);
+}
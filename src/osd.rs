@@ -0,0 +1,191 @@
+/*
+    On screen messages drawn over the game in the main window, so hotkeys like
+    pause and reset show that they did something without looking at the console.
+
+    A message shows for MESSAGE_TIME in the top left. The status (like PAUSED)
+    stays in the bottom left until it is cleared, and so do the overlay lines
+    (the performance numbers) in the top right. Text is drawn with the little
+    5x7 font below, one font pixel to each gameboy pixel, so it scales with the
+    window. Letters are shown in upper case and characters not in the font are
+    left blank.
+*/
+
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, Canvas};
+use sdl2::video::Window;
+use std::time::{Duration, Instant};
+
+const MESSAGE_TIME: Duration = Duration::from_secs(2);
+const CHAR_WIDTH: i32 = 6; // 5 pixels and a gap
+const CHAR_HEIGHT: i32 = 7;
+const MARGIN: i32 = 2; // Between the text and the edge of its box, and the box and the screen
+
+// Each row is 5 bits with the left most pixel in bit 4
+const FONT: [(char, [u8; 7]); 48] = [
+    ('A', [0x0E, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11]),
+    ('B', [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E]),
+    ('C', [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E]),
+    ('D', [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C]),
+    ('E', [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F]),
+    ('F', [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10]),
+    ('G', [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F]),
+    ('H', [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11]),
+    ('I', [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E]),
+    ('J', [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C]),
+    ('K', [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11]),
+    ('L', [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F]),
+    ('M', [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11]),
+    ('N', [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11]),
+    ('O', [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E]),
+    ('P', [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10]),
+    ('Q', [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D]),
+    ('R', [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11]),
+    ('S', [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E]),
+    ('T', [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04]),
+    ('U', [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E]),
+    ('V', [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04]),
+    ('W', [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A]),
+    ('X', [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11]),
+    ('Y', [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04]),
+    ('Z', [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F]),
+    ('0', [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E]),
+    ('1', [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E]),
+    ('2', [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F]),
+    ('3', [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E]),
+    ('4', [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02]),
+    ('5', [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E]),
+    ('6', [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E]),
+    ('7', [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08]),
+    ('8', [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E]),
+    ('9', [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C]),
+    ('.', [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C]),
+    (',', [0x00, 0x00, 0x00, 0x00, 0x0C, 0x04, 0x08]),
+    (':', [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00]),
+    ('-', [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00]),
+    ('+', [0x00, 0x04, 0x04, 0x1F, 0x04, 0x04, 0x00]),
+    ('/', [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00]),
+    ('%', [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03]),
+    ('(', [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02]),
+    (')', [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08]),
+    ('\'', [0x0C, 0x04, 0x08, 0x00, 0x00, 0x00, 0x00]),
+    ('_', [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1F]),
+    (' ', [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]),
+];
+
+pub struct Osd {
+    message: Option<(String, Instant)>, // And when it was shown
+    status: Option<String>,
+    overlay: Vec<String>, // One box each, top to bottom
+}
+
+impl Osd {
+    pub fn new() -> Osd {
+        return Osd {
+            message: None,
+            status: None,
+            overlay: Vec::new(),
+        };
+    }
+
+    pub fn show(self: &mut Self, message: &str) {
+        self.message = Some((message.to_string(), Instant::now()));
+    }
+
+    pub fn set_status(self: &mut Self, status: Option<&str>) {
+        self.status = status.map(String::from);
+    }
+
+    pub fn set_overlay(self: &mut Self, lines: Vec<String>) {
+        self.overlay = lines;
+    }
+
+    // screen is where the gameboy picture is in the window
+    pub fn draw(self: &mut Self, canvas: &mut Canvas<Window>, screen: Rect) {
+        if let Some((_, shown)) = &self.message {
+            if shown.elapsed() > MESSAGE_TIME {
+                self.message = None;
+            }
+        }
+        let pixel_size = std::cmp::max(1, screen.height() as i32 / 144);
+        canvas.set_blend_mode(BlendMode::Blend);
+        if let Some((message, _)) = &self.message {
+            draw_text(canvas, screen, pixel_size, message, (MARGIN, MARGIN));
+        }
+        if let Some(status) = &self.status {
+            let y = (screen.height() as i32 / pixel_size) - CHAR_HEIGHT - (MARGIN * 3);
+            draw_text(canvas, screen, pixel_size, status, (MARGIN, y));
+        }
+        let screen_width = screen.width() as i32 / pixel_size;
+        for (i, line) in self.overlay.iter().enumerate() {
+            let x = screen_width - text_width(line) - MARGIN;
+            let y = MARGIN + (i as i32 * (CHAR_HEIGHT + MARGIN * 2));
+            draw_text(canvas, screen, pixel_size, line, (x, y));
+        }
+        canvas.set_blend_mode(BlendMode::None);
+    }
+}
+
+// A dark box with the text on it, at (x, y) in gameboy pixels
+fn draw_text(
+    canvas: &mut Canvas<Window>,
+    screen: Rect,
+    pixel_size: i32,
+    text: &str,
+    (x, y): (i32, i32),
+) {
+    let to_window = |px: i32, py: i32, w: i32, h: i32| {
+        Rect::new(
+            screen.x() + (px * pixel_size),
+            screen.y() + (py * pixel_size),
+            (w * pixel_size) as u32,
+            (h * pixel_size) as u32,
+        )
+    };
+    let width = text_width(text);
+    let height = CHAR_HEIGHT + (MARGIN * 2);
+    canvas.set_draw_color(Color::RGBA(0, 0, 0, 0xA0));
+    canvas.fill_rect(to_window(x, y, width, height)).unwrap();
+
+    let pixels: Vec<Rect> = text_pixels(text)
+        .into_iter()
+        .map(|(px, py)| to_window(x + MARGIN + px, y + MARGIN + py, 1, 1))
+        .collect();
+    canvas.set_draw_color(Color::RGBA(0xFF, 0xFF, 0xFF, 0xFF));
+    canvas.fill_rects(&pixels).unwrap();
+    canvas.set_draw_color(Color::RGBA(0, 0, 0, 0xFF)); // What clear uses
+}
+
+// Of the box around it, in gameboy pixels
+fn text_width(text: &str) -> i32 {
+    return (text.chars().count() as i32 * CHAR_WIDTH) - 1 + (MARGIN * 2);
+}
+
+// The pixels that are set when the text is drawn at (0, 0)
+fn text_pixels(text: &str) -> Vec<(i32, i32)> {
+    let mut pixels = Vec::new();
+    for (i, c) in text.to_uppercase().chars().enumerate() {
+        let rows = match FONT.iter().find(|(glyph, _)| *glyph == c) {
+            Some((_, rows)) => rows,
+            None => continue,
+        };
+        for (y, row) in rows.iter().enumerate() {
+            for x in 0..5 {
+                if (row >> (4 - x)) & 1 == 1 {
+                    pixels.push(((i as i32 * CHAR_WIDTH) + x, y as i32));
+                }
+            }
+        }
+    }
+    return pixels;
+}
+
+#[test]
+fn test_text_pixels() {
+    // The bar across the middle of a minus, then an I with its top and bottom bars
+    let pixels = text_pixels("-i");
+    assert_eq!(pixels.len(), 5 + 11);
+    assert_eq!(pixels[0..5], [(0, 3), (1, 3), (2, 3), (3, 3), (4, 3)]);
+    assert!(pixels.contains(&(CHAR_WIDTH + 2, 6)));
+    assert!(text_pixels("~ ").is_empty());
+}
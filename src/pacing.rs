@@ -0,0 +1,221 @@
+/*
+    Keeps frames at the gameboy's 59.7275 a second (times the speed).
+
+    Every frame has a deadline counted from when pacing (re)started, a frame is
+    due once the real time since start matches the gameboy time run since then,
+    start + cycles / (CPU_FREQ * speed). Frames are usually 70224 cycles, but the
+    one where the lcd is turned off or on is cut short, so the cycles each frame
+    took are counted rather than the frames. The wait is until the deadline
+    rather than for a frame's worth of time after the last one. Sleeping too long
+    on one frame just means less wait on the next, so the error never adds up and
+    a short hitch gets caught up on. Something long like dragging the window
+    would mean a burst of frames as fast as possible to catch up, so once it is
+    more than MAX_LAG behind the deadlines start again from now.
+
+    sleep usually wakes up a little late. With spin_wait the last SPIN_TIME
+    before a deadline is busy waited instead, which costs cpu but lands frames
+    right on time.
+
+    Once a second the frames shown and how much gameboy time they covered are
+    turned into Stats for the window title.
+*/
+
+use crate::cpu::CPU_FREQ;
+use std::time::{Duration, Instant};
+
+const MAX_LAG: Duration = Duration::from_millis(100); // About 6 frames
+const SPIN_TIME: Duration = Duration::from_millis(1);
+const STATS_TIME: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Stats {
+    pub fps: f64,
+    pub speed_percent: f64, // Gameboy time over real time, 100 is full speed
+}
+
+pub struct Pacer {
+    speed: Option<f64>, // Times normal speed, no waiting at all if None
+    spin_wait: bool,
+    start: Instant, // Deadlines are counted from here
+    cycles: u64,    // Gameboy time since start
+    stats_start: Instant,
+    stats_frames: u64,
+    stats_cycles: u64,
+    stats: Option<Stats>, // Waiting to be taken
+}
+
+impl Pacer {
+    pub fn new() -> Pacer {
+        let now = Instant::now();
+        return Pacer {
+            speed: Some(1.0),
+            spin_wait: false,
+            start: now,
+            cycles: 0,
+            stats_start: now,
+            stats_frames: 0,
+            stats_cycles: 0,
+            stats: None,
+        };
+    }
+
+    pub fn with_spin_wait(mut self: Self, spin_wait: bool) -> Pacer {
+        self.spin_wait = spin_wait;
+        return self;
+    }
+
+    // The deadlines start again from now at the new speed
+    pub fn set_speed(self: &mut Self, speed: Option<f64>) {
+        self.speed = speed;
+        self.restart(Instant::now());
+    }
+
+    // Frames from here on are counted from now
+    fn restart(self: &mut Self, now: Instant) {
+        self.start = now;
+        self.cycles = 0;
+    }
+
+    // Called once a frame that took cycles is ready, returns once it is time to show it
+    pub fn wait_frame(self: &mut Self, cycles: usize) {
+        let now = Instant::now();
+        self.count_frame(now, cycles);
+        if let Some(deadline) = self.next_deadline(now, cycles) {
+            self.wait_until(deadline);
+        }
+    }
+
+    // When the frame just finished should be shown, None if that is right away
+    fn next_deadline(self: &mut Self, now: Instant, cycles: usize) -> Option<Instant> {
+        let speed = self.speed?;
+        self.cycles += cycles as u64;
+        let deadline = self.start + emulated_time(self.cycles, speed);
+        if now > deadline + MAX_LAG {
+            self.restart(now);
+            return None;
+        }
+        return Some(deadline);
+    }
+
+    fn wait_until(self: &Self, deadline: Instant) {
+        let now = Instant::now();
+        if now >= deadline {
+            return;
+        }
+        if !self.spin_wait {
+            std::thread::sleep(deadline - now);
+            return;
+        }
+        if deadline - now > SPIN_TIME {
+            std::thread::sleep(deadline - now - SPIN_TIME);
+        }
+        while Instant::now() < deadline {
+            std::hint::spin_loop();
+        }
+    }
+
+    fn count_frame(self: &mut Self, now: Instant, cycles: usize) {
+        self.stats_frames += 1;
+        self.stats_cycles += cycles as u64;
+        let elapsed = now - self.stats_start;
+        if elapsed >= STATS_TIME {
+            let secs = elapsed.as_secs_f64();
+            self.stats = Some(Stats {
+                fps: self.stats_frames as f64 / secs,
+                speed_percent: emulated_time(self.stats_cycles, 1.0).as_secs_f64() / secs * 100.0,
+            });
+            self.stats_start = now;
+            self.stats_frames = 0;
+            self.stats_cycles = 0;
+        }
+    }
+
+    // The newest stats, once each time they are measured
+    pub fn take_stats(self: &mut Self) -> Option<Stats> {
+        return self.stats.take();
+    }
+}
+
+// How long the gameboy takes to run cycles at the speed
+fn emulated_time(cycles: u64, speed: f64) -> Duration {
+    return Duration::from_secs_f64(cycles as f64 / (CPU_FREQ as f64 * speed));
+}
+
+#[cfg(test)]
+const FRAME: usize = crate::graphics::CYCLES_PER_FRAME;
+
+#[test]
+fn test_deadlines_dont_drift() {
+    let mut pacer = Pacer::new();
+    let start = pacer.start;
+    let frame_time = |frames: u64, speed: f64| emulated_time(frames * FRAME as u64, speed);
+    // Even if every frame is shown late, the deadlines stay where they were
+    for frame in 1..=600 {
+        let late = start + frame_time(frame, 1.0) + Duration::from_millis(5);
+        let deadline = pacer.next_deadline(late, FRAME).unwrap();
+        assert_eq!(deadline, start + frame_time(frame, 1.0));
+    }
+    // 600 frames is a little over 10 seconds
+    assert!((frame_time(600, 1.0).as_secs_f64() - 10.0456).abs() < 0.0001);
+
+    pacer.set_speed(Some(2.0));
+    let start = pacer.start;
+    assert_eq!(
+        pacer.next_deadline(start, FRAME),
+        Some(start + frame_time(1, 2.0))
+    );
+}
+
+#[test]
+fn test_short_frames() {
+    let mut pacer = Pacer::new();
+    let start = pacer.start;
+    // The frame the lcd was turned on in only ran for a third of a frame
+    let short = FRAME / 3;
+    assert_eq!(
+        pacer.next_deadline(start, short),
+        Some(start + emulated_time(short as u64, 1.0))
+    );
+    assert_eq!(
+        pacer.next_deadline(start, FRAME),
+        Some(start + emulated_time((short + FRAME) as u64, 1.0))
+    );
+}
+
+#[test]
+fn test_stall_restarts() {
+    let mut pacer = Pacer::new();
+    let start = pacer.start;
+    let frame_time = emulated_time(FRAME as u64, 1.0);
+    // A little behind gets caught up on
+    let behind = start + frame_time + MAX_LAG / 2;
+    assert_eq!(pacer.next_deadline(behind, FRAME), Some(start + frame_time));
+
+    // Too far behind starts counting again from then
+    let stalled = start + Duration::from_secs(2);
+    assert_eq!(pacer.next_deadline(stalled, FRAME), None);
+    assert_eq!(
+        pacer.next_deadline(stalled, FRAME),
+        Some(stalled + frame_time)
+    );
+
+    pacer.set_speed(None);
+    assert_eq!(pacer.next_deadline(Instant::now(), FRAME), None);
+}
+
+#[test]
+fn test_stats() {
+    let mut pacer = Pacer::new();
+    let start = pacer.stats_start;
+    for frame in 1..=30 {
+        pacer.count_frame(start + Duration::from_millis(frame * 33), FRAME);
+    }
+    assert_eq!(pacer.take_stats(), None);
+    // The last frame was cut short, so it counts as a frame but not a full frames time
+    pacer.count_frame(start + Duration::from_secs(1), FRAME / 2);
+    let stats = pacer.take_stats().unwrap();
+    assert_eq!(stats.fps, 31.0);
+    let speed = (30.5 * FRAME as f64) / CPU_FREQ as f64 * 100.0;
+    assert!((stats.speed_percent - speed).abs() < 0.0001);
+    assert_eq!(pacer.take_stats(), None);
+}
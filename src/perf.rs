@@ -0,0 +1,253 @@
+/*
+    Performance numbers for comparing changes to how the emulator runs, shown in
+    the corner of the window (F10) and/or printed once a second with --perf-log.
+
+    The game loop marks where its time goes with lap(): emulating until a frame
+    is ready, drawing it (the scaler and putting it in the window), waiting on
+    the pacer (or while paused), and everything else (input, rewind snapshots,
+    recording). Everything except waiting adds up to the host frame time.
+
+    Built with the profile feature the bus also times the ppu, apu and mbc each
+    time it advances them (see Bus::adv_cycles), and whatever is left of the
+    emulating time is put down to the cpu, which includes the timer, serial and
+    dma. That means reading the clock a few times every 4 cycles, which is slow
+    enough by itself to change the numbers, so normal builds leave it out.
+
+    --benchmark runs a number of frames headless from a clean start (no save,
+    no sound, a fixed rtc time) and prints benchmark_summary at the end, so the
+    same rom gives a number that can be compared between builds.
+*/
+
+use crate::recorder::FRAME_RATE;
+use std::time::{Duration, Instant};
+
+const REPORT_TIME: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Lap {
+    Emulate,
+    Draw,
+    Wait,
+    Other,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Part {
+    Ppu,
+    Apu,
+    Mbc,
+}
+
+// Time spent in the parts the bus advances, only kept with the profile feature
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct PartTimes {
+    pub ppu: Duration,
+    pub apu: Duration,
+    pub mbc: Duration,
+}
+
+impl PartTimes {
+    pub fn new() -> PartTimes {
+        return PartTimes {
+            ppu: Duration::ZERO,
+            apu: Duration::ZERO,
+            mbc: Duration::ZERO,
+        };
+    }
+
+    // Adds the time since start to the part, and returns now so the next part
+    // can start from it without reading the clock again
+    pub fn add(self: &mut Self, part: Part, start: Instant) -> Instant {
+        let now = Instant::now();
+        let time = match part {
+            Part::Ppu => &mut self.ppu,
+            Part::Apu => &mut self.apu,
+            Part::Mbc => &mut self.mbc,
+        };
+        *time += now - start;
+        return now;
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Report {
+    pub fps: f64,
+    pub frame_ms: f64, // Host time for each frame, not counting waiting
+    pub draw_ms: f64,  // Part of frame_ms
+    pub speed_percent: f64,
+    pub instructions_per_sec: f64,
+    pub split: Option<[f64; 4]>, // Percent of emulating time in the cpu, ppu, apu and mbc
+}
+
+impl Report {
+    // Short enough to fit across the gameboy screen
+    pub fn lines(self: &Self) -> Vec<String> {
+        let mut lines = vec![
+            format!("{:.1} FPS {:.0}%", self.fps, self.speed_percent),
+            format!("FRAME {:.2}MS", self.frame_ms),
+            format!("DRAW {:.2}MS", self.draw_ms),
+            format!("{:.2}M INSTR/S", self.instructions_per_sec / 1_000_000.0),
+        ];
+        if let Some([cpu, ppu, apu, mbc]) = self.split {
+            lines.push(format!("CPU {:.0}% PPU {:.0}%", cpu, ppu));
+            lines.push(format!("APU {:.0}% MBC {:.0}%", apu, mbc));
+        }
+        return lines;
+    }
+
+    pub fn log_line(self: &Self) -> String {
+        let mut line = format!(
+            "fps: {:.2}, frame: {:.3}ms, draw: {:.3}ms, speed: {:.1}%, instructions/s: {:.0}",
+            self.fps, self.frame_ms, self.draw_ms, self.speed_percent, self.instructions_per_sec
+        );
+        if let Some([cpu, ppu, apu, mbc]) = self.split {
+            line += &format!(
+                ", cpu: {:.1}%, ppu: {:.1}%, apu: {:.1}%, mbc: {:.1}%",
+                cpu, ppu, apu, mbc
+            );
+        }
+        return line;
+    }
+}
+
+pub struct Perf {
+    start: Instant, // Of this report
+    last_lap: Instant,
+    laps: [Duration; 4], // Indexed by Lap
+    frames: u64,
+    instructions: u64,
+}
+
+impl Perf {
+    pub fn new() -> Perf {
+        let now = Instant::now();
+        return Perf {
+            start: now,
+            last_lap: now,
+            laps: [Duration::ZERO; 4],
+            frames: 0,
+            instructions: 0,
+        };
+    }
+
+    pub fn count_instruction(self: &mut Self) {
+        self.instructions += 1;
+    }
+
+    // The time since the last lap was spent on this
+    pub fn lap(self: &mut Self, lap: Lap) {
+        let now = Instant::now();
+        self.laps[lap as usize] += now - self.last_lap;
+        self.last_lap = now;
+    }
+
+    // Called once a frame, true when it is time for a report
+    pub fn end_frame(self: &mut Self) -> bool {
+        self.frames += 1;
+        return self.last_lap - self.start >= REPORT_TIME;
+    }
+
+    // Everything since the last report, parts is None without the profile feature
+    pub fn report(self: &mut Self, parts: Option<PartTimes>) -> Report {
+        let elapsed = self.last_lap - self.start;
+        let report = make_report(elapsed, self.frames, self.instructions, self.laps, parts);
+        self.start = self.last_lap;
+        self.laps = [Duration::ZERO; 4];
+        self.frames = 0;
+        self.instructions = 0;
+        return report;
+    }
+}
+
+// What --benchmark prints at the end, speed is how many times faster than a real gameboy
+pub fn benchmark_summary(frames: u64, elapsed: Duration) -> String {
+    let secs = elapsed.as_secs_f64();
+    let fps = frames as f64 / secs;
+    return format!(
+        "Benchmark: {} frames in {:.3}s, {:.1} fps, {:.2}x real time",
+        frames,
+        secs,
+        fps,
+        fps / FRAME_RATE
+    );
+}
+
+fn make_report(
+    elapsed: Duration,
+    frames: u64,
+    instructions: u64,
+    laps: [Duration; 4],
+    parts: Option<PartTimes>,
+) -> Report {
+    let secs = elapsed.as_secs_f64();
+    let frames = std::cmp::max(frames, 1) as f64;
+    let busy = elapsed.saturating_sub(laps[Lap::Wait as usize]);
+    let emulate = laps[Lap::Emulate as usize];
+    let split = parts.map(|parts| {
+        let percent = |time: Duration| time.as_secs_f64() / emulate.as_secs_f64() * 100.0;
+        let cpu = emulate.saturating_sub(parts.ppu + parts.apu + parts.mbc);
+        [
+            percent(cpu),
+            percent(parts.ppu),
+            percent(parts.apu),
+            percent(parts.mbc),
+        ]
+    });
+    return Report {
+        fps: frames / secs,
+        frame_ms: busy.as_secs_f64() * 1000.0 / frames,
+        draw_ms: laps[Lap::Draw as usize].as_secs_f64() * 1000.0 / frames,
+        speed_percent: frames / FRAME_RATE / secs * 100.0,
+        instructions_per_sec: instructions as f64 / secs,
+        split: split,
+    };
+}
+
+#[test]
+fn test_make_report() {
+    let ms = |n| Duration::from_millis(n);
+    // 60 frames in a second, 400ms emulating, 100ms drawing, 450ms waiting
+    let laps = [ms(400), ms(100), ms(450), ms(50)];
+    let parts = PartTimes {
+        ppu: ms(100),
+        apu: ms(60),
+        mbc: ms(20),
+    };
+    let report = make_report(ms(1000), 60, 1_000_000, laps, Some(parts));
+    assert_eq!(report.fps, 60.0);
+    assert!((report.frame_ms - 550.0 / 60.0).abs() < 0.0001);
+    assert!((report.draw_ms - 100.0 / 60.0).abs() < 0.0001);
+    assert!((report.speed_percent - 60.0 / FRAME_RATE * 100.0).abs() < 0.0001);
+    assert_eq!(report.instructions_per_sec, 1_000_000.0);
+    let split = report.split.unwrap();
+    let expected = [55.0, 25.0, 15.0, 5.0];
+    for (percent, expected) in split.iter().zip(expected) {
+        assert!((percent - expected).abs() < 0.0001);
+    }
+    assert_eq!(report.lines().len(), 6);
+
+    let report = make_report(ms(1000), 60, 1_000_000, laps, None);
+    assert_eq!(report.split, None);
+    assert_eq!(report.lines().len(), 4);
+    assert!(!report.log_line().contains("cpu"));
+}
+
+#[test]
+fn test_benchmark_summary() {
+    let summary = benchmark_summary(6000, Duration::from_secs(10));
+    assert_eq!(
+        summary,
+        "Benchmark: 6000 frames in 10.000s, 600.0 fps, 10.05x real time"
+    );
+}
+
+#[test]
+fn test_part_times() {
+    let mut parts = PartTimes::new();
+    let start = Instant::now() - Duration::from_millis(10);
+    let next = parts.add(Part::Ppu, start);
+    assert!(parts.ppu >= Duration::from_millis(10));
+    parts.add(Part::Apu, next);
+    assert!(parts.apu < Duration::from_millis(10));
+    assert_eq!(parts.mbc, Duration::ZERO);
+}
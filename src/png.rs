@@ -0,0 +1,129 @@
+/*
+    Just enough of PNG to write out images of the screen and the debug viewers.
+    Images are 8 bit RGBA, unfiltered, and the zlib stream uses stored (uncompressed)
+    deflate blocks, so the files are bigger than they need to be but every viewer
+    can open them and we dont need a compression library.
+
+    Pixels come in the same BGRA byte order as the framebuffer (See COLORS in
+    gpu_memory.rs) and get swapped to RGBA here.
+
+    https://www.w3.org/TR/png/
+*/
+
+use std::fs;
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+const MAX_STORED_BLOCK: usize = 0xFFFF;
+
+pub fn encode(width: usize, height: usize, bgra: &[u8]) -> Vec<u8> {
+    assert_eq!(
+        bgra.len(),
+        width * height * 4,
+        "Image size doesnt match its pixels"
+    );
+
+    let mut ihdr = Vec::with_capacity(13);
+    ihdr.extend_from_slice(&(width as u32).to_be_bytes());
+    ihdr.extend_from_slice(&(height as u32).to_be_bytes());
+    ihdr.extend_from_slice(&[8, 6, 0, 0, 0]); // 8 bit depth, RGBA, deflate, no filter, no interlace
+
+    // Every row starts with its filter type (0 = None)
+    let mut raw = Vec::with_capacity(height * (1 + width * 4));
+    for row in bgra.chunks(width * 4) {
+        raw.push(0);
+        for pixel in row.chunks(4) {
+            raw.extend_from_slice(&[pixel[2], pixel[1], pixel[0], pixel[3]]);
+        }
+    }
+
+    let mut png = Vec::new();
+    png.extend_from_slice(&SIGNATURE);
+    write_chunk(&mut png, b"IHDR", &ihdr);
+    write_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+    write_chunk(&mut png, b"IEND", &[]);
+    return png;
+}
+
+pub fn save(path: &str, width: usize, height: usize, bgra: &[u8]) -> Result<(), String> {
+    return fs::write(path, encode(width, height, bgra))
+        .map_err(|e| format!("Couldnt write image {}: {}", path, e));
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01]; // Deflate with a 32K window, no dictionary
+    let mut blocks = data.chunks(MAX_STORED_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[0x01, 0x00, 0x00, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let len = block.len() as u16;
+        out.push(if last { 0x01 } else { 0x00 });
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    return out;
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xFFFFFFFF;
+    for byte in bytes {
+        crc ^= u32::from(*byte);
+        for _ in 0..8 {
+            let mask = (!(crc & 1)).wrapping_add(1);
+            crc = (crc >> 1) ^ (0xEDB88320 & mask);
+        }
+    }
+    return !crc;
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in bytes {
+        a = (a + u32::from(*byte)) % 65521;
+        b = (b + a) % 65521;
+    }
+    return (b << 16) | a;
+}
+
+#[test]
+fn test_png_checksums() {
+    assert_eq!(crc32(b"123456789"), 0xCBF43926);
+    assert_eq!(crc32(b"IEND"), 0xAE426082);
+    assert_eq!(adler32(b"Wikipedia"), 0x11E60398);
+}
+
+#[test]
+fn test_png_encode() {
+    // 2x1, one red and one see through blue pixel (BGRA)
+    let png = encode(2, 1, &[0x00, 0x00, 0xFF, 0xFF, 0xFF, 0x00, 0x00, 0x80]);
+
+    assert_eq!(png[..8], SIGNATURE);
+    assert_eq!(png[12..16], *b"IHDR");
+    assert_eq!(png[16..24], [0, 0, 0, 2, 0, 0, 0, 1]);
+    assert_eq!(
+        png[png.len() - 12..],
+        [0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82]
+    );
+
+    // IDAT: zlib header, one stored block holding the filter byte and RGBA pixels
+    let idat = &png[33..png.len() - 12];
+    assert_eq!(idat[..4], [0, 0, 0, 2 + 5 + 9 + 4]);
+    assert_eq!(idat[4..8], *b"IDAT");
+    assert_eq!(idat[8..15], [0x78, 0x01, 0x01, 9, 0, !9, 0xFF]);
+    assert_eq!(
+        idat[15..24],
+        [0, 0xFF, 0x00, 0x00, 0xFF, 0x00, 0x00, 0xFF, 0x80]
+    );
+}
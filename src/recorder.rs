@@ -0,0 +1,74 @@
+/*
+    Records gameplay into a folder, one png for every frame the ppu finishes and
+    a wav of the audio captured over the same cycles:
+        TETRIS_2026-10-18_15-30-00/
+            frame_000001.png
+            frame_000002.png
+            ...
+            audio.wav
+    Nothing is compressed so nothing is lost, but it takes up a lot of room
+    (about 5MB a second). The gameboy runs at 4194304 / 70224 = 59.7275 frames
+    a second, so to turn a recording into a video:
+        ffmpeg -framerate 59.7275 -i frame_%06d.png -i audio.wav -c:v ffv1 out.mkv
+*/
+
+use crate::graphics::viewer::Image;
+use crate::wav::WavWriter;
+use std::path::{Path, PathBuf};
+
+pub const FRAME_RATE: f64 = 4_194_304.0 / 70_224.0;
+
+pub struct Recorder {
+    dir: PathBuf,
+    frames: u64,
+    wav: WavWriter,
+}
+
+impl Recorder {
+    pub fn start(dir: &Path, sample_rate: u32) -> Result<Recorder, String> {
+        if let Err(e) = std::fs::create_dir_all(dir) {
+            return Err(format!("Couldnt create {}: {}", dir.display(), e));
+        }
+        let wav_path = dir.join("audio.wav");
+        let wav = WavWriter::create(&wav_path.to_string_lossy(), sample_rate)?;
+        return Ok(Recorder {
+            dir: dir.to_path_buf(),
+            frames: 0,
+            wav: wav,
+        });
+    }
+
+    pub fn dir(self: &Self) -> &Path {
+        return &self.dir;
+    }
+
+    // The audio samples are the ones from while the frame was being drawn
+    pub fn add_frame(self: &mut Self, image: &Image, samples: &[f32]) -> Result<(), String> {
+        self.frames += 1;
+        let path = self.dir.join(format!("frame_{:06}.png", self.frames));
+        image.save_png(&path.to_string_lossy())?;
+        return self.wav.add_samples(samples);
+    }
+
+    // Returns how many frames were recorded
+    pub fn finish(self: Self) -> Result<u64, String> {
+        self.wav.finish()?;
+        return Ok(self.frames);
+    }
+}
+
+#[test]
+fn test_recorder() {
+    let dir = std::env::temp_dir().join(format!("gb_test_rec_{}", std::process::id()));
+    let mut recorder = Recorder::start(&dir, 48_000).unwrap();
+    let image = Image::new(2, 2);
+    recorder.add_frame(&image, &[0.5; 1608]).unwrap();
+    recorder.add_frame(&image, &[0.5; 1606]).unwrap();
+    assert_eq!(recorder.finish().unwrap(), 2);
+
+    assert!(dir.join("frame_000001.png").exists());
+    assert!(dir.join("frame_000002.png").exists());
+    let wav = std::fs::read(dir.join("audio.wav")).unwrap();
+    assert_eq!(wav.len(), 44 + ((1608 + 1606) * 2));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
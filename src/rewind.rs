@@ -0,0 +1,205 @@
+/*
+    Snapshots of the last while of gameplay (see state.rs) for rewinding.
+
+    A snapshot gets taken every `interval` frames. Only the newest one is kept
+    whole, every older one is kept as the difference to the one after it. Two
+    snapshots in a row are mostly the same, so xoring them together gives mostly
+    zeros, and the runs of zeros are left out:
+        zero run length (u32), literal length (u32), literal bytes, ...
+    Going back a step xors the newest snapshot with its difference to get the one
+    before it. The differences only depend on the snapshot after them, so once the
+    memory limit is reached the oldest ones can just be dropped.
+
+    Snapshots arent always the same length (the ppu fifos and sprite list change
+    size), the shorter one is treated as if it had zeros on the end.
+*/
+
+use std::collections::VecDeque;
+
+const MIN_ZERO_RUN: usize = 8; // Shorter runs of zeros stay in the literal, they cost more to skip
+
+// Turns the snapshot after it back into this one
+struct Delta {
+    frame: u64,
+    len: usize,
+    diff: Vec<u8>, // Compressed
+}
+
+pub struct Rewind {
+    interval: u64,
+    max_bytes: usize,
+    frames: u64,                    // Since the last snapshot
+    newest: Option<(u64, Vec<u8>)>, // Frame it was taken on and the snapshot
+    deltas: VecDeque<Delta>,        // Oldest first
+    used_bytes: usize,              // By the newest snapshot and all the deltas
+}
+
+impl Rewind {
+    pub fn new(interval: u64, max_bytes: usize) -> Rewind {
+        return Rewind {
+            interval: interval,
+            max_bytes: max_bytes,
+            frames: 0,
+            newest: None,
+            deltas: VecDeque::new(),
+            used_bytes: 0,
+        };
+    }
+
+    // Called once every frame, true when a snapshot should be pushed
+    pub fn is_snapshot_frame(self: &mut Self) -> bool {
+        self.frames += 1;
+        if self.frames >= self.interval {
+            self.frames = 0;
+            return true;
+        }
+        return false;
+    }
+
+    pub fn push(self: &mut Self, frame: u64, snapshot: Vec<u8>) {
+        if let Some((prev_frame, prev)) = self.newest.take() {
+            let delta = Delta {
+                frame: prev_frame,
+                len: prev.len(),
+                diff: compress(&xor(&prev, &snapshot)),
+            };
+            self.used_bytes += delta.diff.len();
+            self.used_bytes -= prev.len();
+            self.deltas.push_back(delta);
+        }
+        self.used_bytes += snapshot.len();
+        self.newest = Some((frame, snapshot));
+
+        while self.used_bytes > self.max_bytes {
+            match self.deltas.pop_front() {
+                Some(oldest) => self.used_bytes -= oldest.diff.len(),
+                None => break,
+            }
+        }
+    }
+
+    // Takes out the newest snapshot, the one before it becomes the newest.
+    // None once there is nothing left to go back to
+    pub fn pop(self: &mut Self) -> Option<(u64, Vec<u8>)> {
+        let (frame, snapshot) = self.newest.take()?;
+        self.used_bytes -= snapshot.len();
+        self.frames = 0;
+        if let Some(delta) = self.deltas.pop_back() {
+            let mut prev = snapshot.clone();
+            prev.resize(std::cmp::max(prev.len(), delta.len), 0);
+            apply(&delta.diff, &mut prev);
+            prev.truncate(delta.len);
+            self.used_bytes -= delta.diff.len();
+            self.used_bytes += prev.len();
+            self.newest = Some((delta.frame, prev));
+        }
+        return Some((frame, snapshot));
+    }
+
+    pub fn len(self: &Self) -> usize {
+        return self.deltas.len() + self.newest.iter().count();
+    }
+
+    pub fn used_bytes(self: &Self) -> usize {
+        return self.used_bytes;
+    }
+}
+
+// As long as the longer of the two
+fn xor(a: &[u8], b: &[u8]) -> Vec<u8> {
+    let len = std::cmp::max(a.len(), b.len());
+    return (0..len)
+        .map(|i| a.get(i).unwrap_or(&0) ^ b.get(i).unwrap_or(&0))
+        .collect();
+}
+
+fn compress(diff: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < diff.len() {
+        let zeros_start = i;
+        while i < diff.len() && diff[i] == 0 {
+            i += 1;
+        }
+        let literal_start = i;
+        while i < diff.len() && !diff[i..].starts_with(&[0; MIN_ZERO_RUN]) {
+            i += 1;
+        }
+        out.extend_from_slice(&((literal_start - zeros_start) as u32).to_le_bytes());
+        out.extend_from_slice(&((i - literal_start) as u32).to_le_bytes());
+        out.extend_from_slice(&diff[literal_start..i]);
+    }
+    return out;
+}
+
+// Xors the compressed difference into target
+fn apply(compressed: &[u8], target: &mut [u8]) {
+    let read_u32 = |at: usize| u32::from_le_bytes(compressed[at..at + 4].try_into().unwrap());
+    let mut pos = 0;
+    let mut i = 0;
+    while i < compressed.len() {
+        pos += read_u32(i) as usize;
+        let literal_len = read_u32(i + 4) as usize;
+        i += 8;
+        for byte in &compressed[i..i + literal_len] {
+            target[pos] ^= byte;
+            pos += 1;
+        }
+        i += literal_len;
+    }
+}
+
+#[test]
+fn test_compress() {
+    let a = [vec![1, 2, 3], vec![7; 100], vec![4, 5]].concat();
+    let b = [vec![1, 9, 3], vec![7; 100], vec![4, 6, 8]].concat();
+    let diff = xor(&a, &b);
+    let compressed = compress(&diff);
+    // Only the bytes that changed end up in literals, the rest get skipped
+    assert_eq!(compressed.len(), (8 + 1) + (8 + 2));
+
+    let mut restored = b.clone();
+    apply(&compressed, &mut restored);
+    restored.truncate(a.len());
+    assert_eq!(restored, a);
+}
+
+#[test]
+fn test_rewind_order() {
+    let mut rewind = Rewind::new(1, usize::MAX);
+    for frame in 1..=4 {
+        let snapshot = vec![frame as u8; 16 + frame as usize]; // Different lengths
+        rewind.push(frame, snapshot);
+    }
+    assert_eq!(rewind.len(), 4);
+    for frame in (1..=4).rev() {
+        assert_eq!(
+            rewind.pop(),
+            Some((frame, vec![frame as u8; 16 + frame as usize]))
+        );
+    }
+    assert_eq!(rewind.pop(), None);
+    assert_eq!(rewind.used_bytes(), 0);
+}
+
+#[test]
+fn test_rewind_memory_limit() {
+    // Each new snapshot differs everywhere, so each delta is the full 100 bytes plus 8
+    let mut rewind = Rewind::new(1, 400);
+    for frame in 0..10 {
+        rewind.push(frame, vec![frame as u8; 100]);
+        assert!(rewind.used_bytes() <= 400);
+    }
+    assert_eq!(rewind.len(), 3);
+    assert_eq!(rewind.pop().unwrap().0, 9);
+    assert_eq!(rewind.pop().unwrap().0, 8);
+    assert_eq!(rewind.pop().unwrap().0, 7);
+    assert_eq!(rewind.pop(), None);
+}
+
+#[test]
+fn test_snapshot_interval() {
+    let mut rewind = Rewind::new(3, usize::MAX);
+    let taken: Vec<bool> = (0..6).map(|_| rewind.is_snapshot_frame()).collect();
+    assert_eq!(taken, vec![false, false, true, false, false, true]);
+}
@@ -0,0 +1,95 @@
+/*
+    Screenshots of the gameboy screen (See Cpu::screenshot for the image itself).
+    They get named after the game and the time they were taken, so they dont
+    overwrite each other
+        TETRIS_2026-10-18_15-30-00.png
+    and ones taken on a certain frame (--screenshot-at) get the frame number too
+        TETRIS_2026-10-18_15-30-00_frame600.png
+    Times are UTC since getting the local timezone needs more than std has.
+*/
+
+use crate::graphics::viewer::Image;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+pub fn save(
+    image: &Image,
+    dir: Option<&str>,
+    title: &str,
+    frame: Option<u64>,
+) -> Result<PathBuf, String> {
+    let dir = Path::new(dir.unwrap_or("."));
+    if let Err(e) = std::fs::create_dir_all(dir) {
+        return Err(format!("Couldnt create {}: {}", dir.display(), e));
+    }
+
+    let name = name_now(title, frame);
+
+    // Two in the same second get a number on the end
+    let mut path = dir.join(format!("{}.png", name));
+    let mut count = 2;
+    while path.exists() {
+        path = dir.join(format!("{}_{}.png", name, count));
+        count += 1;
+    }
+
+    image.save_png(&path.to_string_lossy())?;
+    return Ok(path);
+}
+
+// Also used for naming video recordings
+pub fn name_now(title: &str, frame: Option<u64>) -> String {
+    let secs = match SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(time) => time.as_secs(),
+        Err(_) => 0,
+    };
+    return file_name(title, secs, frame);
+}
+
+fn file_name(title: &str, unix_secs: u64, frame: Option<u64>) -> String {
+    let (year, month, day) = civil_date(unix_secs / 86_400);
+    let secs = unix_secs % 86_400;
+    let mut name = format!(
+        "{}_{:04}-{:02}-{:02}_{:02}-{:02}-{:02}",
+        title,
+        year,
+        month,
+        day,
+        secs / 3600,
+        (secs / 60) % 60,
+        secs % 60
+    );
+    if let Some(frame) = frame {
+        name.push_str(&format!("_frame{}", frame));
+    }
+    return name;
+}
+
+// Days since 1970-01-01 to (year, month, day)
+// http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_date(days: u64) -> (u64, u64, u64) {
+    let days = days + 719_468; // Shift the epoch to 0000-03-01
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153; // Months since March
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    return (year, month, day);
+}
+
+#[test]
+fn test_screenshot_name() {
+    assert_eq!(civil_date(0), (1970, 1, 1));
+    assert_eq!(civil_date(11_016), (2000, 2, 29));
+    assert_eq!(civil_date(20_744), (2026, 10, 18));
+
+    assert_eq!(file_name("TETRIS", 0, None), "TETRIS_1970-01-01_00-00-00");
+    assert_eq!(
+        file_name("TETRIS", (20_744 * 86_400) + 55_800, Some(600)),
+        "TETRIS_2026-10-18_15-30-00_frame600"
+    );
+}
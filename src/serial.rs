@@ -1,5 +1,7 @@
 use super::cpu::CPU_FREQ;
 use super::io::Io;
+use crate::model::Model;
+use crate::state::{StateReader, StateWriter};
 
 pub const SB_REG: u16 = 0xFF01;
 pub const SC_REG: u16 = 0xFF02;
@@ -23,6 +25,20 @@ impl Serial {
         };
     }
 
+    pub fn save_state(self: &Self, state: &mut StateWriter) {
+        state.write_u8(self.sb);
+        state.write_u8(self.sc);
+        state.write_bool(self.transferring);
+        state.write_usize(self.transfer_cycles);
+    }
+
+    pub fn load_state(self: &mut Self, state: &mut StateReader) {
+        self.sb = state.read_u8();
+        self.sc = state.read_u8();
+        self.transferring = state.read_bool();
+        self.transfer_cycles = state.read_usize();
+    }
+
     pub fn read_byte(self: &Self, addr: u16) -> u8 {
         return match addr {
             SB_REG => self.sb,
@@ -69,9 +85,10 @@ impl Serial {
         }
     }
 
-    pub fn dmg_init(self: &mut Self) {
+    // Bit 1 (clock speed) only exists on the cgb, otherwise it reads as 1 like the other unused bits
+    pub fn init(self: &mut Self, model: Model) {
         self.sb = 0x00;
-        self.sc = 0x7E;
+        self.sc = if model.is_cgb() { 0x7F } else { 0x7E };
     }
 
     fn start_transfer(self: &mut Self) {
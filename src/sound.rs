@@ -4,6 +4,10 @@
         Quadrangular wave patterns with envelope functions (CH2)
         Voluntary wave patterns from wave RAM (CH3)
         White noise with an envelope function (CH4)
+
+    Samples are only kept while something is capturing them (See set_capture).
+    They come out at sample_rate as interleaved left/right pairs between -1.0 and 1.0,
+    counted off the same cycles as the ppu so they stay in sync with the frames
 */
 
 mod channel3;
@@ -13,6 +17,8 @@ mod tone_sweep;
 use self::channel3::Ch3;
 use self::channel4::Ch4;
 use self::tone_sweep::Tone;
+use crate::cpu::CPU_FREQ;
+use crate::state::{StateReader, StateWriter};
 
 // Sound
 pub const SOUND_START: u16 = 0xFF10;
@@ -44,6 +50,8 @@ pub const PCM34: u16 = 0xFF77; // CGB Only
 pub const WAVE_RAM_START: u16 = 0xFF30;
 pub const WAVE_RAM_END: u16 = 0xFF3F;
 
+const MAX_AMPLITUDE: f32 = 4.0 * 8.0;
+
 pub const DUTY_WAVES: [[u8; 8]; 4] = [
     [0, 0, 0, 0, 0, 0, 0, 1],
     [0, 0, 0, 0, 0, 0, 1, 1],
@@ -61,6 +69,12 @@ pub struct Sound {
     nr52: SoundControl,
     pcm12: u8,
     pcm34: u8,
+    enabled: bool,    // From the config, not the same as nr52 master_on
+    volume: f32,      // 0.0 - 1.0 applied after the gameboy's own volume
+    sample_rate: u32, // Rate the output will be generated at
+    capture: bool,
+    samples: Vec<f32>,
+    sample_counter: usize, // Goes up by sample_rate each cycle, a sample every CPU_FREQ
 }
 
 impl Sound {
@@ -75,9 +89,25 @@ impl Sound {
             nr52: SoundControl::new(),
             pcm12: 0,
             pcm34: 0,
+            enabled: true,
+            volume: 1.0,
+            sample_rate: 48_000,
+            capture: false,
+            samples: Vec::new(),
+            sample_counter: 0,
         };
     }
 
+    // Back to power on, keeping the audio settings and whether samples are captured
+    pub fn reset(self: &mut Self) {
+        let mut sound = Sound::new();
+        sound.enabled = self.enabled;
+        sound.volume = self.volume;
+        sound.sample_rate = self.sample_rate;
+        sound.capture = self.capture;
+        *self = sound;
+    }
+
     pub fn read_byte(self: &Self, addr: u16) -> u8 {
         return match addr {
             NR10 | NR11 | NR12 | NR13 | NR14 => self.ch1.read_byte(addr),
@@ -128,6 +158,65 @@ impl Sound {
         };
     }
 
+    // Just the channels and registers, not the settings or captured samples
+    pub fn save_state(self: &Self, state: &mut StateWriter) {
+        self.ch1.save_state(state);
+        self.ch2.save_state(state);
+        self.ch3.save_state(state);
+        self.ch4.save_state(state);
+        state.write_u8(self.nr50.get());
+        state.write_u8(self.nr51);
+        let nr52 = &self.nr52;
+        for on in [
+            nr52.master_on,
+            nr52.ch4_on,
+            nr52.ch3_on,
+            nr52.ch2_on,
+            nr52.ch1_on,
+        ] {
+            state.write_bool(on);
+        }
+        state.write_u8(self.pcm12);
+        state.write_u8(self.pcm34);
+    }
+
+    pub fn load_state(self: &mut Self, state: &mut StateReader) {
+        self.ch1.load_state(state);
+        self.ch2.load_state(state);
+        self.ch3.load_state(state);
+        self.ch4.load_state(state);
+        self.nr50.set(state.read_u8());
+        self.nr51 = state.read_u8();
+        self.nr52.master_on = state.read_bool();
+        self.nr52.ch4_on = state.read_bool();
+        self.nr52.ch3_on = state.read_bool();
+        self.nr52.ch2_on = state.read_bool();
+        self.nr52.ch1_on = state.read_bool();
+        self.pcm12 = state.read_u8();
+        self.pcm34 = state.read_u8();
+    }
+
+    pub fn set_audio_settings(self: &mut Self, enabled: bool, volume: u8, sample_rate: u32) {
+        self.enabled = enabled;
+        self.volume = f32::from(volume) / 100.0;
+        self.sample_rate = sample_rate;
+    }
+
+    pub fn get_sample_rate(self: &Self) -> u32 {
+        return self.sample_rate;
+    }
+
+    pub fn set_capture(self: &mut Self, capture: bool) {
+        self.capture = capture;
+        self.samples.clear();
+        self.sample_counter = 0;
+    }
+
+    // Everything captured since the last call
+    pub fn take_samples(self: &mut Self) -> Vec<f32> {
+        return std::mem::take(&mut self.samples);
+    }
+
     fn restart(self: &mut Self) {
         self.ch1.restart();
         self.ch2.restart();
@@ -154,6 +243,25 @@ impl Sound {
         self.nr52.ch2_on = self.ch2.is_ch_enabled() || self.ch2.is_counter_off();
         self.nr52.ch3_on = self.ch3.is_ch_enabled() || self.ch3.is_counter_off();
         self.nr52.ch4_on = self.ch4.is_ch_enabled() || self.ch4.is_counter_off();
+
+        if self.capture {
+            self.sample_counter += cycles * self.sample_rate as usize;
+            while self.sample_counter >= CPU_FREQ {
+                self.sample_counter -= CPU_FREQ;
+                self.push_sample();
+            }
+        }
+    }
+
+    fn push_sample(self: &mut Self) {
+        let outputs = self.get_channel_outputs();
+        let (left, right) = self.mixer(&outputs);
+        // Recordings get the game's own mix, the volume from the config (see
+        // user_volume) is only for what gets played live
+        let (left, right) = self.amplifier(left, right);
+        // 4 channels at most 1.0 each, times the largest master volume (8)
+        self.samples.push(left / MAX_AMPLITUDE);
+        self.samples.push(right / MAX_AMPLITUDE);
     }
 
     fn get_channel_outputs(self: &mut Self) -> [f32; 4] {
@@ -198,6 +306,12 @@ impl Sound {
         );
     }
 
+    // The volume set in the config, or 0 when it is muted
+    fn user_volume(self: &Self, left: f32, right: f32) -> (f32, f32) {
+        let volume = if self.enabled { self.volume } else { 0.0 };
+        return (left * volume, right * volume);
+    }
+
     fn high_pass_filter(self: &mut Self) {
         // The last step is supposed to be that all outputs go
         // through a high pass filter to remove the DC offsets
@@ -307,6 +421,18 @@ struct LenPat {
 }
 
 impl LenPat {
+    pub fn save_state(self: &Self, state: &mut StateWriter) {
+        state.write_u8(self.duty);
+        state.write_u8(self.length);
+        state.write_u32(self.timer);
+    }
+
+    pub fn load_state(self: &mut Self, state: &mut StateReader) {
+        self.duty = state.read_u8();
+        self.length = state.read_u8();
+        self.timer = state.read_u32();
+    }
+
     pub fn new(mask: u8) -> LenPat {
         return LenPat {
             duty: 0, // Not used by ch3 and ch4
@@ -353,6 +479,22 @@ struct VolEnv {
 }
 
 impl VolEnv {
+    pub fn save_state(self: &Self, state: &mut StateWriter) {
+        state.write_u8(self.initial_vol);
+        state.write_bool(self.dir_up);
+        state.write_u8(self.sweep);
+        state.write_u32(self.timer);
+        state.write_u8(self.cur_vol);
+    }
+
+    pub fn load_state(self: &mut Self, state: &mut StateReader) {
+        self.initial_vol = state.read_u8();
+        self.dir_up = state.read_bool();
+        self.sweep = state.read_u8();
+        self.timer = state.read_u32();
+        self.cur_vol = state.read_u8();
+    }
+
     pub fn new() -> VolEnv {
         return VolEnv {
             initial_vol: 0,
@@ -429,6 +571,22 @@ impl Freq {
     const MASK_LO: u8 = 0xFF;
     const MASK_HI: u8 = 0xBF;
 
+    pub fn save_state(self: &Self, state: &mut StateWriter) {
+        state.write_bool(self.initial);
+        state.write_bool(self.len_enable);
+        state.write_u8(self.hi);
+        state.write_u8(self.lo);
+        state.write_u32(self.timer);
+    }
+
+    pub fn load_state(self: &mut Self, state: &mut StateReader) {
+        self.initial = state.read_bool();
+        self.len_enable = state.read_bool();
+        self.hi = state.read_u8();
+        self.lo = state.read_u8();
+        self.timer = state.read_u32();
+    }
+
     pub fn new(multiple: u8) -> Freq {
         return Freq {
             initial: false,
@@ -493,3 +651,39 @@ impl Freq {
         self.timer = (2048 - self.get_full() as u32) * u32::from(self.cycle_multiplier);
     }
 }
+
+#[test]
+fn test_sample_capture() {
+    let mut sound = Sound::new();
+    sound.dmg_init();
+    sound.set_audio_settings(true, 100, 48_000);
+    sound.adv_cycles(CPU_FREQ / 10);
+    assert!(sound.take_samples().is_empty()); // Not capturing
+
+    // An eighth of a second is 6000 stereo samples, however the cycles are split up
+    sound.set_capture(true);
+    for _ in 0..(CPU_FREQ / 32) {
+        sound.adv_cycles(4);
+    }
+    let samples = sound.take_samples();
+    assert_eq!(samples.len(), 6000 * 2);
+    assert!(samples.iter().all(|s| (-1.0..=1.0).contains(s)));
+    assert!(sound.take_samples().is_empty());
+}
+
+#[test]
+fn test_capture_ignores_user_volume() {
+    let capture = |enabled: bool, volume: u8| {
+        let mut sound = Sound::new();
+        sound.dmg_init();
+        sound.set_audio_settings(enabled, volume, 48_000);
+        sound.set_capture(true);
+        sound.write_byte(NR50, 0x33); // Half of the master volume
+        sound.adv_cycles(CPU_FREQ / 60);
+        return sound.take_samples();
+    };
+    let loud = capture(true, 100);
+    assert!(loud.iter().any(|s| *s != 0.0));
+    assert_eq!(capture(true, 20), loud);
+    assert_eq!(capture(false, 100), loud); // Muted
+}
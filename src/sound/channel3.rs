@@ -1,6 +1,7 @@
 use super::{Freq, LenPat};
 #[allow(unused_imports)]
 use super::{NR30, NR31, NR32, NR33, NR34, WAVE_RAM_END, WAVE_RAM_START};
+use crate::state::{StateReader, StateWriter};
 
 pub struct Ch3 {
     is_on: bool,      // NR30 (1 is playback)
@@ -33,6 +34,30 @@ impl Ch3 {
         }
     }
 
+    pub fn save_state(self: &Self, state: &mut StateWriter) {
+        state.write_bool(self.is_on);
+        self.len.save_state(state);
+        state.write_u8(self.output_level);
+        self.freq.save_state(state);
+        state.write_u8(self.frame_seq);
+        state.write_usize(self.wave_pos);
+        state.write_usize(self.internal_cycles);
+        state.write_bytes(&self.wave_ram);
+        state.write_u8(self.sample_buffer);
+    }
+
+    pub fn load_state(self: &mut Self, state: &mut StateReader) {
+        self.is_on = state.read_bool();
+        self.len.load_state(state);
+        self.output_level = state.read_u8();
+        self.freq.load_state(state);
+        self.frame_seq = state.read_u8();
+        self.wave_pos = state.read_usize();
+        self.internal_cycles = state.read_usize();
+        state.read_bytes(&mut self.wave_ram);
+        self.sample_buffer = state.read_u8();
+    }
+
     pub fn read_byte(self: &Self, addr: u16) -> u8 {
         match addr {
             NR30 => ((self.is_on as u8) << 7) | 0x7F,
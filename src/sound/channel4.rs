@@ -1,5 +1,6 @@
 use super::{LenPat, VolEnv};
 use super::{NR41, NR42, NR43, NR44};
+use crate::state::{StateReader, StateWriter};
 
 pub struct Ch4 {
     len: LenPat,           // NR41 (Doesnt use duty)
@@ -24,6 +25,35 @@ impl Ch4 {
         }
     }
 
+    pub fn save_state(self: &Self, state: &mut StateWriter) {
+        self.len.save_state(state);
+        self.volenv.save_state(state);
+        let pcounter = &self.pcounter;
+        state.write_u8(pcounter.shift_freq);
+        state.write_bool(pcounter.width);
+        state.write_u8(pcounter.ratio);
+        state.write_u16(pcounter.freq_timer);
+        state.write_bool(self.counter.restart);
+        state.write_bool(self.counter.len_enable);
+        state.write_u8(self.frame_seq);
+        state.write_usize(self.internal_cycles);
+        state.write_u16(self.lfsr);
+    }
+
+    pub fn load_state(self: &mut Self, state: &mut StateReader) {
+        self.len.load_state(state);
+        self.volenv.load_state(state);
+        self.pcounter.shift_freq = state.read_u8();
+        self.pcounter.width = state.read_bool();
+        self.pcounter.ratio = state.read_u8();
+        self.pcounter.freq_timer = state.read_u16();
+        self.counter.restart = state.read_bool();
+        self.counter.len_enable = state.read_bool();
+        self.frame_seq = state.read_u8();
+        self.internal_cycles = state.read_usize();
+        self.lfsr = state.read_u16();
+    }
+
     pub fn read_byte(self: &Self, addr: u16) -> u8 {
         match addr {
             NR41 => self.len.get() | 0xFF,
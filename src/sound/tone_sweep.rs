@@ -2,6 +2,7 @@ use super::DUTY_WAVES;
 use super::{Freq, LenPat, VolEnv};
 use super::{NR10, NR11, NR12, NR13, NR14};
 use super::{NR21, NR22, NR23, NR24};
+use crate::state::{StateReader, StateWriter};
 
 pub struct Tone {
     sweep: Option<Sweep>, // NR10
@@ -31,6 +32,31 @@ impl Tone {
         return self;
     }
 
+    // Only channel 1 has the sweep, which is the same on save and load
+    pub fn save_state(self: &Self, state: &mut StateWriter) {
+        if let Some(sweep) = &self.sweep {
+            sweep.save_state(state);
+        }
+        self.lenpat.save_state(state);
+        self.volenv.save_state(state);
+        self.freq.save_state(state);
+        state.write_u8(self.frame_seq);
+        state.write_usize(self.internal_cycles);
+        state.write_usize(self.duty_pos);
+    }
+
+    pub fn load_state(self: &mut Self, state: &mut StateReader) {
+        if let Some(sweep) = &mut self.sweep {
+            sweep.load_state(state);
+        }
+        self.lenpat.load_state(state);
+        self.volenv.load_state(state);
+        self.freq.load_state(state);
+        self.frame_seq = state.read_u8();
+        self.internal_cycles = state.read_usize();
+        self.duty_pos = state.read_usize();
+    }
+
     pub fn read_byte(self: &Self, addr: u16) -> u8 {
         match addr {
             NR10 => {
@@ -261,6 +287,24 @@ struct Sweep {
 impl Sweep {
     const MASK: u8 = 0x80;
 
+    fn save_state(self: &Self, state: &mut StateWriter) {
+        state.write_u8(self.time);
+        state.write_bool(self.swp_dir);
+        state.write_u8(self.shift);
+        state.write_u32(self.timer);
+        state.write_bool(self.enable);
+        state.write_u16(self.sh_freq);
+    }
+
+    fn load_state(self: &mut Self, state: &mut StateReader) {
+        self.time = state.read_u8();
+        self.swp_dir = state.read_bool();
+        self.shift = state.read_u8();
+        self.timer = state.read_u32();
+        self.enable = state.read_bool();
+        self.sh_freq = state.read_u16();
+    }
+
     pub fn new() -> Sweep {
         return Sweep {
             time: 0,
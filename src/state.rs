@@ -0,0 +1,158 @@
+/*
+    Snapshots of the whole machine, used by rewind (see rewind.rs).
+
+    Every part that changes while a game runs (cpu, memory, ppu, timer, sound,
+    joypad, serial, dma and the mbc) has a save_state that writes its fields here
+    and a load_state that reads them back in the same order. Settings from the
+    config (palette, audio settings, oam bug, ...) and the rom arent included since
+    they dont change from playing, and neither are the debug only fields.
+
+    Snapshots only ever live in memory and get loaded by the same build that made
+    them, so there is no header or version. Numbers are little endian and usize is
+    written as 8 bytes.
+*/
+
+pub struct StateWriter {
+    bytes: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> StateWriter {
+        return StateWriter { bytes: Vec::new() };
+    }
+
+    pub fn write_u8(self: &mut Self, val: u8) {
+        self.bytes.push(val);
+    }
+
+    pub fn write_bool(self: &mut Self, val: bool) {
+        self.bytes.push(val as u8);
+    }
+
+    pub fn write_u16(self: &mut Self, val: u16) {
+        self.bytes.extend_from_slice(&val.to_le_bytes());
+    }
+
+    pub fn write_i16(self: &mut Self, val: i16) {
+        self.bytes.extend_from_slice(&val.to_le_bytes());
+    }
+
+    pub fn write_u32(self: &mut Self, val: u32) {
+        self.bytes.extend_from_slice(&val.to_le_bytes());
+    }
+
+    pub fn write_u64(self: &mut Self, val: u64) {
+        self.bytes.extend_from_slice(&val.to_le_bytes());
+    }
+
+    pub fn write_usize(self: &mut Self, val: usize) {
+        self.write_u64(val as u64);
+    }
+
+    // For things that are always the same size, like the work ram
+    pub fn write_bytes(self: &mut Self, bytes: &[u8]) {
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    // For things that change size, the length goes first
+    pub fn write_vec(self: &mut Self, bytes: &[u8]) {
+        self.write_usize(bytes.len());
+        self.write_bytes(bytes);
+    }
+
+    pub fn finish(self: Self) -> Vec<u8> {
+        return self.bytes;
+    }
+}
+
+// Reading past the end panics, a snapshot is always read by the code that wrote it
+pub struct StateReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> StateReader<'a> {
+        return StateReader {
+            bytes: bytes,
+            pos: 0,
+        };
+    }
+
+    fn take(self: &mut Self, len: usize) -> &'a [u8] {
+        let bytes = &self.bytes[self.pos..self.pos + len];
+        self.pos += len;
+        return bytes;
+    }
+
+    pub fn read_u8(self: &mut Self) -> u8 {
+        return self.take(1)[0];
+    }
+
+    pub fn read_bool(self: &mut Self) -> bool {
+        return self.read_u8() != 0;
+    }
+
+    pub fn read_u16(self: &mut Self) -> u16 {
+        return u16::from_le_bytes(self.take(2).try_into().unwrap());
+    }
+
+    pub fn read_i16(self: &mut Self) -> i16 {
+        return i16::from_le_bytes(self.take(2).try_into().unwrap());
+    }
+
+    pub fn read_u32(self: &mut Self) -> u32 {
+        return u32::from_le_bytes(self.take(4).try_into().unwrap());
+    }
+
+    pub fn read_u64(self: &mut Self) -> u64 {
+        return u64::from_le_bytes(self.take(8).try_into().unwrap());
+    }
+
+    pub fn read_usize(self: &mut Self) -> usize {
+        return self.read_u64() as usize;
+    }
+
+    // Fills all of bytes
+    pub fn read_bytes(self: &mut Self, bytes: &mut [u8]) {
+        bytes.copy_from_slice(self.take(bytes.len()));
+    }
+
+    pub fn read_vec(self: &mut Self) -> Vec<u8> {
+        let len = self.read_usize();
+        return self.take(len).to_vec();
+    }
+
+    // Everything written was read back
+    pub fn is_done(self: &Self) -> bool {
+        return self.pos == self.bytes.len();
+    }
+}
+
+#[test]
+fn test_write_read() {
+    let mut state = StateWriter::new();
+    state.write_u8(0xAB);
+    state.write_bool(true);
+    state.write_u16(0x1234);
+    state.write_i16(-8);
+    state.write_u32(0xDEADBEEF);
+    state.write_usize(70_224);
+    state.write_bytes(&[1, 2, 3]);
+    state.write_vec(&[4, 5]);
+    let bytes = state.finish();
+    assert_eq!(bytes.len(), 1 + 1 + 2 + 2 + 4 + 8 + 3 + (8 + 2));
+
+    let mut state = StateReader::new(&bytes);
+    assert_eq!(state.read_u8(), 0xAB);
+    assert_eq!(state.read_bool(), true);
+    assert_eq!(state.read_u16(), 0x1234);
+    assert_eq!(state.read_i16(), -8);
+    assert_eq!(state.read_u32(), 0xDEADBEEF);
+    assert_eq!(state.read_usize(), 70_224);
+    let mut fixed = [0; 3];
+    state.read_bytes(&mut fixed);
+    assert_eq!(fixed, [1, 2, 3]);
+    assert_eq!(state.read_vec(), vec![4, 5]);
+    assert!(state.is_done());
+}
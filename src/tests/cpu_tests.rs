@@ -2702,3 +2702,135 @@ fn test_handle_interrupt() {
     assert_eq!(cpu.bus.read_byte(cpu.sp + 1), 0x01);
     assert_eq!(cpu.curr_cycles, 20);
 }
+
+#[test]
+fn test_state_round_trip() {
+    let mut cpu = Cpu::new();
+    cpu.pc = 0xC000;
+    cpu.reg.bc = 0x1234;
+    cpu.bus.write_bytes(0xC100, &vec![0xA7, 0x42, 0x99]);
+    let mut state = StateWriter::new();
+    cpu.save_state(&mut state);
+    let snapshot = state.finish();
+
+    let mut loaded = Cpu::new();
+    let mut state = StateReader::new(&snapshot);
+    loaded.load_state(&mut state);
+    assert!(state.is_done());
+    assert_eq!(loaded.pc, 0xC000);
+    assert_eq!(loaded.reg.bc, 0x1234);
+
+    // Saving what was loaded gives back the same snapshot
+    let mut state = StateWriter::new();
+    loaded.save_state(&mut state);
+    assert_eq!(state.finish(), snapshot);
+}
+
+// An mbc3 cart (timer and ram, no battery so no files) with the program at 0x0100
+fn mbc3_cpu(program: &[u8]) -> Cpu {
+    let mut rom = vec![0; 0x8000];
+    rom[0x0100..0x0100 + program.len()].copy_from_slice(program);
+    let mut mbc = Box::new(crate::mbc::mbc3::Mbc3::new());
+    mbc.set_rtc_clock(crate::mbc::RtcClock::Fixed(1_700_000_000));
+    mbc.load_game(
+        "state.gb",
+        rom,
+        vec!["MBC3", "TIMER", "RAM"],
+        0x8000,
+        2,
+        0x2000,
+        1,
+    );
+
+    let mut cpu = Cpu::new();
+    cpu.set_mbc(mbc);
+    cpu.init(Model::Dmg, 0x00);
+    return cpu;
+}
+
+// The same as the emulator's game loop without the frames
+fn run_cycles(cpu: &mut Cpu, cycles: usize) {
+    let mut done = 0;
+    while done < cycles {
+        cpu.check_interrupts();
+        if cpu.is_running {
+            cpu.curr_cycles = 0;
+            cpu.execute();
+        } else {
+            cpu.curr_cycles = 4;
+            cpu.adv_cycles(4);
+        }
+        done += cpu.curr_cycles;
+    }
+}
+
+#[test]
+fn test_state_round_trip_running() {
+    #[rustfmt::skip]
+    let program = [
+        0xAF, 0xE0, 0x40,             // LCD off
+        0x21, 0x00, 0x80,             // Tile 0 striped, tile 1 solid
+        0x06, 0x10, 0x3E, 0xAA, 0x22, 0x05, 0x20, 0xFC,
+        0x06, 0x10, 0x3E, 0xFF, 0x22, 0x05, 0x20, 0xFC,
+        0x21, 0x00, 0xFE,             // Sprite 0 is tile 1 at (32, 32)
+        0x3E, 0x30, 0x22, 0x3E, 0x28, 0x22, 0x3E, 0x01, 0x22, 0xAF, 0x22,
+        0x3E, 0x90, 0xE0, 0x47,       // BGP only uses shades 0 - 2
+        0x3E, 0xE4, 0xE0, 0x48,       // OBP0
+        0x3E, 0x0A, 0xEA, 0x00, 0x00, // Enable the cart ram and rtc
+        0xAF, 0xEA, 0x00, 0x40,       // Ram bank 0
+        0x3E, 0x93, 0xE0, 0x40,       // LCD on with sprites
+        0x04, 0x78, 0xE0, 0x43,       // Loop: SCX = ++B
+        0x21, 0x00, 0xA0, 0x34,       // Cart ram at 0xA000 += 1
+        0x3E, 0x00, 0xEA, 0x00, 0x60, // Latch the rtc
+        0x3E, 0x01, 0xEA, 0x00, 0x60,
+        0x18, 0xEC,                   // Back to the loop
+    ];
+    let mut cpu = mbc3_cpu(&program);
+    run_cycles(&mut cpu, 3 * 70_224);
+    assert!(cpu.get_shades().contains(&3)); // The sprite is on screen
+
+    let mut state = StateWriter::new();
+    cpu.save_state(&mut state);
+    let snapshot = state.finish();
+    let shades_at_snapshot = cpu.get_shades();
+
+    run_cycles(&mut cpu, 100_000);
+    let shades = cpu.get_shades();
+    let regs = (
+        cpu.reg.af, cpu.reg.bc, cpu.reg.de, cpu.reg.hl, cpu.pc, cpu.sp,
+    );
+    assert_ne!(shades, shades_at_snapshot); // SCX kept scrolling
+
+    // A new cpu with the same cart picks up from the snapshot and ends up in the same place
+    let mut loaded = mbc3_cpu(&program);
+    let mut state = StateReader::new(&snapshot);
+    loaded.load_state(&mut state);
+    assert!(state.is_done());
+    run_cycles(&mut loaded, 100_000);
+    assert_eq!(loaded.get_shades(), shades);
+    assert_eq!(
+        (
+            loaded.reg.af,
+            loaded.reg.bc,
+            loaded.reg.de,
+            loaded.reg.hl,
+            loaded.pc,
+            loaded.sp
+        ),
+        regs
+    );
+    assert_eq!(loaded.bus.read_byte(0xA000), cpu.bus.read_byte(0xA000));
+    // Down to the timers, the ppu mode and the rtc
+    let mut state = StateWriter::new();
+    cpu.save_state(&mut state);
+    let after = state.finish();
+    let mut state = StateWriter::new();
+    loaded.save_state(&mut state);
+    assert_eq!(state.finish(), after);
+
+    // And so does the original cpu going back to it
+    let mut state = StateReader::new(&snapshot);
+    cpu.load_state(&mut state);
+    run_cycles(&mut cpu, 100_000);
+    assert_eq!(cpu.get_shades(), shades);
+}
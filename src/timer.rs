@@ -2,6 +2,8 @@
 // https://github.com/AntonioND/giibiiadvance/blob/master/docs/TCAGBD.pdf
 
 use crate::io::Io;
+use crate::model::Model;
+use crate::state::{StateReader, StateWriter};
 pub const TIMER_START: u16 = 0xFF04;
 pub const TIMER_END: u16 = 0xFF07;
 pub const DIV_REG: u16 = 0xFF04;
@@ -57,6 +59,30 @@ impl Timer {
         };
     }
 
+    pub fn save_state(self: &Self, state: &mut StateWriter) {
+        state.write_u16(self.div);
+        state.write_u8(self.tima);
+        state.write_u8(self.tma);
+        state.write_u8(self.tac);
+        state.write_u8(match self.overflow_source {
+            TimaOverflowState::Done => 0,
+            TimaOverflowState::Advancing => 1,
+            TimaOverflowState::None => 2,
+        });
+    }
+
+    pub fn load_state(self: &mut Self, state: &mut StateReader) {
+        self.div = state.read_u16();
+        self.tima = state.read_u8();
+        self.tma = state.read_u8();
+        self.tac = state.read_u8();
+        self.overflow_source = match state.read_u8() {
+            0 => TimaOverflowState::Done,
+            1 => TimaOverflowState::Advancing,
+            _ => TimaOverflowState::None,
+        };
+    }
+
     pub fn read_byte(self: &Self, addr: u16) -> u8 {
         return match addr {
             DIV_REG => (self.div >> 8) as u8,
@@ -206,8 +232,17 @@ impl Timer {
         return (old_div && old_enbl) && !(new_div && new_enbl);
     }
 
-    pub fn dmg_init(self: &mut Self) {
-        self.div = 0xABCC;
+    // The boot roms take a different amount of time to run so the internal
+    // div counter is at a different point when the cartridge starts executing
+    // https://github.com/Gekkio/mooneye-test-suite/blob/main/acceptance/boot_div-dmgABCmgb.s
+    // The other models from https://gbdev.io/pandocs/Power_Up_Sequence.html#div
+    pub fn init(self: &mut Self, model: Model) {
+        self.div = match model {
+            Model::Dmg0 => 0x182C,
+            Model::Dmg | Model::Mgb => 0xABCC,
+            Model::Sgb | Model::Sgb2 => 0xD85C,
+            Model::Cgb => 0x267C,
+        };
         self.tima = 0x00;
         self.tma = 0x00;
         self.tac = 0xF8;
@@ -0,0 +1,107 @@
+/*
+    Writes 16 bit PCM wav files a bit at a time, so a long recording doesnt have
+    to be kept in memory. The sizes in the header arent known until the end, so
+    they get filled in by finish.
+
+    http://soundfile.sapp.org/doc/WaveFormat/
+*/
+
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+
+const HEADER_SIZE: u32 = 44;
+const CHANNELS: u16 = 2;
+const BYTES_PER_SAMPLE: u16 = 2;
+
+pub struct WavWriter {
+    file: BufWriter<File>,
+    path: String,
+    sample_rate: u32,
+    data_len: u32,
+}
+
+impl WavWriter {
+    pub fn create(path: &str, sample_rate: u32) -> Result<WavWriter, String> {
+        let file = match File::create(path) {
+            Ok(f) => f,
+            Err(e) => return Err(format!("Couldnt create {}: {}", path, e)),
+        };
+        let mut wav = WavWriter {
+            file: BufWriter::new(file),
+            path: String::from(path),
+            sample_rate: sample_rate,
+            data_len: 0,
+        };
+        wav.write(&header(sample_rate, 0))?;
+        return Ok(wav);
+    }
+
+    // Interleaved left/right samples between -1.0 and 1.0
+    pub fn add_samples(self: &mut Self, samples: &[f32]) -> Result<(), String> {
+        let mut bytes = Vec::with_capacity(samples.len() * 2);
+        for sample in samples {
+            let sample = (sample.clamp(-1.0, 1.0) * f32::from(i16::MAX)) as i16;
+            bytes.extend_from_slice(&sample.to_le_bytes());
+        }
+        self.data_len += bytes.len() as u32;
+        return self.write(&bytes);
+    }
+
+    pub fn finish(mut self: Self) -> Result<(), String> {
+        let header = header(self.sample_rate, self.data_len);
+        let result = self
+            .file
+            .seek(SeekFrom::Start(0))
+            .and_then(|_| self.file.write_all(&header))
+            .and_then(|_| self.file.flush());
+        return result.map_err(|e| format!("Couldnt write {}: {}", self.path, e));
+    }
+
+    fn write(self: &mut Self, bytes: &[u8]) -> Result<(), String> {
+        return self
+            .file
+            .write_all(bytes)
+            .map_err(|e| format!("Couldnt write {}: {}", self.path, e));
+    }
+}
+
+fn header(sample_rate: u32, data_len: u32) -> [u8; HEADER_SIZE as usize] {
+    let block_align = CHANNELS * BYTES_PER_SAMPLE;
+    let mut header = [0; HEADER_SIZE as usize];
+    header[0..4].copy_from_slice(b"RIFF");
+    header[4..8].copy_from_slice(&(HEADER_SIZE - 8 + data_len).to_le_bytes());
+    header[8..12].copy_from_slice(b"WAVE");
+    header[12..16].copy_from_slice(b"fmt ");
+    header[16..20].copy_from_slice(&16u32.to_le_bytes());
+    header[20..22].copy_from_slice(&1u16.to_le_bytes()); // PCM
+    header[22..24].copy_from_slice(&CHANNELS.to_le_bytes());
+    header[24..28].copy_from_slice(&sample_rate.to_le_bytes());
+    header[28..32].copy_from_slice(&(sample_rate * u32::from(block_align)).to_le_bytes());
+    header[32..34].copy_from_slice(&block_align.to_le_bytes());
+    header[34..36].copy_from_slice(&(BYTES_PER_SAMPLE * 8).to_le_bytes());
+    header[36..40].copy_from_slice(b"data");
+    header[40..44].copy_from_slice(&data_len.to_le_bytes());
+    return header;
+}
+
+#[test]
+fn test_wav_writer() {
+    let path = std::env::temp_dir().join(format!("gb_test_{}.wav", std::process::id()));
+    let path = path.to_string_lossy().to_string();
+
+    let mut wav = WavWriter::create(&path, 48_000).unwrap();
+    wav.add_samples(&[0.0, 1.0, -1.0, 2.0]).unwrap();
+    wav.finish().unwrap();
+
+    let bytes = std::fs::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(bytes.len(), 44 + 8);
+    assert_eq!(bytes[4..8], 44u32.to_le_bytes());
+    assert_eq!(bytes[24..28], 48_000u32.to_le_bytes());
+    assert_eq!(bytes[28..32], 192_000u32.to_le_bytes());
+    assert_eq!(bytes[40..44], 8u32.to_le_bytes());
+    assert_eq!(
+        bytes[44..],
+        [0x00, 0x00, 0xFF, 0x7F, 0x01, 0x80, 0xFF, 0x7F] // Clamped to 1.0
+    );
+}